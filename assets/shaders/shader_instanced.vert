@@ -0,0 +1,28 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+
+layout(location=1) in vec4 a_color;
+
+layout(location=2) in vec2 a_tex_coords;
+
+layout(location=3) in mat4 i_model;
+
+layout(location=7) in vec4 i_color;
+
+layout(location=8) in vec4 i_uv_rect;
+
+layout(location=0) out vec4 v_color;
+
+layout(location=1) out vec2 v_tex_coords;
+
+layout(set=1, binding=0)
+uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+void main() {
+    v_color = a_color * i_color;
+    v_tex_coords = mix(i_uv_rect.xy, i_uv_rect.zw, a_tex_coords);
+    gl_Position = u_view_proj * i_model * vec4(a_position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location=0) in vec3 v_normal;
+
+layout(location=1) in vec2 v_tex_coords;
+
+layout(location=0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Material {
+    vec4 tint;
+    float ambient;
+    // added after lighting, only rgb is used
+    vec4 emissive;
+};
+layout(set = 0, binding = 1) uniform texture2D t_diffuse;
+layout(set = 0, binding = 2) uniform sampler s_diffuse;
+
+// towards the light, which is above since +y is down
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.3, -1.0, 0.5));
+
+void main() {
+    float diffuse = max(dot(normalize(v_normal), LIGHT_DIRECTION), 0.0);
+    vec4 color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * tint;
+    f_color = vec4(color.rgb * mix(diffuse, 1.0, ambient) + emissive.rgb, color.a);
+}
\ No newline at end of file
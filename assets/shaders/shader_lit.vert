@@ -0,0 +1,28 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+
+layout(location=1) in vec3 a_normal;
+
+layout(location=2) in vec2 a_tex_coords;
+
+layout(location=0) out vec3 v_normal;
+
+layout(location=1) out vec2 v_tex_coords;
+
+layout(set=1, binding=0)
+uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+layout(set=2, binding=0)
+uniform Model {
+    mat4 u_model;
+    mat4 u_normal;
+};
+
+void main() {
+    v_normal = mat3(u_normal) * a_normal;
+    v_tex_coords = a_tex_coords;
+    gl_Position = u_view_proj * u_model * vec4(a_position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 450
+
+layout (location = 0) in vec4 v_color;
+
+layout (location = 1) in vec2 v_tex_coords;
+
+layout (location = 2) in float v_layer;
+
+layout (location = 0) out vec4 f_color;
+
+layout (set = 1, binding = 0) uniform texture2DArray t_blocks;
+layout (set = 1, binding = 1) uniform sampler s_blocks;
+
+void main() {
+    // untextured blocks have a negative layer
+    vec4 texel = vec4(1.0);
+    if (v_layer >= 0.0) {
+        texel = texture(sampler2DArray(t_blocks, s_blocks), vec3(v_tex_coords, v_layer));
+    }
+    f_color = v_color * texel;
+}
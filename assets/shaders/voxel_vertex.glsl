@@ -0,0 +1,28 @@
+#version 450
+
+layout (location = 0) in vec3 a_position;
+
+layout (location = 1) in vec4 a_color;
+
+layout (location = 2) in float a_ao;
+
+layout (location = 3) in vec2 a_tex_coords;
+
+layout (location = 4) in float a_layer;
+
+layout (location = 0) out vec4 v_color;
+
+layout (location = 1) out vec2 v_tex_coords;
+
+layout (location = 2) out float v_layer;
+
+layout (set = 0, binding = 0) uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+void main() {
+    v_color = vec4(a_color.rgb * a_ao, a_color.a);
+    v_tex_coords = a_tex_coords;
+    v_layer = a_layer;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
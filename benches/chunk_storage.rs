@@ -0,0 +1,117 @@
+//! Block storage throughput and memory, run with
+//! `cargo bench --bench chunk_storage`.
+//!
+//! The crate only has a binary, so the storage modules are compiled straight
+//! into the bench. They don't depend on anything but each other.
+
+mod common;
+
+// the bench only touches part of the modules it pulls in
+#[allow(dead_code)]
+#[path = "../src/voxel"]
+mod voxel {
+    pub mod chunk;
+    pub mod world;
+}
+
+use voxel::chunk::{BlockId, Chunk, CHUNK_SIZE, CHUNK_VOLUME};
+use voxel::world::World;
+
+const ROUNDS: usize = 20;
+
+/// `common::bench` with the rounds of this bench
+fn bench<F: FnMut() -> usize>(name: &str, operations: usize, f: F) {
+    common::bench(name, ROUNDS, operations, f)
+}
+
+/// Deterministic pseudo random block ids out of `types`
+fn blocks(types: usize) -> impl Iterator<Item = BlockId> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    std::iter::repeat_with(move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % types as u64) as BlockId
+    })
+}
+
+fn positions() -> impl Iterator<Item = [usize; 3]> {
+    (0..CHUNK_SIZE)
+        .flat_map(|x| (0..CHUNK_SIZE).flat_map(move |y| (0..CHUNK_SIZE).map(move |z| [x, y, z])))
+}
+
+fn filled_chunk(types: usize) -> Chunk {
+    let mut chunk = Chunk::empty();
+    for ([x, y, z], block) in positions().zip(blocks(types)) {
+        chunk.set(x, y, z, block);
+    }
+    chunk
+}
+
+fn chunk_benches() {
+    for &types in [1, 2, 4, 16, 256, 4096].iter() {
+        let chunk = filled_chunk(types);
+        println!("{:>4} types: {:>6} bytes", types, chunk.memory_usage());
+
+        bench(&format!("chunk get, {} types", types), CHUNK_VOLUME, || {
+            positions()
+                .map(|[x, y, z]| chunk.get(x, y, z) as usize)
+                .sum()
+        });
+
+        let mut chunk = chunk;
+        let replacements: Vec<BlockId> = blocks(types).skip(7).take(CHUNK_VOLUME).collect();
+        bench(&format!("chunk set, {} types", types), CHUNK_VOLUME, || {
+            positions()
+                .zip(replacements.iter())
+                .map(|([x, y, z], &block)| chunk.set(x, y, z, block) as usize)
+                .sum()
+        });
+    }
+
+    bench("chunk fill from empty, 16 types", CHUNK_VOLUME, || {
+        filled_chunk(16).solid_count()
+    });
+}
+
+fn world_benches() {
+    const EXTENT: i32 = 64;
+    let volume = (2 * EXTENT) as usize * (2 * EXTENT) as usize * 16;
+
+    let mut world = World::new();
+    bench("world set, 4x1x4 chunks", volume, || {
+        let mut changed = 0;
+        for x in -EXTENT..EXTENT {
+            for z in -EXTENT..EXTENT {
+                for y in 0..16 {
+                    changed += world.set(x, y, z, ((x ^ z) & 3) as BlockId + 1) as usize;
+                }
+            }
+        }
+        world.take_dirty();
+        changed
+    });
+
+    bench("world get, 4x1x4 chunks", volume, || {
+        let mut sum = 0;
+        for x in -EXTENT..EXTENT {
+            for z in -EXTENT..EXTENT {
+                for y in 0..16 {
+                    sum += world.get(x, y, z) as usize;
+                }
+            }
+        }
+        sum
+    });
+
+    println!(
+        "world: {} chunks, {} bytes",
+        world.chunk_count(),
+        world.memory_usage()
+    );
+}
+
+fn main() {
+    chunk_benches();
+    world_benches();
+}
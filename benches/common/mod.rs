@@ -0,0 +1,18 @@
+//! Timing shared by the benches, pulled in with `mod common;`.
+
+use std::time::{Duration, Instant};
+
+/// Runs `f` `rounds` times and prints the time per operation of the fastest
+/// round. `f` returns a value that depends on the work so it isn't optimized
+/// out.
+pub fn bench<F: FnMut() -> usize>(name: &str, rounds: usize, operations: usize, mut f: F) {
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut checksum = 0usize;
+    for _ in 0..rounds {
+        let start = Instant::now();
+        checksum = checksum.wrapping_add(f());
+        best = best.min(start.elapsed());
+    }
+    let nanos = best.as_nanos() as f64 / operations as f64;
+    println!("{:<32} {:>8.2} ns/op  (checksum {})", name, nanos, checksum);
+}
@@ -0,0 +1,134 @@
+//! Sparse voxel octree against the chunk grid, run with
+//! `cargo bench --bench octree`.
+//!
+//! Compares memory, get and set on a dense hilly scene and on a sparse one
+//! with a few hundred blocks spread over a large region.
+
+extern crate ultraviolet as uv;
+
+mod common;
+
+// the bench only touches part of the modules it pulls in
+#[allow(dead_code)]
+#[path = "../src/voxel"]
+mod voxel {
+    pub mod chunk;
+    pub mod octree;
+    pub mod world;
+}
+
+use voxel::chunk::BlockId;
+use voxel::octree::Octree;
+use voxel::world::World;
+
+const ROUNDS: usize = 10;
+
+/// `common::bench` with the rounds of this bench
+fn bench<F: FnMut() -> usize>(name: &str, operations: usize, f: F) {
+    common::bench(name, ROUNDS, operations, f)
+}
+
+struct Scene {
+    name: &'static str,
+    origin: [i32; 3],
+    depth: u32,
+    blocks: Vec<([i32; 3], BlockId)>,
+}
+
+/// Rolling hills of stone under dirt filling a 128 block cube
+fn hills() -> Scene {
+    let mut blocks = Vec::new();
+    for x in 0..128 {
+        for z in 0..128 {
+            let height = 64.0 + 12.0 * (x as f32 / 9.0).sin() * (z as f32 / 13.0).cos();
+            // up is -y
+            let surface = 128 - height as i32;
+            for y in surface..128 {
+                let block = if y < surface + 3 { 2 } else { 1 };
+                blocks.push(([x, y, z], block));
+            }
+        }
+    }
+    Scene {
+        name: "hills",
+        origin: [0, 0, 0],
+        depth: 7,
+        blocks,
+    }
+}
+
+/// A few hundred blocks spread over a 1024 block cube
+fn sparse() -> Scene {
+    let mut state = 0x2545_f491u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state % 1024) as i32 - 512
+    };
+    let blocks = (0..500)
+        .map(|i| ([next(), next(), next()], 1 + i % 4))
+        .collect();
+    Scene {
+        name: "sparse",
+        origin: [-512, -512, -512],
+        depth: 10,
+        blocks,
+    }
+}
+
+fn run(scene: Scene) {
+    let count = scene.blocks.len();
+    println!("{}: {} blocks", scene.name, count);
+
+    let mut world = World::new();
+    bench(&format!("{} world set", scene.name), count, || {
+        scene
+            .blocks
+            .iter()
+            .map(|&([x, y, z], block)| world.set(x, y, z, block) as usize)
+            .sum()
+    });
+    let mut octree = Octree::new(scene.origin, scene.depth);
+    bench(&format!("{} octree set", scene.name), count, || {
+        scene
+            .blocks
+            .iter()
+            .map(|&([x, y, z], block)| octree.set(x, y, z, block) as usize)
+            .sum()
+    });
+
+    bench(&format!("{} world get", scene.name), count, || {
+        scene
+            .blocks
+            .iter()
+            .map(|&([x, y, z], _)| world.get(x, y, z) as usize)
+            .sum()
+    });
+    bench(&format!("{} octree get", scene.name), count, || {
+        scene
+            .blocks
+            .iter()
+            .map(|&([x, y, z], _)| octree.get(x, y, z) as usize)
+            .sum()
+    });
+
+    bench(&format!("{} octree from world", scene.name), count, || {
+        Octree::from_world(&world, scene.origin, scene.depth).node_count()
+    });
+
+    world.compact();
+    println!(
+        "{} memory: world {} bytes in {} chunks, octree {} bytes in {} nodes",
+        scene.name,
+        world.memory_usage(),
+        world.chunk_count(),
+        octree.memory_usage(),
+        octree.node_count()
+    );
+}
+
+fn main() {
+    run(hills());
+    run(sparse());
+}
@@ -0,0 +1,150 @@
+use uv::vec::Vec3;
+
+use crate::types::{Vertex, VertexN};
+
+/// CPU side mesh data: a vertex list plus triangle indices.
+///
+/// Triangles are wound counter clockwise when looked at from their front side,
+/// matching the `FrontFace::Ccw` + `CullMode::Back` rasterization state of
+/// `pipeline::create_render_pipeline`.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh<V> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<u32>,
+}
+
+impl<V> Mesh<V> {
+    pub fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn with_capacity(vertices: usize, indices: usize) -> Self {
+        Self {
+            vertices: Vec::with_capacity(vertices),
+            indices: Vec::with_capacity(indices),
+        }
+    }
+
+    /// Adds a vertex and returns its index
+    pub fn push_vertex(&mut self, vertex: V) -> u32 {
+        self.vertices.push(vertex);
+        (self.vertices.len() - 1) as u32
+    }
+
+    pub fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    /// Adds the quad a -> b -> c -> d as the triangles (a, b, c) and (a, c, d)
+    pub fn push_quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+    }
+
+    /// Moves all vertices and triangles of `other` into this mesh
+    pub fn append(&mut self, mut other: Mesh<V>) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.append(&mut other.vertices);
+        self.indices
+            .extend(other.indices.into_iter().map(|i| i + offset));
+    }
+
+    /// Converts every vertex, keeping the indices
+    pub fn map<U, F>(self, f: F) -> Mesh<U>
+    where
+        F: FnMut(V) -> U,
+    {
+        Mesh {
+            vertices: self.vertices.into_iter().map(f).collect(),
+            indices: self.indices,
+        }
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices.chunks(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    pub fn num_indices(&self) -> u32 {
+        self.indices.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+impl<V: bytemuck::Pod> Mesh<V> {
+    pub fn create_vertex_buffer(&self, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_with_data(
+            bytemuck::cast_slice(&self.vertices),
+            wgpu::BufferUsage::VERTEX,
+        )
+    }
+
+    /// Index buffer for `wgpu::IndexFormat::Uint32`
+    pub fn create_index_buffer(&self, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_with_data(
+            bytemuck::cast_slice(&self.indices),
+            wgpu::BufferUsage::INDEX,
+        )
+    }
+
+    /// Both buffers, ready to draw
+    pub fn upload(&self, device: &wgpu::Device) -> GpuMesh {
+        GpuMesh {
+            vertex_buffer: self.create_vertex_buffer(device),
+            index_buffer: self.create_index_buffer(device),
+            num_indices: self.num_indices(),
+        }
+    }
+}
+
+/// Vertex and index buffer of an uploaded mesh, indices are
+/// `wgpu::IndexFormat::Uint32`
+pub struct GpuMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+}
+
+impl GpuMesh {
+    /// Draws the mesh with whatever pipeline and bind groups are set
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+impl Mesh<VertexN> {
+    /// Per vertex normals averaged from the adjacent triangles, weighted by their area
+    pub fn smooth_normals(&self) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::zero(); self.vertices.len()];
+        for triangle in self.triangles() {
+            let position = |i: u32| Vec3::from(self.vertices[i as usize].position);
+            let [a, b, c] = triangle;
+            let face = (position(b) - position(a)).cross(position(c) - position(a));
+            for &i in triangle.iter() {
+                normals[i as usize] += face;
+            }
+        }
+
+        for normal in normals.iter_mut() {
+            if normal.mag_sq() > 0.0 {
+                normal.normalize();
+            }
+        }
+        normals
+    }
+
+    /// Drops the normals and paints every vertex with `color`
+    pub fn with_color(self, color: [f32; 4]) -> Mesh<Vertex> {
+        self.map(|v| Vertex {
+            position: v.position,
+            color,
+            tex_coords: v.tex_coords,
+        })
+    }
+}
@@ -0,0 +1,3 @@
+pub mod mesh;
+pub mod primitives;
+pub mod transform;
\ No newline at end of file
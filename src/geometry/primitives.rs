@@ -0,0 +1,472 @@
+//! CPU only generators for test geometry.
+//!
+//! Every shape is centered on the origin. Like the `CameraController` the
+//! generators treat -y as up, so planes face -y and cones point towards -y.
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use uv::vec::Vec3;
+
+use crate::geometry::mesh::Mesh;
+use crate::types::VertexN;
+
+fn vertex(position: Vec3, normal: Vec3, tex_coords: [f32; 2]) -> VertexN {
+    VertexN {
+        position: position.into(),
+        normal: normal.into(),
+        tex_coords,
+    }
+}
+
+/// Axis aligned cube with `size` long edges, 4 vertices per face for hard normals
+pub fn cube(size: f32) -> Mesh<VertexN> {
+    let half = size / 2.0;
+    let x = Vec3::unit_x();
+    let y = Vec3::unit_y();
+    let z = Vec3::unit_z();
+
+    // (normal, u, v) with u x v = normal, so walking the corners u -> v is ccw
+    let faces = [
+        (x, y, z),
+        (-x, z, y),
+        (y, z, x),
+        (-y, x, z),
+        (z, x, y),
+        (-z, y, x),
+    ];
+
+    let mut mesh = Mesh::with_capacity(24, 36);
+    for &(normal, u, v) in faces.iter() {
+        let center = normal * half;
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let mut ids = [0; 4];
+        for (id, &(su, sv)) in ids.iter_mut().zip(corners.iter()) {
+            let position = center + u * (su * half) + v * (sv * half);
+            let tex_coords = [(su + 1.0) / 2.0, (1.0 - sv) / 2.0];
+            *id = mesh.push_vertex(vertex(position, normal, tex_coords));
+        }
+        mesh.push_quad(ids[0], ids[1], ids[2], ids[3]);
+    }
+    mesh
+}
+
+/// Flat grid of `width` (x) by `depth` (z) facing up, split into `cells_x` * `cells_z` quads
+pub fn plane(width: f32, depth: f32, cells_x: u32, cells_z: u32) -> Mesh<VertexN> {
+    let cells_x = cells_x.max(1);
+    let cells_z = cells_z.max(1);
+    let normal = -Vec3::unit_y();
+
+    let mut mesh = Mesh::with_capacity(
+        ((cells_x + 1) * (cells_z + 1)) as usize,
+        (cells_x * cells_z * 6) as usize,
+    );
+    for iz in 0..=cells_z {
+        for ix in 0..=cells_x {
+            let u = ix as f32 / cells_x as f32;
+            let v = iz as f32 / cells_z as f32;
+            let position = Vec3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth);
+            mesh.push_vertex(vertex(position, normal, [u, v]));
+        }
+    }
+
+    let row = cells_x + 1;
+    for iz in 0..cells_z {
+        for ix in 0..cells_x {
+            let a = iz * row + ix;
+            // x cross z points along -y, so a -> +x -> +x+z -> +z faces up
+            mesh.push_quad(a, a + 1, a + row + 1, a + row);
+        }
+    }
+    mesh
+}
+
+/// Sphere built from `sectors` meridians and `stacks` parallels
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> Mesh<VertexN> {
+    let stacks = stacks.max(2);
+    let profile: Vec<ProfilePoint> = (0..=stacks)
+        .map(|i| {
+            let phi = PI * i as f32 / stacks as f32;
+            // sin(PI) isn't exactly zero, the poles have to be
+            let sin = if i == 0 || i == stacks {
+                0.0
+            } else {
+                phi.sin()
+            };
+            ProfilePoint {
+                radius: radius * sin,
+                y: radius * phi.cos(),
+                normal: (sin, phi.cos()),
+                v: i as f32 / stacks as f32,
+            }
+        })
+        .collect();
+
+    revolve(&profile, sectors)
+}
+
+/// Subdivided icosahedron, every subdivision splits each triangle into four
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh<VertexN> {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalized())
+    .collect();
+
+    #[rustfmt::skip]
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| -> u32 {
+            let key = (a.min(b), a.max(b));
+            *midpoints.entry(key).or_insert_with(|| {
+                let middle = (positions[a as usize] + positions[b as usize]) * 0.5;
+                positions.push(middle.normalized());
+                (positions.len() - 1) as u32
+            })
+        };
+
+        let mut subdivided = Vec::with_capacity(faces.len() * 4);
+        for &[a, b, c] in faces.iter() {
+            let ab = midpoint(a, b, &mut positions);
+            let bc = midpoint(b, c, &mut positions);
+            let ca = midpoint(c, a, &mut positions);
+            subdivided.push([a, ab, ca]);
+            subdivided.push([b, bc, ab]);
+            subdivided.push([c, ca, bc]);
+            subdivided.push([ab, bc, ca]);
+        }
+        faces = subdivided;
+    }
+
+    let mut mesh = Mesh::with_capacity(positions.len(), faces.len() * 3);
+    for normal in positions {
+        let u = 0.5 + normal.z.atan2(normal.x) / (2.0 * PI);
+        let v = normal.y.clamp(-1.0, 1.0).acos() / PI;
+        mesh.push_vertex(vertex(normal * radius, normal, [u, v]));
+    }
+    for [a, b, c] in faces {
+        mesh.push_triangle(a, b, c);
+    }
+    mesh
+}
+
+/// Closed cylinder around the y axis
+pub fn cylinder(radius: f32, height: f32, sectors: u32) -> Mesh<VertexN> {
+    let half = height / 2.0;
+    let profile = [
+        ProfilePoint {
+            radius,
+            y: half,
+            normal: (1.0, 0.0),
+            v: 0.0,
+        },
+        ProfilePoint {
+            radius,
+            y: -half,
+            normal: (1.0, 0.0),
+            v: 1.0,
+        },
+    ];
+
+    let mut mesh = revolve(&profile, sectors);
+    mesh.append(disc(radius, half, sectors, true));
+    mesh.append(disc(radius, -half, sectors, false));
+    mesh
+}
+
+/// Closed cone around the y axis with its tip pointing up
+pub fn cone(radius: f32, height: f32, sectors: u32) -> Mesh<VertexN> {
+    let half = height / 2.0;
+    // the side normal leans towards the tip by the slope of the cone
+    let slope = Vec3::new(height, -radius, 0.0).normalized();
+    let profile = [
+        ProfilePoint {
+            radius,
+            y: half,
+            normal: (slope.x, slope.y),
+            v: 0.0,
+        },
+        ProfilePoint {
+            radius: 0.0,
+            y: -half,
+            normal: (slope.x, slope.y),
+            v: 1.0,
+        },
+    ];
+
+    let mut mesh = revolve(&profile, sectors);
+    mesh.append(disc(radius, half, sectors, true));
+    mesh
+}
+
+/// Torus around the y axis, `major_radius` to the center of the tube, `minor_radius` of the tube
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> Mesh<VertexN> {
+    let major_segments = major_segments.max(3);
+    let minor_segments = minor_segments.max(3);
+
+    let mut mesh = Mesh::with_capacity(
+        ((major_segments + 1) * (minor_segments + 1)) as usize,
+        (major_segments * minor_segments * 6) as usize,
+    );
+    for i in 0..=major_segments {
+        let theta = 2.0 * PI * i as f32 / major_segments as f32;
+        let center = Vec3::new(theta.cos(), 0.0, theta.sin()) * major_radius;
+        for j in 0..=minor_segments {
+            let phi = 2.0 * PI * j as f32 / minor_segments as f32;
+            let normal = Vec3::new(phi.cos() * theta.cos(), phi.sin(), phi.cos() * theta.sin());
+            let tex_coords = [
+                i as f32 / major_segments as f32,
+                j as f32 / minor_segments as f32,
+            ];
+            mesh.push_vertex(vertex(center + normal * minor_radius, normal, tex_coords));
+        }
+    }
+
+    let row = minor_segments + 1;
+    for i in 0..major_segments {
+        for j in 0..minor_segments {
+            let a = i * row + j;
+            mesh.push_quad(a, a + 1, a + row + 1, a + row);
+        }
+    }
+    mesh
+}
+
+/// Cylinder of `height` capped with two hemispheres, `rings` parallels per hemisphere
+pub fn capsule(radius: f32, height: f32, sectors: u32, rings: u32) -> Mesh<VertexN> {
+    let rings = rings.max(1);
+    let half = height / 2.0;
+    let length = PI * radius + height;
+
+    let mut profile = Vec::with_capacity(2 * rings as usize + 2);
+    for (offset, first_ring) in [(half, 0), (-half, rings)].iter() {
+        for i in *first_ring..=*first_ring + rings {
+            let phi = PI / 2.0 * i as f32 / rings as f32;
+            let arc = radius * phi + if *offset < 0.0 { height } else { 0.0 };
+            let sin = if i == 0 || i == 2 * rings {
+                0.0
+            } else {
+                phi.sin()
+            };
+            profile.push(ProfilePoint {
+                radius: radius * sin,
+                y: radius * phi.cos() + offset,
+                normal: (sin, phi.cos()),
+                v: arc / length,
+            });
+        }
+    }
+
+    revolve(&profile, sectors)
+}
+
+/// A point of a surface of revolution, `normal` is (radial, y)
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: (f32, f32),
+    v: f32,
+}
+
+/// Spins a profile ordered from +y to -y around the y axis.
+///
+/// Rings with a radius of zero become poles and don't emit degenerate triangles.
+fn revolve(profile: &[ProfilePoint], sectors: u32) -> Mesh<VertexN> {
+    let sectors = sectors.max(3);
+    let row = sectors + 1;
+
+    let mut mesh = Mesh::with_capacity(
+        profile.len() * row as usize,
+        profile.len() * sectors as usize * 6,
+    );
+    for point in profile {
+        for j in 0..=sectors {
+            let theta = 2.0 * PI * j as f32 / sectors as f32;
+            let (sin, cos) = theta.sin_cos();
+            let position = Vec3::new(point.radius * cos, point.y, point.radius * sin);
+            let normal = Vec3::new(point.normal.0 * cos, point.normal.1, point.normal.0 * sin);
+            mesh.push_vertex(vertex(
+                position,
+                normal,
+                [j as f32 / sectors as f32, point.v],
+            ));
+        }
+    }
+
+    // walking around the ring goes towards +z at theta = 0 and the profile goes
+    // towards -y, z x -y = +x is the outside
+    for i in 0..profile.len().saturating_sub(1) {
+        let top_is_pole = profile[i].radius == 0.0;
+        let bottom_is_pole = profile[i + 1].radius == 0.0;
+        for j in 0..sectors {
+            let a = i as u32 * row + j;
+            let b = a + row;
+            if !top_is_pole {
+                mesh.push_triangle(a, a + 1, b);
+            }
+            if !bottom_is_pole {
+                mesh.push_triangle(a + 1, b + 1, b);
+            }
+        }
+    }
+    mesh
+}
+
+/// Flat disc at height `y`, facing +y or -y
+fn disc(radius: f32, y: f32, sectors: u32, facing_positive_y: bool) -> Mesh<VertexN> {
+    let sectors = sectors.max(3);
+    let normal = if facing_positive_y {
+        Vec3::unit_y()
+    } else {
+        -Vec3::unit_y()
+    };
+
+    let mut mesh = Mesh::with_capacity(sectors as usize + 2, sectors as usize * 3);
+    let center = mesh.push_vertex(vertex(Vec3::new(0.0, y, 0.0), normal, [0.5, 0.5]));
+    for j in 0..=sectors {
+        let theta = 2.0 * PI * j as f32 / sectors as f32;
+        let (sin, cos) = theta.sin_cos();
+        let position = Vec3::new(radius * cos, y, radius * sin);
+        mesh.push_vertex(vertex(position, normal, [0.5 + cos / 2.0, 0.5 + sin / 2.0]));
+    }
+
+    for j in 0..sectors {
+        let current = center + 1 + j;
+        // going around with increasing theta is clockwise seen from +y
+        if facing_positive_y {
+            mesh.push_triangle(center, current + 1, current);
+        } else {
+            mesh.push_triangle(center, current, current + 1);
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(mesh: &Mesh<VertexN>, i: u32) -> Vec3 {
+        Vec3::from(mesh.vertices[i as usize].position)
+    }
+
+    fn normal(mesh: &Mesh<VertexN>, i: u32) -> Vec3 {
+        Vec3::from(mesh.vertices[i as usize].normal)
+    }
+
+    /// Normal of the triangle as `FrontFace::Ccw` sees it, a -> b -> c counter
+    /// clockwise means it points at the viewer
+    fn face_normal(mesh: &Mesh<VertexN>, [a, b, c]: [u32; 3]) -> Vec3 {
+        (position(mesh, b) - position(mesh, a)).cross(position(mesh, c) - position(mesh, a))
+    }
+
+    /// Every triangle is front facing from the side its vertex normals point to,
+    /// so back face culling never drops the outside
+    fn assert_wound_outwards(name: &str, mesh: &Mesh<VertexN>) {
+        assert!(!mesh.is_empty(), "{} is empty", name);
+        for triangle in mesh.triangles() {
+            let face = face_normal(mesh, triangle);
+            assert!(
+                face.mag() > 1e-8,
+                "{} has a degenerate triangle {:?}",
+                name,
+                triangle
+            );
+
+            let [a, b, c] = triangle;
+            let shading = normal(mesh, a) + normal(mesh, b) + normal(mesh, c);
+            assert!(
+                face.dot(shading) > 0.0,
+                "{} triangle {:?} is wound against its normals",
+                name,
+                triangle
+            );
+            for &i in triangle.iter() {
+                assert!(
+                    (normal(mesh, i).mag() - 1.0).abs() < 1e-4,
+                    "{} has a normal that isn't unit length",
+                    name
+                );
+            }
+        }
+    }
+
+    /// For shapes that are convex around the origin every face points away from it
+    fn assert_convex_outwards(name: &str, mesh: &Mesh<VertexN>) {
+        assert_wound_outwards(name, mesh);
+        for triangle in mesh.triangles() {
+            let [a, b, c] = triangle;
+            let center = (position(mesh, a) + position(mesh, b) + position(mesh, c)) / 3.0;
+            assert!(
+                face_normal(mesh, triangle).dot(center) > 0.0,
+                "{} triangle {:?} faces inwards",
+                name,
+                triangle
+            );
+        }
+    }
+
+    #[test]
+    fn convex_shapes_face_outwards() {
+        assert_convex_outwards("cube", &cube(1.0));
+        assert_convex_outwards("uv_sphere", &uv_sphere(1.0, 16, 8));
+        assert_convex_outwards("icosphere", &icosphere(1.0, 0));
+        assert_convex_outwards("subdivided icosphere", &icosphere(1.0, 3));
+        assert_convex_outwards("cylinder", &cylinder(1.0, 2.0, 12));
+        assert_convex_outwards("cone", &cone(1.0, 2.0, 12));
+        assert_convex_outwards("capsule", &capsule(0.5, 1.0, 12, 4));
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let mesh = plane(2.0, 3.0, 4, 5);
+        assert_wound_outwards("plane", &mesh);
+        for triangle in mesh.triangles() {
+            // -y is up
+            assert!(face_normal(&mesh, triangle).y < 0.0);
+        }
+    }
+
+    #[test]
+    fn torus_faces_away_from_the_tube() {
+        let (major, minor) = (2.0, 0.5);
+        let mesh = torus(major, minor, 16, 8);
+        assert_wound_outwards("torus", &mesh);
+        for triangle in mesh.triangles() {
+            let [a, b, c] = triangle;
+            let center = (position(&mesh, a) + position(&mesh, b) + position(&mesh, c)) / 3.0;
+            let ring = Vec3::new(center.x, 0.0, center.z).normalized() * major;
+            assert!(face_normal(&mesh, triangle).dot(center - ring) > 0.0);
+        }
+    }
+
+    #[test]
+    fn icosphere_shares_midpoints() {
+        assert_eq!(icosphere(1.0, 0).vertices.len(), 12);
+        assert_eq!(icosphere(1.0, 1).vertices.len(), 42);
+    }
+}
@@ -0,0 +1,125 @@
+//! Placement of a single object in the world.
+//!
+//! A `Transform` scales first, then rotates, then translates. `to_matrix`
+//! gives the model matrix for the vertex shader, `normal_matrix` the matrix
+//! normals have to go through so they stay perpendicular to the surface
+//! under non uniform scale.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translation: uv::Vec3,
+    pub rotation: uv::Rotor3,
+    /// Scale along the object's own axes, none of them may be zero
+    pub scale: uv::Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: uv::Vec3::zero(),
+            rotation: uv::Rotor3::identity(),
+            scale: uv::Vec3::one(),
+        }
+    }
+
+    pub fn from_translation(translation: uv::Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::identity()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: uv::Rotor3) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: uv::Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_uniform_scale(self, scale: f32) -> Self {
+        self.with_scale(uv::Vec3::broadcast(scale))
+    }
+
+    pub fn translate(&mut self, offset: uv::Vec3) {
+        self.translation += offset;
+    }
+
+    /// Applies `rotation` after the current one, around the object's origin
+    pub fn rotate(&mut self, rotation: uv::Rotor3) {
+        self.rotation = (rotation * self.rotation).normalized();
+    }
+
+    /// Model matrix, translation * rotation * scale
+    pub fn to_matrix(self) -> uv::Mat4 {
+        let linear = self.rotation.into_matrix() * uv::Mat3::from_nonuniform_scale(self.scale);
+        uv::Mat4::from_translation(self.translation) * linear.into_homogeneous()
+    }
+
+    /// Inverse transpose of the model matrix without the translation. The
+    /// rotation is orthogonal, so that is the rotation times the inverse of
+    /// the scale and no general inverse is needed. Normals still have to be
+    /// normalized after it.
+    pub fn normal_matrix(&self) -> uv::Mat3 {
+        let inverse_scale =
+            uv::Vec3::new(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z);
+        self.rotation.into_matrix() * uv::Mat3::from_nonuniform_scale(inverse_scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Transform {
+        Transform::from_translation(uv::Vec3::new(1.0, -2.0, 3.0))
+            .with_rotation(uv::Rotor3::from_euler_angles(0.3, 0.7, 1.1))
+            .with_scale(uv::Vec3::new(2.0, 0.5, 3.0))
+    }
+
+    fn apply(matrix: uv::Mat4, point: uv::Vec3) -> uv::Vec3 {
+        let point = matrix * uv::Vec4::new(point.x, point.y, point.z, 1.0);
+        uv::Vec3::new(point.x, point.y, point.z)
+    }
+
+    #[test]
+    fn matrix_scales_then_rotates_then_translates() {
+        let transform = sample();
+        let point = uv::Vec3::new(1.0, 2.0, -3.0);
+        let expected = transform.rotation * (point * transform.scale) + transform.translation;
+        assert!((apply(transform.to_matrix(), point) - expected).mag() < 1e-4);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = sample();
+        let matrix = transform.to_matrix();
+        // the plane x + y + z = 0 and two of its tangents
+        let normal = transform.normal_matrix() * uv::Vec3::new(1.0, 1.0, 1.0);
+        for &tangent in [uv::Vec3::new(1.0, -1.0, 0.0), uv::Vec3::new(0.0, 1.0, -1.0)].iter() {
+            let tangent = apply(matrix, tangent) - apply(matrix, uv::Vec3::zero());
+            assert!(tangent.dot(normal).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn rotations_add_up() {
+        let mut transform = sample();
+        let mut expected = sample();
+        transform.rotate(uv::Rotor3::from_rotation_xz(0.5));
+        transform.rotate(uv::Rotor3::from_rotation_xz(0.5));
+        expected.rotation = uv::Rotor3::from_rotation_xz(1.0) * expected.rotation;
+
+        let point = uv::Vec3::new(1.0, 0.0, 0.0);
+        let difference = apply(transform.to_matrix(), point) - apply(expected.to_matrix(), point);
+        assert!(difference.mag() < 1e-4);
+    }
+}
@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while importing an asset
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// A malformed line of a text format
+    Parse { line: usize, message: String },
+    /// Structurally broken or unsupported data
+    Format(String),
+    Gltf(gltf::Error),
+    Image(image::ImageError),
+    Json(serde_json::Error),
+    /// Wraps an error with the file it happened in
+    File { path: PathBuf, error: Box<LoadError> },
+}
+
+impl LoadError {
+    pub fn parse(line: usize, message: impl Into<String>) -> Self {
+        LoadError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+
+    pub fn format(message: impl Into<String>) -> Self {
+        LoadError::Format(message.into())
+    }
+
+    pub fn in_file(self, path: impl Into<PathBuf>) -> Self {
+        LoadError::File {
+            path: path.into(),
+            error: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Format(message) => write!(f, "{}", message),
+            LoadError::Gltf(error) => write!(f, "{}", error),
+            LoadError::Image(error) => write!(f, "{}", error),
+            LoadError::Json(error) => write!(f, "{}", error),
+            LoadError::File { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            LoadError::Gltf(error) => Some(error),
+            LoadError::Image(error) => Some(error),
+            LoadError::Json(error) => Some(error),
+            LoadError::File { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+impl From<gltf::Error> for LoadError {
+    fn from(error: gltf::Error) -> Self {
+        LoadError::Gltf(error)
+    }
+}
+
+impl From<image::ImageError> for LoadError {
+    fn from(error: image::ImageError) -> Self {
+        LoadError::Image(error)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(error: serde_json::Error) -> Self {
+        LoadError::Json(error)
+    }
+}
@@ -0,0 +1,710 @@
+//! glTF 2.0 (.gltf + .bin, embedded or .glb) scene import.
+//!
+//! glTF is y-up, the engine is y-down. Both are right handed, so a half turn
+//! around the x axis maps one onto the other without flipping triangle
+//! winding. Node `world` matrices already contain that conversion, mesh data
+//! stays in glTF's local space. Cameras look down their local -z in both, so
+//! a camera node's `world` matrix is its engine eye transformation as is.
+use std::path::Path;
+
+use uv::mat::{Mat3, Mat4};
+use uv::projection::rh_ydown::{orthographic_vk, perspective_infinite_z_vk, perspective_vk};
+use uv::rotor::Rotor3;
+use uv::vec::{Vec3, Vec4};
+use uv::Isometry3;
+
+use crate::geometry::mesh::Mesh;
+use crate::loader::error::LoadError;
+use crate::render::texture;
+use crate::tools::camera::Camera;
+use crate::types::VertexN;
+use crate::utils;
+
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<PbrMaterial>,
+    /// Decoded images, referenced by `TextureRef::image`
+    pub images: Vec<image::RgbaImage>,
+    /// How each image has to be sampled, see `image_spaces`
+    pub image_spaces: Vec<texture::ColorSpace>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default scene
+    pub roots: Vec<usize>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+pub struct GltfMesh {
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Clone)]
+pub struct GltfPrimitive {
+    pub mesh: Mesh<VertexN>,
+    /// Index into `GltfScene::materials`, `None` uses the glTF default material
+    pub material: Option<usize>,
+}
+
+/// The metallic roughness material parameters the engine can draw
+#[derive(Clone, Debug)]
+pub struct PbrMaterial {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    /// Index into `GltfScene::images`. Only the first texture coordinate set
+    /// is imported, textures using another one are left out.
+    pub base_color_texture: Option<usize>,
+    pub emissive_factor: [f32; 3],
+}
+
+pub struct GltfNode {
+    pub translation: Vec3,
+    pub rotation: Rotor3,
+    pub scale: Vec3,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    /// Local to world in engine coordinates
+    pub world: Mat4,
+}
+
+impl GltfNode {
+    pub fn local_matrix(&self) -> Mat4 {
+        Mat4::from_translation(self.translation)
+            * self.rotation.into_matrix().into_homogeneous()
+            * Mat4::from_nonuniform_scale(self.scale.into_homogeneous_vector())
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum GltfProjection {
+    Perspective {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+pub struct GltfCamera {
+    pub projection: GltfProjection,
+}
+
+impl GltfCamera {
+    /// Vulkan style projection, `aspect` is used if the file doesn't specify one
+    pub fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        match self.projection {
+            GltfProjection::Perspective {
+                yfov,
+                aspect_ratio,
+                znear,
+                zfar,
+            } => {
+                let aspect = aspect_ratio.unwrap_or(aspect);
+                match zfar {
+                    Some(zfar) => perspective_vk(yfov, aspect, znear, zfar),
+                    None => perspective_infinite_z_vk(yfov, aspect, znear),
+                }
+            }
+            GltfProjection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => orthographic_vk(-xmag, xmag, -ymag, ymag, znear, zfar),
+        }
+    }
+
+    fn aspect(&self, fallback: f32) -> f32 {
+        match self.projection {
+            GltfProjection::Perspective { aspect_ratio, .. } => aspect_ratio.unwrap_or(fallback),
+            GltfProjection::Orthographic { xmag, ymag, .. } => xmag / ymag,
+        }
+    }
+
+    fn fov(&self) -> f32 {
+        match self.projection {
+            GltfProjection::Perspective { yfov, .. } => yfov,
+            GltfProjection::Orthographic { .. } => 0.0,
+        }
+    }
+}
+
+/// Half turn around x, maps glTF's y-up space onto the engine's y-down space
+fn to_engine() -> Mat4 {
+    Mat4::from_nonuniform_scale(Vec4::new(1.0, -1.0, -1.0, 1.0))
+}
+
+/// Imports a .gltf or .glb file together with its buffers and images
+pub fn load(path: impl AsRef<Path>) -> Result<GltfScene, LoadError> {
+    let path = path.as_ref();
+    let (document, buffers, images) =
+        gltf::import(path).map_err(|e| LoadError::from(e).in_file(path))?;
+    import(&document, &buffers, &images).map_err(|e| e.in_file(path))
+}
+
+fn import(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+) -> Result<GltfScene, LoadError> {
+    let images = images
+        .iter()
+        .map(convert_image)
+        .collect::<Result<Vec<_>, _>>()?;
+    let image_spaces = image_spaces(document, images.len());
+
+    let materials = document.materials().map(import_material).collect();
+
+    let mut meshes = Vec::new();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            if let Some(mesh) = import_primitive(&primitive, buffers)? {
+                primitives.push(GltfPrimitive {
+                    mesh,
+                    material: primitive.material().index(),
+                });
+            }
+        }
+        meshes.push(GltfMesh { primitives });
+    }
+
+    let cameras = document
+        .cameras()
+        .map(|camera| GltfCamera {
+            projection: match camera.projection() {
+                gltf::camera::Projection::Perspective(p) => GltfProjection::Perspective {
+                    yfov: p.yfov(),
+                    aspect_ratio: p.aspect_ratio(),
+                    znear: p.znear(),
+                    zfar: p.zfar(),
+                },
+                gltf::camera::Projection::Orthographic(o) => GltfProjection::Orthographic {
+                    xmag: o.xmag(),
+                    ymag: o.ymag(),
+                    znear: o.znear(),
+                    zfar: o.zfar(),
+                },
+            },
+        })
+        .collect();
+
+    let mut nodes: Vec<GltfNode> = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            GltfNode {
+                translation: translation.into(),
+                rotation: utils::rotor_from_quaternion(rotation),
+                scale: scale.into(),
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                camera: node.camera().map(|camera| camera.index()),
+                world: Mat4::identity(),
+            }
+        })
+        .collect();
+
+    for index in 0..nodes.len() {
+        for child in nodes[index].children.clone() {
+            if nodes[child].parent.is_some() {
+                return Err(LoadError::format(format!("node {} has two parents", child)));
+            }
+            nodes[child].parent = Some(index);
+        }
+    }
+
+    let roots: Vec<usize> = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None => (0..nodes.len())
+            .filter(|&i| nodes[i].parent.is_none())
+            .collect(),
+    };
+
+    // parents before children, glTF doesn't allow cycles but a broken file might contain one
+    let mut stack: Vec<(usize, Mat4)> = roots.iter().map(|&root| (root, to_engine())).collect();
+    let mut visited = vec![false; nodes.len()];
+    while let Some((index, parent_world)) = stack.pop() {
+        if visited[index] {
+            return Err(LoadError::format(format!(
+                "node {} is part of a cycle",
+                index
+            )));
+        }
+        visited[index] = true;
+
+        let world = parent_world * nodes[index].local_matrix();
+        nodes[index].world = world;
+        stack.extend(nodes[index].children.iter().map(|&child| (child, world)));
+    }
+
+    Ok(GltfScene {
+        meshes,
+        materials,
+        images,
+        image_spaces,
+        nodes,
+        roots,
+        cameras,
+    })
+}
+
+impl GltfScene {
+    /// Uploads one of the images through the usual texture path, in the
+    /// color space of what the materials use it for
+    pub fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        image: usize,
+        label: &str,
+    ) -> (texture::Texture, wgpu::CommandBuffer) {
+        let space = self.image_spaces[image];
+        texture::Texture::from_rgba(device, &self.images[image], label, space)
+    }
+
+    /// Nodes of the default scene, parents before their children. Nodes
+    /// outside of it aren't part of what the file shows.
+    pub fn scene_nodes(&self) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        let mut stack = self.roots.clone();
+        while let Some(index) = stack.pop() {
+            nodes.push(index);
+            stack.extend(&self.nodes[index].children);
+        }
+        nodes
+    }
+
+    /// The primitives of `node`'s mesh moved to where the node puts them in
+    /// the engine's world, ready to be drawn without a model matrix
+    pub fn world_primitives(&self, node: usize) -> Vec<GltfPrimitive> {
+        let node = &self.nodes[node];
+        let mesh = match node.mesh {
+            Some(mesh) => &self.meshes[mesh],
+            None => return Vec::new(),
+        };
+
+        let world = node.world;
+        let linear = Mat3::new(
+            world.cols[0].xyz(),
+            world.cols[1].xyz(),
+            world.cols[2].xyz(),
+        );
+        // the inverse transpose, spelled out since ultraviolet's Mat3::inversed
+        // divides by a determinant that is always 0
+        let [x, y, z] = linear.cols;
+        let determinant = x.dot(y.cross(z));
+        let inverse = 1.0 / determinant;
+        let normal_matrix = Mat3::new(
+            y.cross(z) * inverse,
+            z.cross(x) * inverse,
+            x.cross(y) * inverse,
+        );
+        // a mirroring transformation turns the front faces to the back
+        let mirrored = determinant < 0.0;
+
+        mesh.primitives
+            .iter()
+            .map(|primitive| {
+                let mut mesh = primitive.mesh.clone().map(|vertex| {
+                    let position = Vec3::from(vertex.position);
+                    VertexN {
+                        position: (world * position.into_homogeneous_point()).xyz().into(),
+                        normal: (normal_matrix * Vec3::from(vertex.normal))
+                            .normalized()
+                            .into(),
+                        ..vertex
+                    }
+                });
+                if mirrored {
+                    for triangle in mesh.indices.chunks_exact_mut(3) {
+                        triangle.swap(1, 2);
+                    }
+                }
+                GltfPrimitive {
+                    mesh,
+                    material: primitive.material,
+                }
+            })
+            .collect()
+    }
+
+    /// Engine camera looking through the camera attached to `node`
+    pub fn camera(&self, node: usize, sc_desc: &wgpu::SwapChainDescriptor) -> Option<Camera> {
+        let node = &self.nodes[node];
+        let camera = &self.cameras[node.camera?];
+        let aspect = sc_desc.width as f32 / sc_desc.height as f32;
+
+        let eye = node.world;
+        let axes = Mat3::new(
+            eye.cols[0].xyz().normalized(),
+            eye.cols[1].xyz().normalized(),
+            eye.cols[2].xyz().normalized(),
+        );
+        let rotation = utils::rotor_from_matrix(axes);
+        let view = Isometry3::new(eye.cols[3].xyz(), rotation).inversed();
+
+        Some(Camera::with_projection(
+            view,
+            camera.projection_matrix(aspect),
+            camera.aspect(aspect),
+            camera.fov(),
+        ))
+    }
+}
+
+fn import_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Option<Mesh<VertexN>>, LoadError> {
+    use gltf::mesh::Mode;
+
+    let mode = primitive.mode();
+    if !(mode == Mode::Triangles || mode == Mode::TriangleStrip || mode == Mode::TriangleFan) {
+        eprintln!(
+            "warning: skipping {:?} primitive, only triangles are supported",
+            mode
+        );
+        return Ok(None);
+    }
+
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .ok_or_else(|| LoadError::format("primitive without positions"))?
+        .collect();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+    let tex_coords: Option<Vec<[f32; 2]>> =
+        reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+
+    let count = positions.len();
+    if normals.as_ref().is_some_and(|n| n.len() != count)
+        || tex_coords.as_ref().is_some_and(|t| t.len() != count)
+    {
+        return Err(LoadError::format(
+            "vertex attributes with different lengths",
+        ));
+    }
+
+    let mut mesh = Mesh::with_capacity(count, count);
+    for i in 0..count {
+        mesh.push_vertex(VertexN {
+            position: positions[i],
+            normal: normals.as_ref().map_or([0.0; 3], |n| n[i]),
+            tex_coords: tex_coords.as_ref().map_or([0.0; 2], |t| t[i]),
+        });
+    }
+
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..count as u32).collect(),
+    };
+    if let Some(&index) = indices.iter().find(|&&i| i as usize >= count) {
+        return Err(LoadError::format(format!("index {} out of range", index)));
+    }
+
+    match mode {
+        Mode::TriangleStrip => {
+            for i in 0..indices.len().saturating_sub(2) {
+                // every other triangle of a strip is wound the other way around
+                if i % 2 == 0 {
+                    mesh.push_triangle(indices[i], indices[i + 1], indices[i + 2]);
+                } else {
+                    mesh.push_triangle(indices[i + 1], indices[i], indices[i + 2]);
+                }
+            }
+        }
+        Mode::TriangleFan => {
+            for i in 1..indices.len().saturating_sub(1) {
+                mesh.push_triangle(indices[0], indices[i], indices[i + 1]);
+            }
+        }
+        _ => {
+            for triangle in indices.chunks_exact(3) {
+                mesh.push_triangle(triangle[0], triangle[1], triangle[2]);
+            }
+        }
+    }
+
+    if normals.is_none() {
+        let generated = mesh.smooth_normals();
+        for (vertex, normal) in mesh.vertices.iter_mut().zip(generated) {
+            vertex.normal = normal.into();
+        }
+    }
+
+    Ok(Some(mesh))
+}
+
+/// Base color and emissive textures hold sRGB colors, everything else glTF
+/// textures hold, like normals, roughness or occlusion, is linear data
+fn image_spaces(document: &gltf::Document, count: usize) -> Vec<texture::ColorSpace> {
+    let mut spaces = vec![texture::ColorSpace::Linear; count];
+    for material in document.materials() {
+        let colors = material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .into_iter()
+            .chain(material.emissive_texture());
+        for info in colors {
+            spaces[info.texture().source().index()] = texture::ColorSpace::Srgb;
+        }
+    }
+    spaces
+}
+
+fn import_material(material: gltf::Material) -> PbrMaterial {
+    let name = material.name().unwrap_or_default().to_string();
+    let pbr = material.pbr_metallic_roughness();
+    let base_color_texture = match pbr.base_color_texture() {
+        Some(info) if info.tex_coord() != 0 => {
+            eprintln!(
+                "warning: leaving out the base color texture of {}, it uses TEXCOORD_{}",
+                name,
+                info.tex_coord()
+            );
+            None
+        }
+        info => info.map(|info| info.texture().source().index()),
+    };
+
+    PbrMaterial {
+        name,
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture,
+        emissive_factor: material.emissive_factor(),
+    }
+}
+
+/// Expands every pixel format glTF images can decode to into RGBA8
+fn convert_image(data: &gltf::image::Data) -> Result<image::RgbaImage, LoadError> {
+    use gltf::image::Format;
+
+    let pixels = &data.pixels;
+    let rgba: Vec<u8> = match data.format {
+        Format::R8 => pixels.iter().flat_map(|&r| vec![r, r, r, 255]).collect(),
+        Format::R8G8 => pixels
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8G8B8 => pixels
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8B8A8 => pixels.clone(),
+        Format::B8G8R8 => pixels
+            .chunks(3)
+            .flat_map(|p| vec![p[2], p[1], p[0], 255])
+            .collect(),
+        Format::B8G8R8A8 => pixels
+            .chunks(4)
+            .flat_map(|p| vec![p[2], p[1], p[0], p[3]])
+            .collect(),
+        // 16 bit channels are little endian, keep the high byte
+        Format::R16 => pixels
+            .chunks(2)
+            .flat_map(|p| vec![p[1], p[1], p[1], 255])
+            .collect(),
+        Format::R16G16 => pixels
+            .chunks(4)
+            .flat_map(|p| vec![p[1], p[3], 0, 255])
+            .collect(),
+        Format::R16G16B16 => pixels
+            .chunks(6)
+            .flat_map(|p| vec![p[1], p[3], p[5], 255])
+            .collect(),
+        Format::R16G16B16A16 => pixels
+            .chunks(8)
+            .flat_map(|p| vec![p[1], p[3], p[5], p[7]])
+            .collect(),
+    };
+
+    image::RgbaImage::from_raw(data.width, data.height, rgba)
+        .ok_or_else(|| LoadError::format("image data doesn't match its size"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base64(data: &[u8]) -> String {
+        const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in data.chunks(3) {
+            let byte = |i: usize| u32::from(*chunk.get(i).unwrap_or(&0));
+            let bits = byte(0) << 16 | byte(1) << 8 | byte(2);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(TABLE[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        encoded
+    }
+
+    /// One triangle under a scaled child node and two cameras 5 units away
+    /// from the origin, one looking down glTF's -z and one down -x. Written
+    /// to `name` in the temp directory, slice imports can't use data URIs.
+    fn scene(name: &str) -> GltfScene {
+        let mut bytes = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0, 2, 3]}}],
+                "nodes": [
+                    {{"mesh": 0, "translation": [1, 2, 3], "children": [1]}},
+                    {{"scale": [2, 2, 2]}},
+                    {{"camera": 0, "translation": [0, 0, 5]}},
+                    {{"camera": 0, "translation": [5, 0, 0],
+                      "rotation": [0, 0.70710677, 0, 0.70710677]}}
+                ],
+                "cameras": [{{"type": "perspective",
+                    "perspective": {{"yfov": 1.0, "znear": 0.1, "zfar": 100}}}}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}}}]}}],
+                "buffers": [{{"byteLength": 36,
+                    "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+                "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3,
+                    "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}}]
+            }}"#,
+            base64(&bytes)
+        );
+        let path = std::env::temp_dir().join(format!("troxel_{}.gltf", name));
+        std::fs::write(&path, json).unwrap();
+        load(&path).unwrap()
+    }
+
+    fn sc_desc() -> wgpu::SwapChainDescriptor {
+        wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: 200,
+            height: 100,
+            present_mode: wgpu::PresentMode::Fifo,
+        }
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).mag() < 1e-4
+    }
+
+    #[test]
+    fn world_matrices_are_in_engine_space() {
+        let scene = scene("world_matrices");
+        assert_eq!(scene.roots, vec![0, 2, 3]);
+        assert_eq!(scene.nodes[1].parent, Some(0));
+        assert_eq!(scene.meshes[0].primitives[0].mesh.indices, vec![0, 1, 2]);
+
+        // glTF (1 * 2 + 1, 2, 3) with y and z flipped
+        let point = scene.nodes[1].world * Vec4::new(1.0, 0.0, 0.0, 1.0);
+        assert!(
+            close(point.xyz(), Vec3::new(3.0, -2.0, -3.0)),
+            "{:?}",
+            point
+        );
+    }
+
+    #[test]
+    fn world_primitives_are_baked_into_engine_space() {
+        let scene = scene("world_primitives");
+        let nodes = scene.scene_nodes();
+        assert_eq!(nodes.len(), 4);
+        let position = |node| nodes.iter().position(|&n| n == node).unwrap();
+        assert!(position(0) < position(1));
+        assert!(scene.world_primitives(1).is_empty());
+
+        let primitives = scene.world_primitives(0);
+        assert_eq!(primitives.len(), 1);
+        let mesh = &primitives[0].mesh;
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        // glTF (1, 0, 0) + (1, 2, 3) with y and z flipped, the generated
+        // normal along glTF's +z too
+        let vertex = &mesh.vertices[1];
+        assert!(close(vertex.position.into(), Vec3::new(2.0, -2.0, -3.0)));
+        assert!(close(vertex.normal.into(), Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn cameras_look_at_what_they_look_at_in_gltf() {
+        let scene = scene("cameras");
+        let camera = scene.camera(2, &sc_desc()).unwrap();
+
+        // glTF (0, 0, 5) looking down -z at the origin
+        let ray = camera.ray();
+        assert!(
+            close(ray.origin, Vec3::new(0.0, 0.0, -5.0)),
+            "{:?}",
+            ray.origin
+        );
+        assert!(
+            close(ray.direction, Vec3::new(0.0, 0.0, 1.0)),
+            "{:?}",
+            ray.direction
+        );
+
+        // the origin is in front, glTF's +y (engine -y) is up on screen and
+        // +x is right, wgpu's clip space has y pointing up
+        let project = |point: Vec3| {
+            let clip = camera.to_matrix() * Vec4::new(point.x, point.y, point.z, 1.0);
+            assert!(clip.w > 0.0, "{:?} is behind the camera", point);
+            clip.xyz() / clip.w
+        };
+        assert!(close(
+            project(Vec3::zero()) * Vec3::new(1.0, 1.0, 0.0),
+            Vec3::zero()
+        ));
+        assert!(project(Vec3::new(0.0, -1.0, 0.0)).y > 0.0);
+        assert!(project(Vec3::new(1.0, 0.0, 0.0)).x > 0.0);
+
+        // turned a quarter around glTF's y, looking down -x at the origin
+        let ray = scene.camera(3, &sc_desc()).unwrap().ray();
+        assert!(
+            close(ray.origin, Vec3::new(5.0, 0.0, 0.0)),
+            "{:?}",
+            ray.origin
+        );
+        assert!(
+            close(ray.direction, Vec3::new(-1.0, 0.0, 0.0)),
+            "{:?}",
+            ray.direction
+        );
+    }
+
+    #[test]
+    fn nodes_without_a_camera_have_none() {
+        assert!(scene("no_camera").camera(0, &sc_desc()).is_none());
+    }
+
+    #[test]
+    fn only_color_textures_are_srgb() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "images": [{"uri": "color.png"}, {"uri": "normal.png"},
+                       {"uri": "emission.png"}, {"uri": "unused.png"}],
+            "textures": [{"source": 0}, {"source": 1}, {"source": 2}],
+            "materials": [{
+                "pbrMetallicRoughness": {"baseColorTexture": {"index": 0}},
+                "normalTexture": {"index": 1},
+                "emissiveTexture": {"index": 2}
+            }]
+        }"#;
+        use texture::ColorSpace::{Linear, Srgb};
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        assert_eq!(image_spaces(&document, 4), vec![Srgb, Linear, Srgb, Linear]);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod error;
+pub mod gltf;
+pub mod obj;
+pub mod vox;
@@ -0,0 +1,534 @@
+//! Wavefront OBJ + MTL import.
+//!
+//! Supports positions, texture coordinates, normals, polygon faces of any size,
+//! objects / groups and `usemtl` material switches. Every object, group and
+//! material switch becomes its own `ObjMesh`.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use uv::vec::Vec3;
+
+use crate::geometry::mesh::Mesh;
+use crate::loader::error::LoadError;
+use crate::types::VertexN;
+
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+pub struct ObjMesh {
+    pub mesh: Mesh<VertexN>,
+    /// Index into `ObjModel::materials`
+    pub material: Option<usize>,
+}
+
+/// The parameters of an MTL material the engine can draw, the texture path
+/// is already resolved
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: [f32; 3],
+    pub emissive: [f32; 3],
+    /// Opacity, 1 is opaque
+    pub dissolve: f32,
+    pub diffuse_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: [1.0; 3],
+            emissive: [0.0; 3],
+            dissolve: 1.0,
+            diffuse_texture: None,
+        }
+    }
+}
+
+/// Loads an OBJ file and the MTL libraries it references
+pub fn load(path: impl AsRef<Path>) -> Result<ObjModel, LoadError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| LoadError::from(e).in_file(path))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, base_dir).map_err(|e| e.in_file(path))
+}
+
+/// Parses OBJ source, `mtllib`s are looked up relative to `base_dir`
+pub fn parse(source: &str, base_dir: &Path) -> Result<ObjModel, LoadError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    let mut meshes: Vec<ObjMesh> = Vec::new();
+    let mut builder = MeshBuilder::new(None);
+    let mut skipped: HashSet<&str> = HashSet::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let v = floats(parts, line_number, 3, 3)?;
+                positions.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let t = floats(parts, line_number, 2, 1)?;
+                // OBJ has its texture origin at the bottom left, wgpu at the top left
+                tex_coords.push([t[0], 1.0 - t[1]]);
+            }
+            "vn" => {
+                let n = floats(parts, line_number, 3, 3)?;
+                normals.push(Vec3::new(n[0], n[1], n[2]).normalized());
+            }
+            "f" => {
+                let mut corners = Vec::new();
+                for corner in parts {
+                    corners.push(parse_corner(
+                        corner,
+                        line_number,
+                        positions.len(),
+                        tex_coords.len(),
+                        normals.len(),
+                    )?);
+                }
+                if corners.len() < 3 {
+                    return Err(LoadError::parse(
+                        line_number,
+                        "face with less than 3 vertices",
+                    ));
+                }
+                builder.add_face(&corners, &positions, &tex_coords, &normals);
+            }
+            "o" | "g" => {
+                let material = builder.material;
+                builder.finish_into(&mut meshes);
+                builder = MeshBuilder::new(material);
+            }
+            "usemtl" => {
+                let name = parts.collect::<Vec<_>>().join(" ");
+                let material = materials.iter().position(|m| m.name == name);
+                if material.is_none() {
+                    eprintln!("warning: line {}: unknown material {}", line_number, name);
+                }
+                if material != builder.material {
+                    builder.finish_into(&mut meshes);
+                    builder = MeshBuilder::new(material);
+                }
+            }
+            "mtllib" => {
+                for library in parts {
+                    let path = base_dir.join(library);
+                    match fs::read_to_string(&path) {
+                        Ok(mtl) => {
+                            let mtl_dir = path.parent().unwrap_or(base_dir);
+                            let parsed = parse_mtl(&mtl, mtl_dir).map_err(|e| e.in_file(&path))?;
+                            materials.extend(parsed);
+                        }
+                        Err(error) => {
+                            eprintln!("warning: can't read {}: {}", path.display(), error);
+                        }
+                    }
+                }
+            }
+            // smoothing groups, lines and points don't affect triangle meshes
+            "s" | "l" | "p" => {}
+            // free form geometry, render attributes and the like, warned about once
+            _ => {
+                if skipped.insert(keyword) {
+                    eprintln!(
+                        "warning: line {}: skipping unsupported statement {}",
+                        line_number, keyword
+                    );
+                }
+            }
+        }
+    }
+    builder.finish_into(&mut meshes);
+
+    Ok(ObjModel { meshes, materials })
+}
+
+/// Parses MTL source, texture paths are resolved relative to `base_dir`
+pub fn parse_mtl(source: &str, base_dir: &Path) -> Result<Vec<ObjMaterial>, LoadError> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = parts.collect::<Vec<_>>().join(" ");
+            materials.push(ObjMaterial::new(&name));
+            continue;
+        }
+
+        let material = materials
+            .last_mut()
+            .ok_or_else(|| LoadError::parse(line_number, format!("{} before newmtl", keyword)))?;
+
+        match keyword {
+            "Kd" => material.diffuse = color(parts, line_number)?,
+            "Ke" => material.emissive = color(parts, line_number)?,
+            "d" => material.dissolve = floats(parts, line_number, 1, 1)?[0],
+            "Tr" => material.dissolve = 1.0 - floats(parts, line_number, 1, 1)?[0],
+            "map_Kd" => {
+                material.diffuse_texture = Some(texture_path(parts, line_number, base_dir)?)
+            }
+            // specular and ambient terms, lighting models and the remaining
+            // maps aren't used by the engine
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Collects one mesh, de-duplicating `f` corners into shared vertices
+struct MeshBuilder {
+    material: Option<usize>,
+    mesh: Mesh<VertexN>,
+    lookup: HashMap<Corner, u32>,
+    has_normal: Vec<bool>,
+}
+
+/// Position, texture coordinate and normal index of a face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+impl MeshBuilder {
+    fn new(material: Option<usize>) -> Self {
+        Self {
+            material,
+            mesh: Mesh::new(),
+            lookup: HashMap::new(),
+            has_normal: Vec::new(),
+        }
+    }
+
+    fn add_face(
+        &mut self,
+        corners: &[Corner],
+        positions: &[Vec3],
+        tex_coords: &[[f32; 2]],
+        normals: &[Vec3],
+    ) {
+        let ids: Vec<u32> = corners
+            .iter()
+            .map(|corner| self.vertex(*corner, positions, tex_coords, normals))
+            .collect();
+
+        let polygon: Vec<Vec3> = corners.iter().map(|c| positions[c.0]).collect();
+        for [a, b, c] in triangulate(&polygon) {
+            self.mesh.push_triangle(ids[a], ids[b], ids[c]);
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        corner: Corner,
+        positions: &[Vec3],
+        tex_coords: &[[f32; 2]],
+        normals: &[Vec3],
+    ) -> u32 {
+        if let Some(&id) = self.lookup.get(&corner) {
+            return id;
+        }
+
+        let (position, tex_coord, normal) = corner;
+        let id = self.mesh.push_vertex(VertexN {
+            position: positions[position].into(),
+            normal: normal.map(|n| normals[n]).unwrap_or_else(Vec3::zero).into(),
+            tex_coords: tex_coord.map(|t| tex_coords[t]).unwrap_or([0.0, 0.0]),
+        });
+        self.has_normal.push(normal.is_some());
+        self.lookup.insert(corner, id);
+        id
+    }
+
+    fn finish_into(mut self, meshes: &mut Vec<ObjMesh>) {
+        if self.mesh.is_empty() {
+            return;
+        }
+        self.generate_missing_normals();
+        meshes.push(ObjMesh {
+            mesh: self.mesh,
+            material: self.material,
+        });
+    }
+
+    /// Smooth normals for corners without a `vn`
+    fn generate_missing_normals(&mut self) {
+        if self.has_normal.iter().all(|&has| has) {
+            return;
+        }
+
+        let normals = self.mesh.smooth_normals();
+        for (i, vertex) in self.mesh.vertices.iter_mut().enumerate() {
+            if !self.has_normal[i] {
+                vertex.normal = normals[i].into();
+            }
+        }
+    }
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn` into zero based indices
+fn parse_corner(
+    corner: &str,
+    line: usize,
+    positions: usize,
+    tex_coords: usize,
+    normals: usize,
+) -> Result<Corner, LoadError> {
+    let mut indices = corner.split('/');
+    let position = match indices.next() {
+        Some(index) if !index.is_empty() => resolve_index(index, positions, line)?,
+        _ => {
+            return Err(LoadError::parse(
+                line,
+                format!("missing vertex index in {}", corner),
+            ))
+        }
+    };
+    let tex_coord = match indices.next() {
+        Some(index) if !index.is_empty() => Some(resolve_index(index, tex_coords, line)?),
+        _ => None,
+    };
+    let normal = match indices.next() {
+        Some(index) if !index.is_empty() => Some(resolve_index(index, normals, line)?),
+        _ => None,
+    };
+    if indices.next().is_some() {
+        return Err(LoadError::parse(
+            line,
+            format!("invalid face corner {}", corner),
+        ));
+    }
+
+    Ok((position, tex_coord, normal))
+}
+
+/// OBJ indices start at 1, negative ones count back from the newest element
+fn resolve_index(index: &str, count: usize, line: usize) -> Result<usize, LoadError> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| LoadError::parse(line, format!("invalid index {}", index)))?;
+    let resolved = if value > 0 {
+        value - 1
+    } else {
+        count as i64 + value
+    };
+
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::parse(
+            line,
+            format!("index {} out of range", index),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// Reads up to `count` floats, at least `required`; missing optional ones are 0
+fn floats<'a>(
+    parts: impl Iterator<Item = &'a str>,
+    line: usize,
+    count: usize,
+    required: usize,
+) -> Result<Vec<f32>, LoadError> {
+    let mut values = Vec::with_capacity(count);
+    for part in parts.take(count) {
+        values.push(
+            part.parse()
+                .map_err(|_| LoadError::parse(line, format!("invalid number {}", part)))?,
+        );
+    }
+
+    if values.len() < required {
+        return Err(LoadError::parse(
+            line,
+            format!("expected {} numbers, found {}", required, values.len()),
+        ));
+    }
+    values.resize(count, 0.0);
+    Ok(values)
+}
+
+fn color<'a>(parts: impl Iterator<Item = &'a str>, line: usize) -> Result<[f32; 3], LoadError> {
+    let c = floats(parts, line, 3, 3)?;
+    Ok([c[0], c[1], c[2]])
+}
+
+/// The file name is the last argument, everything before it are map options
+fn texture_path<'a>(
+    parts: impl Iterator<Item = &'a str>,
+    line: usize,
+    base_dir: &Path,
+) -> Result<PathBuf, LoadError> {
+    let file = parts
+        .last()
+        .ok_or_else(|| LoadError::parse(line, "missing texture file"))?;
+    Ok(base_dir.join(file.replace('\\', "/")))
+}
+
+/// Ear clipping triangulation of a planar polygon given in ccw order.
+///
+/// Falls back to a fan for whatever is left if the polygon is degenerate.
+fn triangulate(polygon: &[Vec3]) -> Vec<[usize; 3]> {
+    if polygon.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method, robust for concave and slightly non planar polygons
+    let mut normal = Vec3::zero();
+    for (i, current) in polygon.iter().enumerate() {
+        let next = polygon[(i + 1) % polygon.len()];
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    let mut triangles = Vec::with_capacity(polygon.len() - 2);
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let prev = polygon[remaining[(i + count - 1) % count]];
+            let current = polygon[remaining[i]];
+            let next = polygon[remaining[(i + 1) % count]];
+
+            let convex = (current - prev).cross(next - current).dot(normal) > 0.0;
+            convex
+                && (0..count)
+                    .filter(|&j| j != i && j != (i + 1) % count && j != (i + count - 1) % count)
+                    .all(|j| !inside_triangle(polygon[remaining[j]], prev, current, next, normal))
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + count - 1) % count],
+                    remaining[i],
+                    remaining[(i + 1) % count],
+                ]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+fn inside_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3, normal: Vec3) -> bool {
+    (b - a).cross(p - a).dot(normal) >= 0.0
+        && (c - b).cross(p - b).dot(normal) >= 0.0
+        && (a - c).cross(p - c).dot(normal) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_statements_are_skipped() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvp 0.5 0.5\ncstype bspline\n\
+                      curv 0 1 1 2\nshadow_obj shadow.obj\nf 1 2 3\n";
+        let model = parse(source, Path::new("")).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].mesh.indices.len(), 3);
+    }
+
+    #[test]
+    fn malformed_statements_are_errors() {
+        let error = parse("v 0 0\n", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "line 1: expected 3 numbers, found 2");
+        let error = parse("v 0 0 0\nf 1 2 3\n", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "line 2: index 2 out of range");
+    }
+
+    #[test]
+    fn mtl_keeps_what_the_engine_draws() {
+        let source = "newmtl glow\nKa 1 1 1\nKd 0.5 0.25 1\nKs 1 1 1\nNs 10\nKe 0 1 0\n\
+                      Tr 0.25\nmap_Kd -bm 1 textures\\glow.png\nnewmtl plain\n";
+        let materials = parse_mtl(source, Path::new("models")).unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "glow");
+        assert_eq!(materials[0].diffuse, [0.5, 0.25, 1.0]);
+        assert_eq!(materials[0].emissive, [0.0, 1.0, 0.0]);
+        assert_eq!(materials[0].dissolve, 0.75);
+        assert_eq!(
+            materials[0].diffuse_texture.as_deref(),
+            Some(Path::new("models/textures/glow.png"))
+        );
+        assert!(materials[1].diffuse_texture.is_none());
+    }
+
+    #[test]
+    fn concave_polygons_are_triangulated_inside() {
+        // an L, the corner at (1, 1) points inwards
+        let polygon: Vec<Vec3> = [(0, 0), (2, 0), (2, 1), (1, 1), (1, 2), (0, 2)]
+            .iter()
+            .map(|&(x, y)| Vec3::new(x as f32, y as f32, 0.0))
+            .collect();
+        let triangles = triangulate(&polygon);
+        assert_eq!(triangles.len(), 4);
+
+        let mut area = 0.0;
+        for &[a, b, c] in &triangles {
+            let (a, b, c) = (polygon[a], polygon[b], polygon[c]);
+            // ccw like the polygon, so none is flipped over the notch
+            let doubled = (b - a).cross(c - a).z;
+            assert!(doubled > 0.0, "{:?} {:?} {:?}", a, b, c);
+            area += doubled / 2.0;
+
+            let center = (a + b + c) / 3.0;
+            assert!(
+                center.x < 1.0 || center.y < 1.0,
+                "{:?} is in the notch",
+                center
+            );
+        }
+        // nothing overlaps or sticks out if the triangles add up to the L
+        assert_eq!(area, 3.0);
+    }
+
+    #[test]
+    fn repeated_corners_share_a_vertex() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nv 2 1 0\n\
+                      vt 0 0\nvt 1 1\nvn 0 0 1\n\
+                      f 1/1/1 2/1/1 3/1/1 4/1/1\nf 2/1/1 5/1/1 6/1/1 3/1/1\n";
+        let model = parse(source, Path::new("")).unwrap();
+        let mesh = &model.meshes[0].mesh;
+        // the quads share the corners 2 and 3
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices.len(), 12);
+
+        // the same position with another texture coordinate is a new vertex
+        let source = format!("{}f 1/2/1 2/1/1 3/1/1\n", source);
+        let model = parse(&source, Path::new("")).unwrap();
+        assert_eq!(model.meshes[0].mesh.vertices.len(), 7);
+    }
+}
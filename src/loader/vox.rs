@@ -0,0 +1,299 @@
+//! MagicaVoxel .vox import and export.
+//!
+//! Reads the SIZE / XYZI model chunks and the RGBA palette, everything else
+//! (materials, the scene graph of newer files) is skipped. MagicaVoxel is z-up,
+//! so a file's (x, y, z) ends up at (x, size z - 1 - z, y) in a `VoxelGrid`,
+//! which puts the top of the model towards the engine's -y up.
+use std::fs;
+use std::path::Path;
+
+use crate::loader::error::LoadError;
+use crate::voxel::grid::{Palette, VoxelGrid};
+
+const VERSION: i32 = 150;
+/// Models can't be bigger than this, voxel coordinates are stored as bytes
+pub const MAX_SIZE: usize = 256;
+
+pub struct VoxFile {
+    pub models: Vec<VoxelGrid>,
+    pub palette: Palette,
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<VoxFile, LoadError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|e| LoadError::from(e).in_file(path))?;
+    parse(&bytes).map_err(|e| e.in_file(path))
+}
+
+pub fn save(path: impl AsRef<Path>, file: &VoxFile) -> Result<(), LoadError> {
+    let path = path.as_ref();
+    let bytes = write(file).map_err(|e| e.in_file(path))?;
+    fs::write(path, bytes).map_err(|e| LoadError::from(e).in_file(path))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<VoxFile, LoadError> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != b"VOX " {
+        return Err(LoadError::format("not a .vox file"));
+    }
+    let _version = reader.i32()?;
+
+    let main = reader.chunk()?;
+    if main.id != *b"MAIN" {
+        return Err(LoadError::format("missing MAIN chunk"));
+    }
+
+    let mut children = Reader {
+        bytes: main.children,
+        position: 0,
+    };
+    let mut models = Vec::new();
+    let mut size: Option<[usize; 3]> = None;
+    let mut palette = default_palette();
+
+    while !children.is_empty() {
+        let chunk = children.chunk()?;
+        let mut content = Reader {
+            bytes: chunk.content,
+            position: 0,
+        };
+        match &chunk.id {
+            b"SIZE" => {
+                let mut dimensions = [0; 3];
+                for dimension in dimensions.iter_mut() {
+                    let value = content.i32()?;
+                    if value <= 0 || value as usize > MAX_SIZE {
+                        return Err(LoadError::format(format!("invalid model size {}", value)));
+                    }
+                    *dimension = value as usize;
+                }
+                size = Some(dimensions);
+            }
+            b"XYZI" => {
+                let [sx, sy, sz] = size
+                    .take()
+                    .ok_or_else(|| LoadError::format("XYZI chunk without SIZE"))?;
+                let mut grid = VoxelGrid::new([sx, sz, sy]);
+
+                let count = content.i32()?;
+                if count < 0 {
+                    return Err(LoadError::format("negative voxel count"));
+                }
+                for _ in 0..count {
+                    let voxel = content.take(4)?;
+                    let (x, y, z) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize);
+                    if x >= sx || y >= sy || z >= sz {
+                        return Err(LoadError::format(format!(
+                            "voxel ({}, {}, {}) outside of the model",
+                            x, y, z
+                        )));
+                    }
+                    grid.set(x, sz - 1 - z, y, voxel[3]);
+                }
+                models.push(grid);
+            }
+            b"RGBA" => {
+                // entry i of the chunk is the color of voxel value i + 1
+                for entry in palette.iter_mut().skip(1) {
+                    let rgba = content.take(4)?;
+                    *entry = [rgba[0], rgba[1], rgba[2], rgba[3]];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(VoxFile { models, palette })
+}
+
+pub fn write(file: &VoxFile) -> Result<Vec<u8>, LoadError> {
+    let mut children = Vec::new();
+    if file.models.len() > 1 {
+        write_chunk(
+            &mut children,
+            b"PACK",
+            &(file.models.len() as i32).to_le_bytes(),
+        );
+    }
+
+    for model in file.models.iter() {
+        let [sx, sy, sz] = model.size();
+        if sx > MAX_SIZE || sy > MAX_SIZE || sz > MAX_SIZE {
+            return Err(LoadError::format(format!(
+                "model of {:?} is bigger than {} voxels",
+                model.size(),
+                MAX_SIZE
+            )));
+        }
+
+        let mut size = Vec::with_capacity(12);
+        for &dimension in [sx, sz, sy].iter() {
+            size.extend_from_slice(&(dimension as i32).to_le_bytes());
+        }
+        write_chunk(&mut children, b"SIZE", &size);
+
+        let mut voxels = Vec::with_capacity(4 + model.count() * 4);
+        voxels.extend_from_slice(&(model.count() as i32).to_le_bytes());
+        for ([x, y, z], value) in model.iter() {
+            voxels.extend_from_slice(&[x as u8, z as u8, (sy - 1 - y) as u8, value]);
+        }
+        write_chunk(&mut children, b"XYZI", &voxels);
+    }
+
+    let mut rgba = Vec::with_capacity(256 * 4);
+    for value in 1..256 {
+        rgba.extend_from_slice(&file.palette[value]);
+    }
+    rgba.extend_from_slice(&[0; 4]);
+    write_chunk(&mut children, b"RGBA", &rgba);
+
+    let mut bytes = Vec::with_capacity(20 + children.len());
+    bytes.extend_from_slice(b"VOX ");
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(b"MAIN");
+    bytes.extend_from_slice(&0i32.to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&children);
+    Ok(bytes)
+}
+
+/// MagicaVoxel's palette for files without an RGBA chunk: a 6x6x6 color cube
+/// followed by red, green, blue and gray ramps
+pub fn default_palette() -> Palette {
+    let mut palette = [[0; 4]; 256];
+    let steps = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut value = 1;
+    for &r in steps.iter() {
+        for &g in steps.iter() {
+            for &b in steps.iter() {
+                if r == 0 && g == 0 && b == 0 {
+                    continue;
+                }
+                palette[value] = [r, g, b, 0xff];
+                value += 1;
+            }
+        }
+    }
+    for channel in 0..4 {
+        for &shade in ramp.iter() {
+            palette[value] = match channel {
+                0 => [shade, 0, 0, 0xff],
+                1 => [0, shade, 0, 0xff],
+                2 => [0, 0, shade, 0xff],
+                _ => [shade, shade, shade, 0xff],
+            };
+            value += 1;
+        }
+    }
+    palette
+}
+
+struct Chunk<'a> {
+    id: [u8; 4],
+    content: &'a [u8],
+    children: &'a [u8],
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() - self.position < count {
+            return Err(LoadError::format("unexpected end of file"));
+        }
+        let taken = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn chunk(&mut self) -> Result<Chunk<'a>, LoadError> {
+        let id = self.take(4)?;
+        let content_size = self.i32()?;
+        let children_size = self.i32()?;
+        if content_size < 0 || children_size < 0 {
+            return Err(LoadError::format("negative chunk size"));
+        }
+        Ok(Chunk {
+            id: [id[0], id[1], id[2], id[3]],
+            content: self.take(content_size as usize)?,
+            children: self.take(children_size as usize)?,
+        })
+    }
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&0i32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_and_palette_survive_a_round_trip() {
+        let mut first = VoxelGrid::new([3, 4, 5]);
+        first.set(0, 0, 0, 1);
+        first.set(1, 2, 3, 7);
+        first.set(2, 3, 4, 200);
+        let mut second = VoxelGrid::new([1, 1, 1]);
+        second.set(0, 0, 0, 9);
+        let mut palette = default_palette();
+        palette[7] = [1, 2, 3, 4];
+
+        let file = VoxFile {
+            models: vec![first.clone(), second.clone()],
+            palette,
+        };
+        let bytes = write(&file).unwrap();
+        let read = parse(&bytes).unwrap();
+        assert_eq!(read.models, vec![first, second]);
+        assert_eq!(read.palette[7], [1, 2, 3, 4]);
+        assert_eq!(read.palette[8], palette[8]);
+
+        assert!(parse(&bytes[..bytes.len() - 3]).is_err());
+        assert!(parse(b"VOX").is_err());
+    }
+
+    #[test]
+    fn z_up_becomes_minus_y_up() {
+        let mut size = Vec::new();
+        for dimension in [2i32, 3, 4].iter() {
+            size.extend_from_slice(&dimension.to_le_bytes());
+        }
+        let mut voxels = 1i32.to_le_bytes().to_vec();
+        // at the top of the model
+        voxels.extend_from_slice(&[1, 2, 3, 5]);
+        let mut children = Vec::new();
+        write_chunk(&mut children, b"SIZE", &size);
+        write_chunk(&mut children, b"XYZI", &voxels);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&children);
+
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.models[0].size(), [2, 4, 3]);
+        assert_eq!(file.models[0].get(1, 0, 2), 5);
+        assert_eq!(file.models[0].count(), 1);
+    }
+}
@@ -1,6 +1,7 @@
 extern crate ultraviolet as uv;
 
 use std::mem;
+use std::path::PathBuf;
 
 use winit::{
     event::*,
@@ -14,14 +15,21 @@ use wgpu::{RenderPassDescriptor, ShaderModule};
 use winit_input_helper::WinitInputHelper;
 
 mod config;
+mod geometry;
+mod loader;
 mod render;
 mod state;
 mod types;
 mod utils;
 mod tools;
+mod voxel;
 
+use render::upload::StagingBelt;
 use state::traits::Stateful;
 
+/// Size of the staging chunks all per frame uploads go through
+const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 0x1000;
+
 
 use crate::state::state_handler::StateHandler;
 
@@ -57,8 +65,8 @@ fn main() {
                 }
             }
             Event::RedrawRequested(_) => {
-                state.update();
-                state.render();
+                let encoder = state.update();
+                state.render(encoder);
             }
             Event::MainEventsCleared => {
                 window.request_redraw();
@@ -77,6 +85,7 @@ struct State {
     input: WinitInputHelper,
 
     state_handler: state::state_handler::StateHandler,
+    staging_belt: StagingBelt,
 
     size: winit::dpi::PhysicalSize<u32>,
 }
@@ -102,6 +111,7 @@ impl State {
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 extensions: wgpu::Extensions {
+                    // samplers can't use it yet, see `SamplerPreset`
                     anisotropic_filtering: false,
                 },
                 limits: Default::default(),
@@ -124,6 +134,25 @@ impl State {
             &device, &queue, &sc_desc, &size,
         )));
 
+        let mut textured_state = state::states::textured_state::TexturedState::new(
+            &device, &queue, &sc_desc, &size,
+        );
+        let mut voxel_state = state::states::voxel_state::VoxelState::new(
+            &device, &queue, &sc_desc, &size,
+        );
+        // models given on the command line, e.g. `troxel model.obj house.vox`
+        for path in std::env::args_os().skip(1).map(PathBuf::from) {
+            let imported = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("vox") => voxel_state.import(&device, &path),
+                _ => textured_state.import(&device, &queue, &sc_desc, &path),
+            };
+            if let Err(e) = imported {
+                eprintln!("warning: can't import {}", e);
+            }
+        }
+        state_handler.add_state(Box::new(textured_state));
+        state_handler.add_state(Box::new(voxel_state));
+
         state_handler.set_state(state::states::state_ids::CHAOTIC);
 
         Self {
@@ -135,31 +164,38 @@ impl State {
             input,
 
             state_handler,
+            staging_belt: StagingBelt::new(STAGING_CHUNK_SIZE),
 
             size,
         }
     }
 
-    fn update(&mut self) {
-        self.state_handler.states[self.state_handler.current_state_in_vec].update(&mut self.device, &mut self.queue);
+    // records the uploads of the current state, render draws into the same encoder
+    fn update(&mut self) -> wgpu::CommandEncoder {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Frame Encoder"),
+            });
+
+        self.state_handler.states[self.state_handler.current_state_in_vec].update(&self.device, &mut encoder, &mut self.staging_belt);
+
+        encoder
     }
 
-    fn render(&mut self) {
+    fn render(&mut self, mut encoder: wgpu::CommandEncoder) {
         let frame = self
             .swap_chain
             .get_next_texture()
             .expect("Timeout getting texture");
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
 
         {
             self.state_handler.states[self.state_handler.current_state_in_vec].render(&frame, &mut encoder);
         }
 
+        self.staging_belt.finish();
         self.queue.submit(&[encoder.finish()]);
+        self.staging_belt.recall(&self.device);
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -191,6 +227,14 @@ impl State {
             self.state_handler.set_state(state_ids::CHAOTIC);
             self.state_handler.states[self.state_handler.current_state_in_vec].resize(&mut self.device, &mut self.sc_desc, &self.size);
         }
+        if self.input.key_pressed(VirtualKeyCode::F5) {
+            self.state_handler.set_state(state_ids::TEXTURED);
+            self.state_handler.states[self.state_handler.current_state_in_vec].resize(&mut self.device, &mut self.sc_desc, &self.size);
+        }
+        if self.input.key_pressed(VirtualKeyCode::F6) {
+            self.state_handler.set_state(state_ids::VOXEL);
+            self.state_handler.states[self.state_handler.current_state_in_vec].resize(&mut self.device, &mut self.sc_desc, &self.size);
+        }
         self.state_handler.states[self.state_handler.current_state_in_vec].input(&self.input)
     }
 }
@@ -0,0 +1,356 @@
+//! Packs many small images into one texture.
+//!
+//! `AtlasBuilder` places images of any size on shelves of a square power of
+//! two atlas. Every image is surrounded by `padding` pixels copied from its
+//! own border, so linear filtering and the smaller mip levels don't pull in
+//! the neighbours. A padding of 2^n keeps the first n mip levels clean.
+//!
+//! `ArrayBuilder` puts images of the same size into the layers of a 2D texture
+//! array instead, which needs no padding and allows repeating UVs.
+//!
+//! Both hand out indices in the order images were added and look them up by
+//! name.
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use image::RgbaImage;
+
+use crate::loader::error::LoadError;
+use crate::render::texture::{ColorSpace, Texture};
+
+/// Largest atlas the builder will create, the minimum every wgpu backend has to
+/// support
+pub const MAX_ATLAS_SIZE: u32 = 8192;
+
+/// Smallest atlas side
+const MIN_ATLAS_SIZE: u32 = 64;
+
+/// Texture coordinates of an image in the atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    /// Maps coordinates from 0 to 1 across the image into the atlas
+    pub fn map(&self, uv: [f32; 2]) -> [f32; 2] {
+        [
+            self.min[0] + (self.max[0] - self.min[0]) * uv[0],
+            self.min[1] + (self.max[1] - self.min[1]) * uv[1],
+        ]
+    }
+}
+
+/// Where an image ended up, in pixels without the padding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct AtlasBuilder {
+    padding: u32,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(padding: u32) -> Self {
+        Self {
+            padding,
+            images: Vec::new(),
+        }
+    }
+
+    /// Adds an image and returns its index
+    pub fn add(&mut self, name: impl Into<String>, image: RgbaImage) -> u32 {
+        self.images.push((name.into(), image));
+        self.images.len() as u32 - 1
+    }
+
+    /// Packs the images into the smallest atlas they fit in
+    pub fn build(self) -> Result<Atlas, LoadError> {
+        let padded: Vec<(u32, u32)> = self
+            .images
+            .iter()
+            .map(|(_, image)| {
+                let (width, height) = image.dimensions();
+                (width + 2 * self.padding, height + 2 * self.padding)
+            })
+            .collect();
+
+        // tallest first keeps the shelves full
+        let mut order: Vec<usize> = (0..padded.len()).collect();
+        order.sort_by_key(|&i| Reverse((padded[i].1, padded[i].0)));
+
+        let area: u64 = padded.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+        let widest = padded.iter().map(|&(w, h)| w.max(h)).max().unwrap_or(0);
+        let mut size = MIN_ATLAS_SIZE;
+        while (size as u64 * size as u64) < area || size < widest {
+            size *= 2;
+        }
+
+        let corners = loop {
+            if size > MAX_ATLAS_SIZE {
+                return Err(LoadError::format(format!(
+                    "{} images don't fit into a {}x{} atlas",
+                    self.images.len(),
+                    MAX_ATLAS_SIZE,
+                    MAX_ATLAS_SIZE
+                )));
+            }
+            match pack_shelves(&padded, &order, size) {
+                Some(corners) => break corners,
+                None => size *= 2,
+            }
+        };
+
+        let mut pixels = RgbaImage::new(size, size);
+        let mut placements = Vec::with_capacity(self.images.len());
+        let mut names = HashMap::new();
+        for (index, ((name, image), &(x, y))) in self.images.iter().zip(&corners).enumerate() {
+            blit_padded(&mut pixels, image, x, y, self.padding);
+            placements.push(Placement {
+                x: x + self.padding,
+                y: y + self.padding,
+                width: image.width(),
+                height: image.height(),
+            });
+            names.entry(name.clone()).or_insert(index as u32);
+        }
+
+        Ok(Atlas {
+            pixels,
+            placements,
+            names,
+        })
+    }
+}
+
+/// Top left corner of every padded image, or `None` if they don't fit into a
+/// `size` by `size` atlas
+fn pack_shelves(padded: &[(u32, u32)], order: &[usize], size: u32) -> Option<Vec<(u32, u32)>> {
+    let mut corners = vec![(0, 0); padded.len()];
+    let mut x = 0;
+    let mut y = 0;
+    let mut shelf_height = 0;
+    for &index in order {
+        let (width, height) = padded[index];
+        if x + width > size {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if width > size || y + height > size {
+            return None;
+        }
+        corners[index] = (x, y);
+        x += width;
+        shelf_height = shelf_height.max(height);
+    }
+    Some(corners)
+}
+
+/// Copies `image` to `x + padding`, `y + padding` and stretches its border
+/// pixels out over the padding
+fn blit_padded(target: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32, padding: u32) {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    for ty in 0..height + 2 * padding {
+        let sy = ty.saturating_sub(padding).min(height - 1);
+        for tx in 0..width + 2 * padding {
+            let sx = tx.saturating_sub(padding).min(width - 1);
+            target.put_pixel(x + tx, y + ty, *image.get_pixel(sx, sy));
+        }
+    }
+}
+
+/// Packed images, still on the CPU
+pub struct Atlas {
+    pixels: RgbaImage,
+    placements: Vec<Placement>,
+    names: HashMap<String, u32>,
+}
+
+impl Atlas {
+    pub fn size(&self) -> u32 {
+        self.pixels.width()
+    }
+
+    /// Index of the first image added under `name`
+    pub fn index(&self, name: &str) -> Option<u32> {
+        self.names.get(name).copied()
+    }
+
+    pub fn placement(&self, index: u32) -> Option<Placement> {
+        self.placements.get(index as usize).copied()
+    }
+
+    pub fn uv(&self, index: u32) -> Option<UvRect> {
+        let size = self.size() as f32;
+        self.placement(index).map(|p| UvRect {
+            min: [p.x as f32 / size, p.y as f32 / size],
+            max: [
+                (p.x + p.width) as f32 / size,
+                (p.y + p.height) as f32 / size,
+            ],
+        })
+    }
+
+    pub fn uv_by_name(&self, name: &str) -> Option<UvRect> {
+        self.index(name).and_then(|index| self.uv(index))
+    }
+
+    pub fn upload(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        space: ColorSpace,
+    ) -> (Texture, wgpu::CommandBuffer) {
+        Texture::from_rgba(device, &self.pixels, label, space)
+    }
+}
+
+/// Images of the same size for the layers of a 2D texture array
+pub struct ArrayBuilder {
+    size: Option<(u32, u32)>,
+    layers: Vec<RgbaImage>,
+    names: HashMap<String, u32>,
+}
+
+impl ArrayBuilder {
+    pub fn new() -> Self {
+        Self {
+            size: None,
+            layers: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Adds an image as the next layer and returns its index. All layers need
+    /// the size of the first one.
+    pub fn add(&mut self, name: impl Into<String>, image: RgbaImage) -> Result<u32, LoadError> {
+        let name = name.into();
+        let dimensions = image.dimensions();
+        match self.size {
+            Some(size) if size != dimensions => {
+                return Err(LoadError::format(format!(
+                    "{} is {}x{}, the other layers are {}x{}",
+                    name, dimensions.0, dimensions.1, size.0, size.1
+                )))
+            }
+            _ => self.size = Some(dimensions),
+        }
+
+        let layer = self.layers.len() as u32;
+        self.layers.push(image);
+        self.names.entry(name).or_insert(layer);
+        Ok(layer)
+    }
+
+    /// Layer of the first image added under `name`
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.names.get(name).copied()
+    }
+
+    pub fn upload(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        space: ColorSpace,
+    ) -> Result<(Texture, wgpu::CommandBuffer), LoadError> {
+        if self.layers.is_empty() {
+            return Err(LoadError::format("texture array without layers"));
+        }
+        Ok(Texture::array_from_rgba(device, &self.layers, label, space))
+    }
+}
+
+impl Default for ArrayBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn images_and_their_padding_dont_overlap() {
+        let size = |i: u32| (8 + (i * 7) % 25, 5 + (i * 13) % 30);
+        let mut builder = AtlasBuilder::new(2);
+        for i in 0..40 {
+            let (width, height) = size(i);
+            builder.add(format!("image {}", i), solid(width, height, i as u8 + 1));
+        }
+        let atlas = builder.build().unwrap();
+        assert!(atlas.size().is_power_of_two());
+
+        for i in 0..40 {
+            let p = atlas.placement(i).unwrap();
+            assert_eq!((p.width, p.height), size(i));
+            // every texel of the image and its padding still has its value
+            for y in p.y - 2..p.y + p.height + 2 {
+                for x in p.x - 2..p.x + p.width + 2 {
+                    assert_eq!(atlas.pixels.get_pixel(x, y)[0], i as u8 + 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uvs_are_found_by_name() {
+        let mut builder = AtlasBuilder::new(1);
+        builder.add("a", solid(16, 16, 1));
+        builder.add("b", solid(8, 4, 2));
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.size(), MIN_ATLAS_SIZE);
+
+        let uv = atlas.uv_by_name("b").unwrap();
+        let p = atlas.placement(1).unwrap();
+        let size = atlas.size() as f32;
+        assert_eq!(uv.min, [p.x as f32 / size, p.y as f32 / size]);
+        assert_eq!(uv.map([0.0, 0.0]), uv.min);
+        assert_eq!(uv.map([1.0, 1.0]), uv.max);
+        assert!(atlas.uv_by_name("c").is_none());
+    }
+
+    #[test]
+    fn images_that_dont_fit_are_an_error() {
+        let mut builder = AtlasBuilder::new(1);
+        builder.add("wide", RgbaImage::new(MAX_ATLAS_SIZE, 1));
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn layers_are_found_by_name() {
+        let mut builder = ArrayBuilder::new();
+        assert_eq!(builder.add("a", solid(16, 16, 1)).unwrap(), 0);
+        assert_eq!(builder.add("b", solid(16, 16, 2)).unwrap(), 1);
+        // the first layer keeps the name
+        assert_eq!(builder.add("a", solid(16, 16, 3)).unwrap(), 2);
+
+        assert_eq!(builder.layer("a"), Some(0));
+        assert_eq!(builder.layer("b"), Some(1));
+        assert_eq!(builder.layer("c"), None);
+        assert_eq!(builder.layers[2].get_pixel(0, 0)[0], 3);
+    }
+
+    #[test]
+    fn layers_need_the_same_size() {
+        let mut builder = ArrayBuilder::new();
+        builder.add("a", solid(16, 16, 1)).unwrap();
+        assert!(builder.add("b", solid(16, 8, 1)).is_err());
+        assert_eq!(builder.layer("b"), None);
+        assert_eq!(builder.layers.len(), 1);
+    }
+}
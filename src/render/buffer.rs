@@ -46,4 +46,22 @@ pub fn create_uniform_bind_group(
     });
 
     (bind_group, layout)
-}
\ No newline at end of file
+}
+
+/// Like `create_uniform_bind_group_layout`, but the buffer is bound with a
+/// dynamic offset, see `upload::UniformArena`
+pub fn create_dynamic_uniform_bind_group_layout(
+    device: &wgpu::Device,
+    visibility: wgpu::ShaderStage,
+    binding: u32,
+    label: &str,
+) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        bindings: &[wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::UniformBuffer { dynamic: true },
+        }],
+        label: Some(label),
+    })
+}
@@ -0,0 +1,253 @@
+//! Drawing many copies of one mesh in a single call.
+//!
+//! Every copy gets a model matrix, a color and the rectangle of a texture
+//! atlas its texture coordinates are mapped into from a second vertex buffer
+//! stepped per instance, at the shader locations after the mesh's vertex
+//! attributes (see `shader_instanced.vert`). The buffer grows to the next
+//! power of two when more instances are uploaded than it can hold.
+use crate::geometry::mesh::GpuMesh;
+use crate::geometry::transform::Transform;
+use crate::render::atlas::UvRect;
+use crate::render::upload::StagingBelt;
+
+/// Per instance data as the shader reads it
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+    /// `min` and `max` of the `UvRect`
+    pub uv_rect: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for InstanceRaw {}
+unsafe impl bytemuck::Zeroable for InstanceRaw {}
+
+/// First shader location of the instance attributes, the model matrix takes
+/// four of them, the color and the atlas rectangle follow
+pub const INSTANCE_LOCATION: u32 = 3;
+
+impl InstanceRaw {
+    pub fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        use std::mem;
+        const VEC4: wgpu::BufferAddress = mem::size_of::<[f32; 4]>() as wgpu::BufferAddress;
+        wgpu::VertexBufferDescriptor {
+            stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: INSTANCE_LOCATION,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: VEC4,
+                    shader_location: INSTANCE_LOCATION + 1,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: 2 * VEC4,
+                    shader_location: INSTANCE_LOCATION + 2,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: 3 * VEC4,
+                    shader_location: INSTANCE_LOCATION + 3,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: 4 * VEC4,
+                    shader_location: INSTANCE_LOCATION + 4,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: 5 * VEC4,
+                    shader_location: INSTANCE_LOCATION + 5,
+                    format: wgpu::VertexFormat::Float4,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Instance {
+    pub model: uv::Mat4,
+    pub color: [f32; 4],
+    /// The whole texture unless the mesh is textured from an atlas
+    pub uv_rect: UvRect,
+}
+
+impl Instance {
+    pub fn new(model: uv::Mat4) -> Self {
+        Self {
+            model,
+            color: [1.0; 4],
+            uv_rect: UvRect {
+                min: [0.0; 2],
+                max: [1.0; 2],
+            },
+        }
+    }
+
+    pub fn from_transform(transform: &Transform) -> Self {
+        Self::new(transform.to_matrix())
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_uv_rect(mut self, uv_rect: UvRect) -> Self {
+        self.uv_rect = uv_rect;
+        self
+    }
+
+    pub fn to_raw(self) -> InstanceRaw {
+        let cols = self.model.cols;
+        InstanceRaw {
+            model: [
+                [cols[0].x, cols[0].y, cols[0].z, cols[0].w],
+                [cols[1].x, cols[1].y, cols[1].z, cols[1].w],
+                [cols[2].x, cols[2].y, cols[2].z, cols[2].w],
+                [cols[3].x, cols[3].y, cols[3].z, cols[3].w],
+            ],
+            color: self.color,
+            uv_rect: [
+                self.uv_rect.min[0],
+                self.uv_rect.min[1],
+                self.uv_rect.max[0],
+                self.uv_rect.max[1],
+            ],
+        }
+    }
+}
+
+/// Capacity of a buffer that has to hold `len` instances
+fn grown_capacity(len: usize) -> usize {
+    len.max(1).next_power_of_two()
+}
+
+/// Vertex buffer holding `instances`, padded with zeros up to `capacity`
+fn create_filled_buffer(
+    device: &wgpu::Device,
+    instances: &[InstanceRaw],
+    capacity: usize,
+) -> wgpu::Buffer {
+    let mut data = instances.to_vec();
+    data.resize(
+        capacity,
+        InstanceRaw {
+            model: [[0.0; 4]; 4],
+            color: [0.0; 4],
+            uv_rect: [0.0; 4],
+        },
+    );
+    device.create_buffer_with_data(
+        bytemuck::cast_slice(&data),
+        wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    )
+}
+
+/// GPU buffer of instances, bound to vertex buffer slot 1
+pub struct InstanceBuffer {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    len: usize,
+}
+
+impl InstanceBuffer {
+    /// Buffer filled with `instances` on creation
+    pub fn with_instances(device: &wgpu::Device, instances: &[Instance]) -> Self {
+        let raw: Vec<InstanceRaw> = instances.iter().map(|instance| instance.to_raw()).collect();
+        let capacity = grown_capacity(raw.len());
+
+        Self {
+            buffer: create_filled_buffer(device, &raw, capacity),
+            capacity,
+            len: raw.len(),
+        }
+    }
+
+    /// Replaces the instances. Records a write into the buffer if they fit,
+    /// creates a bigger buffer otherwise.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+        instances: &[Instance],
+    ) {
+        let raw: Vec<InstanceRaw> = instances.iter().map(|instance| instance.to_raw()).collect();
+        self.update_raw(device, encoder, staging_belt, &raw);
+    }
+
+    pub fn update_raw(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+        instances: &[InstanceRaw],
+    ) {
+        self.len = instances.len();
+        if instances.is_empty() {
+            return;
+        }
+
+        if instances.len() > self.capacity {
+            self.capacity = grown_capacity(instances.len());
+            self.buffer = create_filled_buffer(device, instances, self.capacity);
+            return;
+        }
+
+        staging_belt.write_buffer(
+            device,
+            encoder,
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(instances),
+        );
+    }
+
+    /// Draws every instance of `mesh` with whatever instanced pipeline and
+    /// bind groups are set
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, mesh: &'a GpuMesh) {
+        if self.len == 0 {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, &mesh.vertex_buffer, 0, 0);
+        render_pass.set_vertex_buffer(1, &self.buffer, 0, 0);
+        render_pass.set_index_buffer(&mesh.index_buffer, 0, 0);
+        render_pass.draw_indexed(0..mesh.num_indices, 0, 0..self.len as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_instances_match_the_attributes() {
+        let desc = InstanceRaw::desc();
+        assert_eq!(desc.stride, std::mem::size_of::<InstanceRaw>() as u64);
+        assert_eq!(desc.attributes.len(), 6);
+        assert_eq!(desc.attributes[4].offset, 64);
+        assert_eq!(desc.attributes[5].offset, 80);
+        assert_eq!(desc.attributes[5].shader_location, INSTANCE_LOCATION + 5);
+
+        let model = uv::Mat4::from_translation(uv::Vec3::new(1.0, 2.0, 3.0));
+        let raw = Instance::new(model).with_color([0.5; 4]).to_raw();
+        assert_eq!(raw.model[0], [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(raw.model[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(raw.color, [0.5; 4]);
+        assert_eq!(raw.uv_rect, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn buffers_grow_to_powers_of_two() {
+        assert_eq!(grown_capacity(0), 1);
+        assert_eq!(grown_capacity(512), 512);
+        assert_eq!(grown_capacity(513), 1024);
+    }
+}
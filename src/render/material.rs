@@ -0,0 +1,560 @@
+//! Materials: a shader pair plus typed parameters.
+//!
+//! A `MaterialShader` compiles the shaders and declares their parameters,
+//! which fixes the layout of bind group 0:
+//!
+//! - colors and scalars, if there are any, are one uniform block at binding 0,
+//!   in declaration order with std140 layout (`vec4` and `float`)
+//! - every texture follows as a `texture2D` and a `sampler` binding
+//!
+//! Bind group 1 is the camera uniform block of `tools::uniforms::Uniforms`,
+//! the same for every material. Pipelines for single meshes take the mesh's
+//! `tools::uniforms::ModelUniforms` as bind group 2, instanced pipelines
+//! read them from the instances. Many `Material`s can share a shader, each
+//! with its own values. Changing colors and scalars only uploads the uniform
+//! block, changing a texture rebuilds the bind group.
+//!
+//! `PipelineCache` builds the render pipeline of a shader once per vertex type
+//! and render target format, with or without per instance data.
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use glsl_to_spirv::ShaderType;
+
+use crate::render::instance::InstanceRaw;
+use crate::render::texture::{ColorSpace, Texture};
+use crate::render::upload::StagingBelt;
+use crate::render::{buffer, pipeline, shader, texture};
+use crate::tools::uniforms::Uniforms;
+use crate::types::VertexLayout;
+
+static NEXT_SHADER_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    /// `vec4` in the uniform block, white by default
+    Color,
+    /// `float` in the uniform block, 0 by default
+    Scalar,
+    /// `texture2D` and `sampler`, a white texel by default
+    Texture,
+}
+
+#[derive(Clone)]
+enum ParamValue {
+    Color([f32; 4]),
+    Scalar(f32),
+    Texture(Rc<Texture>),
+}
+
+struct Param {
+    name: String,
+    kind: ParamKind,
+    /// Offset in the uniform block for colors and scalars, binding of the
+    /// texture for textures
+    slot: u32,
+}
+
+pub struct MaterialShader {
+    id: usize,
+    name: String,
+    vs_module: wgpu::ShaderModule,
+    fs_module: wgpu::ShaderModule,
+    params: Vec<Param>,
+    /// Size of the uniform block, 0 without colors and scalars
+    uniform_size: u32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// White texel for textures that weren't set
+    white: Rc<Texture>,
+}
+
+impl MaterialShader {
+    /// Compiles the GLSL sources and lays out `params`
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: &str,
+        vertex_source: &str,
+        fragment_source: &str,
+        params: &[(&str, ParamKind)],
+    ) -> Self {
+        let vs_module = shader::create_shader_module(vertex_source, ShaderType::Vertex, device);
+        let fs_module = shader::create_shader_module(fragment_source, ShaderType::Fragment, device);
+
+        let (params, uniform_size) = layout_params(params);
+        let bind_group_layout = if is_lone_texture(&params) {
+            pipeline::default_texture_bind_group_layout(device, name)
+        } else {
+            create_bind_group_layout(device, name, &params, uniform_size)
+        };
+
+        let (white, cmd_buffer) = Texture::from_rgba(
+            device,
+            &image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])),
+            "material_white_texture",
+            ColorSpace::Srgb,
+        );
+        queue.submit(&[cmd_buffer]);
+
+        Self {
+            id: NEXT_SHADER_ID.fetch_add(1, Ordering::Relaxed),
+            name: name.to_string(),
+            vs_module,
+            fs_module,
+            params,
+            uniform_size,
+            bind_group_layout,
+            white: Rc::new(white),
+        }
+    }
+
+    fn param(&self, name: &str, kind: ParamKind) -> (usize, &Param) {
+        self.params
+            .iter()
+            .enumerate()
+            .find(|(_, param)| param.name == name && param.kind == kind)
+            .unwrap_or_else(|| {
+                panic!(
+                    "material shader {} has no {:?} parameter {}",
+                    self.name, kind, name
+                )
+            })
+    }
+}
+
+/// Uniform block offsets and texture bindings of the parameters, and the size
+/// of the uniform block
+fn layout_params(params: &[(&str, ParamKind)]) -> (Vec<Param>, u32) {
+    let mut offset = 0u32;
+    let has_uniforms = params.iter().any(|&(_, kind)| kind != ParamKind::Texture);
+    let mut binding = if has_uniforms { 1 } else { 0 };
+
+    let params = params
+        .iter()
+        .map(|&(name, kind)| {
+            let slot = match kind {
+                ParamKind::Color => {
+                    offset = offset.next_multiple_of(16);
+                    offset += 16;
+                    offset - 16
+                }
+                ParamKind::Scalar => {
+                    offset += 4;
+                    offset - 4
+                }
+                ParamKind::Texture => {
+                    binding += 2;
+                    binding - 2
+                }
+            };
+            Param {
+                name: name.to_string(),
+                kind,
+                slot,
+            }
+        })
+        .collect();
+
+    (params, offset.next_multiple_of(16))
+}
+
+/// A single texture and nothing else is laid out like `shader_tex.frag` and
+/// the bind groups of `pipeline::default_texture_bind_group_layout`
+fn is_lone_texture(params: &[Param]) -> bool {
+    match params {
+        [param] => param.kind == ParamKind::Texture,
+        _ => false,
+    }
+}
+
+fn create_bind_group_layout(
+    device: &wgpu::Device,
+    name: &str,
+    params: &[Param],
+    uniform_size: u32,
+) -> wgpu::BindGroupLayout {
+    let mut entries = Vec::new();
+    if uniform_size > 0 {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+        });
+    }
+    for param in params.iter().filter(|p| p.kind == ParamKind::Texture) {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: param.slot,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: param.slot + 1,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler { comparison: false },
+        });
+    }
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        bindings: &entries,
+        label: Some(name),
+    })
+}
+
+/// Parameter values of a `MaterialShader`, set as bind group 0
+pub struct Material {
+    shader: Rc<MaterialShader>,
+    values: Vec<ParamValue>,
+    uniform_buffer: Option<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    uniforms_dirty: bool,
+    bind_group_dirty: bool,
+}
+
+impl Material {
+    pub fn new(device: &wgpu::Device, shader: &Rc<MaterialShader>) -> Self {
+        let values: Vec<ParamValue> = shader
+            .params
+            .iter()
+            .map(|param| match param.kind {
+                ParamKind::Color => ParamValue::Color([1.0; 4]),
+                ParamKind::Scalar => ParamValue::Scalar(0.0),
+                ParamKind::Texture => ParamValue::Texture(Rc::clone(&shader.white)),
+            })
+            .collect();
+
+        let uniform_buffer = if shader.uniform_size > 0 {
+            Some(device.create_buffer_with_data(
+                &uniform_bytes(shader, &values),
+                wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            ))
+        } else {
+            None
+        };
+        let bind_group = create_bind_group(device, shader, &values, uniform_buffer.as_ref());
+
+        Self {
+            shader: Rc::clone(shader),
+            values,
+            uniform_buffer,
+            bind_group,
+            uniforms_dirty: false,
+            bind_group_dirty: false,
+        }
+    }
+
+    pub fn shader(&self) -> &Rc<MaterialShader> {
+        &self.shader
+    }
+
+    /// Panics if the shader has no color parameter `name`, like the other
+    /// setters
+    pub fn set_color(&mut self, name: &str, color: [f32; 4]) {
+        let (index, _) = self.shader.param(name, ParamKind::Color);
+        self.values[index] = ParamValue::Color(color);
+        self.uniforms_dirty = true;
+    }
+
+    pub fn set_scalar(&mut self, name: &str, value: f32) {
+        let (index, _) = self.shader.param(name, ParamKind::Scalar);
+        self.values[index] = ParamValue::Scalar(value);
+        self.uniforms_dirty = true;
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: Rc<Texture>) {
+        let (index, _) = self.shader.param(name, ParamKind::Texture);
+        self.values[index] = ParamValue::Texture(texture);
+        self.bind_group_dirty = true;
+    }
+
+    /// Records the upload of changed parameters, call it before drawing
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+    ) {
+        if self.uniforms_dirty {
+            if let Some(uniform_buffer) = &self.uniform_buffer {
+                staging_belt.write_buffer(
+                    device,
+                    encoder,
+                    uniform_buffer,
+                    0,
+                    &uniform_bytes(&self.shader, &self.values),
+                );
+            }
+            self.uniforms_dirty = false;
+        }
+        if self.bind_group_dirty {
+            self.bind_group = create_bind_group(
+                device,
+                &self.shader,
+                &self.values,
+                self.uniform_buffer.as_ref(),
+            );
+            self.bind_group_dirty = false;
+        }
+    }
+
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+    }
+}
+
+/// The uniform block with std140 layout
+fn uniform_bytes(shader: &MaterialShader, values: &[ParamValue]) -> Vec<u8> {
+    let mut bytes = vec![0; shader.uniform_size as usize];
+    for (param, value) in shader.params.iter().zip(values) {
+        let offset = param.slot as usize;
+        match value {
+            ParamValue::Color(color) => {
+                bytes[offset..offset + 16].copy_from_slice(bytemuck::cast_slice(color))
+            }
+            ParamValue::Scalar(value) => {
+                bytes[offset..offset + 4].copy_from_slice(bytemuck::bytes_of(value))
+            }
+            ParamValue::Texture(_) => {}
+        }
+    }
+    bytes
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    shader: &MaterialShader,
+    values: &[ParamValue],
+    uniform_buffer: Option<&wgpu::Buffer>,
+) -> wgpu::BindGroup {
+    if let [ParamValue::Texture(texture)] = values {
+        return pipeline::create_texture_bind_group(
+            device,
+            &shader.bind_group_layout,
+            texture,
+            &shader.name,
+        );
+    }
+
+    let mut bindings = Vec::new();
+    if let Some(buffer) = uniform_buffer {
+        bindings.push(wgpu::Binding {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer {
+                buffer,
+                range: 0..shader.uniform_size as wgpu::BufferAddress,
+            },
+        });
+    }
+    for (param, value) in shader.params.iter().zip(values) {
+        if let ParamValue::Texture(texture) = value {
+            bindings.push(wgpu::Binding {
+                binding: param.slot,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            bindings.push(wgpu::Binding {
+                binding: param.slot + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+    }
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &shader.bind_group_layout,
+        bindings: &bindings,
+        label: Some(&shader.name),
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    shader: usize,
+    vertex: TypeId,
+    instanced: bool,
+    format: wgpu::TextureFormat,
+}
+
+fn key<V: VertexLayout>(
+    shader: &MaterialShader,
+    format: wgpu::TextureFormat,
+    instanced: bool,
+) -> PipelineKey {
+    PipelineKey {
+        shader: shader.id,
+        vertex: TypeId::of::<V>(),
+        instanced,
+        format,
+    }
+}
+
+/// Render pipelines of material shaders, built on first use. They draw
+/// triangle lists with `Uint32` indices and a `texture::DEPTH_FORMAT` depth
+/// attachment.
+pub struct PipelineCache {
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+}
+
+impl PipelineCache {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            camera_bind_group_layout: buffer::create_uniform_bind_group_layout(
+                device,
+                wgpu::ShaderStage::VERTEX,
+                0,
+                "camera_bind_group_layout",
+            ),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Bind group 1 of every material pipeline, `buffer` holds `Uniforms`
+    pub fn create_camera_bind_group(
+        &self,
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.camera_bind_group_layout,
+            bindings: &[wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer,
+                    range: 0..std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+                },
+            }],
+            label: Some("camera_bind_group"),
+        })
+    }
+
+    /// Builds the pipeline of `shader` for single meshes with vertices of
+    /// type `V` drawn into `format` unless it exists already. Bind group 2
+    /// has the layout `objects`, e.g. the `UniformArena::layout` the meshes'
+    /// `ModelUniforms` come from. Pipelines are cached without it, so every
+    /// draw with them has to bind groups of that same layout.
+    pub fn prepare<V: VertexLayout>(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &MaterialShader,
+        format: wgpu::TextureFormat,
+        objects: &wgpu::BindGroupLayout,
+    ) {
+        self.build(
+            device,
+            shader,
+            format,
+            key::<V>(shader, format, false),
+            &[V::desc()],
+            Some(objects),
+        );
+    }
+
+    /// Like `prepare`, with `InstanceRaw`s in vertex buffer slot 1
+    pub fn prepare_instanced<V: VertexLayout>(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &MaterialShader,
+        format: wgpu::TextureFormat,
+    ) {
+        self.build(
+            device,
+            shader,
+            format,
+            key::<V>(shader, format, true),
+            &[V::desc(), InstanceRaw::desc()],
+            None,
+        );
+    }
+
+    fn build(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &MaterialShader,
+        format: wgpu::TextureFormat,
+        key: PipelineKey,
+        vertex_buffers: &[wgpu::VertexBufferDescriptor],
+        objects: Option<&wgpu::BindGroupLayout>,
+    ) {
+        if self.pipelines.contains_key(&key) {
+            return;
+        }
+
+        let mut bind_group_layouts =
+            vec![&shader.bind_group_layout, &self.camera_bind_group_layout];
+        bind_group_layouts.extend(objects);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &bind_group_layouts,
+        });
+        let render_pipeline = pipeline::create_render_pipeline(
+            device,
+            &layout,
+            wgpu::PrimitiveTopology::TriangleList,
+            &shader.vs_module,
+            &shader.fs_module,
+            format,
+            texture::DEPTH_FORMAT,
+            vertex_buffers,
+            wgpu::IndexFormat::Uint32,
+            false,
+            "main",
+        );
+        self.pipelines.insert(key, render_pipeline);
+    }
+
+    /// Pipeline built by `prepare`
+    pub fn get<V: VertexLayout>(
+        &self,
+        shader: &MaterialShader,
+        format: wgpu::TextureFormat,
+    ) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(&key::<V>(shader, format, false))
+    }
+
+    /// Pipeline built by `prepare_instanced`
+    pub fn get_instanced<V: VertexLayout>(
+        &self,
+        shader: &MaterialShader,
+        format: wgpu::TextureFormat,
+    ) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(&key::<V>(shader, format, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots(params: &[Param]) -> Vec<u32> {
+        params.iter().map(|param| param.slot).collect()
+    }
+
+    #[test]
+    fn uniforms_follow_std140_and_textures_follow_the_block() {
+        let (params, size) = layout_params(&[
+            ("a", ParamKind::Scalar),
+            ("b", ParamKind::Color),
+            ("t", ParamKind::Texture),
+            ("c", ParamKind::Scalar),
+            ("d", ParamKind::Scalar),
+            ("u", ParamKind::Texture),
+        ]);
+        // the color skips to the next vec4, textures take two bindings each
+        assert_eq!(slots(&params), vec![0, 16, 1, 32, 36, 3]);
+        assert_eq!(size, 48);
+        assert!(!is_lone_texture(&params));
+    }
+
+    #[test]
+    fn a_lone_texture_uses_the_default_texture_layout() {
+        let (params, size) = layout_params(&[("diffuse", ParamKind::Texture)]);
+        assert_eq!(slots(&params), vec![0]);
+        assert_eq!(size, 0);
+        assert!(is_lone_texture(&params));
+
+        let (params, _) = layout_params(&[("tint", ParamKind::Color)]);
+        assert!(!is_lone_texture(&params));
+    }
+}
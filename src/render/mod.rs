@@ -1,4 +1,8 @@
+pub mod atlas;
 pub mod buffer;
+pub mod instance;
+pub mod material;
 pub mod pipeline;
 pub mod shader;
 pub mod texture;
+pub mod upload;
@@ -1,3 +1,5 @@
+use crate::render::texture::Texture;
+
 pub fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -7,6 +9,7 @@ pub fn create_render_pipeline(
     color_format: wgpu::TextureFormat,
     depth_format: wgpu::TextureFormat,
     vertex_buffers: &[wgpu::VertexBufferDescriptor],
+    index_format: wgpu::IndexFormat,
     alpha: bool,
     entry_point: &str,
 ) -> wgpu::RenderPipeline {
@@ -44,7 +47,7 @@ pub fn create_render_pipeline(
             stencil_write_mask: 0,
         }),
         vertex_state: wgpu::VertexStateDescriptor {
-            index_format: wgpu::IndexFormat::Uint16,
+            index_format,
             vertex_buffers,
         },
         sample_count: 1,
@@ -61,6 +64,7 @@ pub fn create_render_pipeline_no_depth(
     fs_module: &wgpu::ShaderModule,
     color_format: wgpu::TextureFormat,
     vertex_buffers: &[wgpu::VertexBufferDescriptor],
+    index_format: wgpu::IndexFormat,
     alpha: bool,
     entry_point: &str,
 ) -> wgpu::RenderPipeline {
@@ -90,7 +94,7 @@ pub fn create_render_pipeline_no_depth(
         primitive_topology: primitive,
         depth_stencil_state: None,
         vertex_state: wgpu::VertexStateDescriptor {
-            index_format: wgpu::IndexFormat::Uint16,
+            index_format,
             vertex_buffers,
         },
         sample_count: 1,
@@ -102,6 +106,16 @@ pub fn create_render_pipeline_no_depth(
 pub fn default_texture_bind_group_layout(
     device: &wgpu::Device,
     label: &str,
+) -> wgpu::BindGroupLayout {
+    texture_bind_group_layout(device, wgpu::TextureViewDimension::D2, label)
+}
+
+/// Texture and sampler for the fragment stage, e.g. with
+/// `wgpu::TextureViewDimension::D2Array` for texture arrays
+pub fn texture_bind_group_layout(
+    device: &wgpu::Device,
+    dimension: wgpu::TextureViewDimension,
+    label: &str,
 ) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         bindings: &[
@@ -110,8 +124,8 @@ pub fn default_texture_bind_group_layout(
                 wgpu::ShaderStage::FRAGMENT,
                 wgpu::BindingType::SampledTexture {
                     multisampled: false,
-                    dimension: wgpu::TextureViewDimension::D2,
-                    component_type: wgpu::TextureComponentType::Uint,
+                    dimension,
+                    component_type: wgpu::TextureComponentType::Float,
                 },
             ),
             create_bind_group_layout_entry(
@@ -124,6 +138,29 @@ pub fn default_texture_bind_group_layout(
     })
 }
 
+/// Bind group for `default_texture_bind_group_layout` and `texture_bind_group_layout`
+pub fn create_texture_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    texture: &Texture,
+    label: &str,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        bindings: &[
+            wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::Binding {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+        label: Some(label),
+    })
+}
+
 fn create_bind_group_layout_entry(
     binding: u32,
     visibility: wgpu::ShaderStage,
@@ -1,5 +1,72 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::loader::error::LoadError;
+
 pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+/// Rows of buffer to texture copies have to start at multiples of this
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// How the texels of a texture are meant to be read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors like albedo or emission, converted from sRGB when sampled
+    Srgb,
+    /// Data like normal, roughness or height maps, sampled as stored
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// Filtering of color textures
+///
+/// There is no anisotropic preset. `wgpu::SamplerDescriptor` in wgpu 0.5 has
+/// no anisotropy field and wgpu-core ignores `Extensions::anisotropic_filtering`,
+/// so it would sample exactly like `Trilinear`. Add it once wgpu can set the
+/// anisotropy of a sampler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerPreset {
+    /// Sharp texels up close and far away, for pixel art
+    Nearest,
+    /// Smooth texels and blends between mip levels
+    Trilinear,
+}
+
+impl SamplerPreset {
+    /// Clamped to the edge, which atlases need
+    pub fn descriptor(self) -> wgpu::SamplerDescriptor {
+        self.with_address_mode(wgpu::AddressMode::ClampToEdge)
+    }
+
+    /// E.g. `wgpu::AddressMode::Repeat` for tiling textures
+    pub fn with_address_mode(self, address_mode: wgpu::AddressMode) -> wgpu::SamplerDescriptor {
+        use wgpu::FilterMode::{Linear, Nearest};
+
+        let (mag_filter, min_filter, mipmap_filter) = match self {
+            SamplerPreset::Nearest => (Nearest, Nearest, Nearest),
+            SamplerPreset::Trilinear => (Linear, Linear, Linear),
+        };
+
+        create_sampler_descriptor(
+            address_mode,
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            -100.0,
+            100.0,
+            wgpu::CompareFunction::Undefined,
+        )
+    }
+}
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -7,10 +74,50 @@ pub struct Texture {
 }
 
 impl Texture {
-    pub fn new(device: &wgpu::Device, path: &str, label: &str) -> (Self, wgpu::CommandBuffer) {
-        let image = image::open(path).unwrap();
-        let rgba_image = image.as_rgba8().unwrap();
+    /// Loads an image file in any format the `image` crate can decode
+    pub fn new(
+        device: &wgpu::Device,
+        path: impl AsRef<Path>,
+        label: &str,
+        space: ColorSpace,
+    ) -> Result<(Self, wgpu::CommandBuffer), LoadError> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| LoadError::from(e).in_file(path))?;
+
+        Ok(Self::from_image(device, &image, label, space))
+    }
+
+    /// Decodes an image file that is already in memory, e.g. from `include_bytes!`
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        bytes: &[u8],
+        label: &str,
+        space: ColorSpace,
+    ) -> Result<(Self, wgpu::CommandBuffer), LoadError> {
+        let image = image::load_from_memory(bytes)?;
+
+        Ok(Self::from_image(device, &image, label, space))
+    }
+
+    /// Converts grey, RGB and 16 bit images to RGBA8 first
+    pub fn from_image(
+        device: &wgpu::Device,
+        image: &image::DynamicImage,
+        label: &str,
+        space: ColorSpace,
+    ) -> (Self, wgpu::CommandBuffer) {
+        match image.as_rgba8() {
+            Some(rgba_image) => Self::from_rgba(device, rgba_image, label, space),
+            None => Self::from_rgba(device, &image.to_rgba(), label, space),
+        }
+    }
 
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        rgba_image: &image::RgbaImage,
+        label: &str,
+        space: ColorSpace,
+    ) -> (Self, wgpu::CommandBuffer) {
         let image_dim = rgba_image.dimensions();
 
         let size = create_size(image_dim, 1);
@@ -19,30 +126,87 @@ impl Texture {
             label,
             size,
             1,
-            1,
+            mip_level_count(image_dim),
             1,
             wgpu::TextureDimension::D2,
-            wgpu::TextureFormat::Rgba8UnormSrgb,
+            space.format(),
             wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
         ));
 
-        let buffer = device.create_buffer_with_data(&rgba_image, wgpu::BufferUsage::COPY_SRC);
-
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("texture_buffer_copy_encoder"),
         });
 
-        encoder.copy_buffer_to_texture(
-            buffer_copy_view(&buffer, 0, 4 * image_dim.0, image_dim.1),
-            texture_copy_view(&texture, 0, 0, wgpu::Origin3d::ZERO),
-            size,
-        );
+        copy_mip_chain(device, &mut encoder, &texture, 0, rgba_image, space);
 
         let cmd_buffer = encoder.finish();
 
         let view = texture.create_default_view();
 
-        let sampler = device.create_sampler(&default_sampler_descriptor());
+        let sampler = device.create_sampler(&SamplerPreset::Trilinear.descriptor());
+
+        (
+            Self {
+                texture,
+                view,
+                sampler,
+            },
+            cmd_buffer,
+        )
+    }
+
+    /// 2D texture array with one layer per image, all images need the same size
+    pub fn array_from_rgba(
+        device: &wgpu::Device,
+        layers: &[image::RgbaImage],
+        label: &str,
+        space: ColorSpace,
+    ) -> (Self, wgpu::CommandBuffer) {
+        let image_dim = layers[0].dimensions();
+        let layer_count = layers.len() as u32;
+        let mip_levels = mip_level_count(image_dim);
+
+        let size = create_size(image_dim, 1);
+
+        let texture = device.create_texture(&create_texture_descriptor(
+            label,
+            size,
+            layer_count,
+            mip_levels,
+            1,
+            wgpu::TextureDimension::D2,
+            space.format(),
+            wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        ));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("texture_array_buffer_copy_encoder"),
+        });
+
+        for (layer, rgba_image) in layers.iter().enumerate() {
+            copy_mip_chain(
+                device,
+                &mut encoder,
+                &texture,
+                layer as u32,
+                rgba_image,
+                space,
+            );
+        }
+
+        let cmd_buffer = encoder.finish();
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            format: space.format(),
+            dimension: wgpu::TextureViewDimension::D2Array,
+            aspect: wgpu::TextureAspect::All,
+            base_mip_level: 0,
+            level_count: mip_levels,
+            base_array_layer: 0,
+            array_layer_count: layer_count,
+        });
+
+        let sampler = device.create_sampler(&SamplerPreset::Trilinear.descriptor());
 
         (
             Self {
@@ -54,6 +218,12 @@ impl Texture {
         )
     }
 
+    /// Replaces the sampler, textures are created with `SamplerPreset::Trilinear`
+    pub fn with_sampler(mut self, device: &wgpu::Device, desc: &wgpu::SamplerDescriptor) -> Self {
+        self.sampler = device.create_sampler(desc);
+        self
+    }
+
     pub fn new_depth(
         device: &wgpu::Device,
         sc_desc: &wgpu::SwapChainDescriptor,
@@ -86,6 +256,122 @@ impl Texture {
     }
 }
 
+/// Levels of a full mip chain down to 1x1
+pub fn mip_level_count(size: (u32, u32)) -> u32 {
+    32 - size.0.max(size.1).max(1).leading_zeros()
+}
+
+/// Halves an image with a box filter, averaging colors in linear space for
+/// sRGB images. Odd edges fold their last texel into the one before.
+pub fn downsample(rgba_image: &image::RgbaImage, space: ColorSpace) -> image::RgbaImage {
+    let (width, height) = rgba_image.dimensions();
+    let to_linear: Vec<f32> = (0..=255u8)
+        .map(|value| match space {
+            ColorSpace::Srgb => srgb_to_linear(value),
+            ColorSpace::Linear => value as f32 / 255.0,
+        })
+        .collect();
+
+    let half = ((width / 2).max(1), (height / 2).max(1));
+    image::RgbaImage::from_fn(half.0, half.1, |x, y| {
+        // the last texel of a level with an odd size takes the remaining one
+        let xs = 2 * x..if x == half.0 - 1 { width } else { 2 * x + 2 };
+        let ys = 2 * y..if y == half.1 - 1 { height } else { 2 * y + 2 };
+
+        let mut sum = [0.0f32; 4];
+        let mut count = 0.0;
+        for sy in ys {
+            for sx in xs.clone() {
+                let texel = rgba_image.get_pixel(sx, sy);
+                for c in 0..3 {
+                    sum[c] += to_linear[texel[c] as usize];
+                }
+                sum[3] += texel[3] as f32 / 255.0;
+                count += 1.0;
+            }
+        }
+
+        let mut texel = image::Rgba([0; 4]);
+        for c in 0..4 {
+            let value = sum[c] / count;
+            let value = match space {
+                ColorSpace::Srgb if c < 3 => linear_to_srgb(value),
+                _ => value,
+            };
+            texel[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        texel
+    })
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Queues copies of an image and all of its mip levels into `array_layer` of
+/// `texture`
+fn copy_mip_chain(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    array_layer: u32,
+    rgba_image: &image::RgbaImage,
+    space: ColorSpace,
+) {
+    let mip_levels = mip_level_count(rgba_image.dimensions());
+    let mut level = Cow::Borrowed(rgba_image);
+    for mip_level in 0..mip_levels {
+        if mip_level > 0 {
+            level = Cow::Owned(downsample(&level, space));
+        }
+        let (width, height) = level.dimensions();
+
+        let data = pad_rows(&level);
+        let buffer = device.create_buffer_with_data(&data, wgpu::BufferUsage::COPY_SRC);
+
+        encoder.copy_buffer_to_texture(
+            buffer_copy_view(&buffer, 0, padded_bytes_per_row(width), height),
+            texture_copy_view(texture, mip_level, array_layer, wgpu::Origin3d::ZERO),
+            create_size((width, height), 1),
+        );
+    }
+}
+
+/// Bytes per row of a copy of `width` RGBA8 texels, rounded up to the alignment
+/// wgpu needs
+fn padded_bytes_per_row(width: u32) -> u32 {
+    (4 * width).next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+/// The texels of an image with every row padded to `padded_bytes_per_row`
+fn pad_rows(rgba_image: &image::RgbaImage) -> Cow<'_, [u8]> {
+    let (width, height) = rgba_image.dimensions();
+    let unpadded = 4 * width as usize;
+    let padded = padded_bytes_per_row(width) as usize;
+    if padded == unpadded || height == 0 {
+        return Cow::Borrowed(&**rgba_image);
+    }
+
+    let mut data = vec![0; padded * height as usize];
+    for (row, padded_row) in rgba_image.chunks(unpadded).zip(data.chunks_mut(padded)) {
+        padded_row[..unpadded].copy_from_slice(row);
+    }
+    Cow::Owned(data)
+}
+
 fn create_size(size: (u32, u32), depth: u32) -> wgpu::Extent3d {
     wgpu::Extent3d {
         width: size.0,
@@ -179,3 +465,64 @@ fn texture_copy_view(
         origin,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(1), 256);
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+
+        let image = image::RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 7, 9]));
+        let texels: &[u8] = &image;
+        let data = pad_rows(&image);
+        assert_eq!(data.len(), 2 * 256);
+        assert_eq!(&data[..12], &texels[..12]);
+        assert_eq!(&data[256..268], &texels[12..]);
+        assert!(data[12..256].iter().all(|&byte| byte == 0));
+
+        // rows that are aligned already aren't copied
+        let aligned = image::RgbaImage::new(64, 3);
+        assert!(matches!(pad_rows(&aligned), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn mip_chains_go_down_to_one_texel() {
+        assert_eq!(mip_level_count((0, 0)), 1);
+        assert_eq!(mip_level_count((1, 1)), 1);
+        assert_eq!(mip_level_count((256, 256)), 9);
+        assert_eq!(mip_level_count((300, 5)), 9);
+    }
+
+    #[test]
+    fn downsampling_averages_in_linear_space() {
+        let white = image::Rgba([255; 4]);
+        let clear = image::Rgba([0; 4]);
+        let stripes =
+            image::RgbaImage::from_fn(4, 4, |x, _| if x % 2 == 0 { white } else { clear });
+
+        let linear = downsample(&stripes, ColorSpace::Linear);
+        assert_eq!(linear.dimensions(), (2, 2));
+        assert_eq!(linear.get_pixel(0, 0).0, [128, 128, 128, 128]);
+
+        // half of white in linear light is a lot brighter than half of 255
+        let srgb = downsample(&stripes, ColorSpace::Srgb);
+        assert_eq!(srgb.get_pixel(0, 0).0, [188, 188, 188, 128]);
+
+        for value in 0..=255u8 {
+            let round_trip = linear_to_srgb(srgb_to_linear(value)) * 255.0;
+            assert_eq!(round_trip.round() as u8, value);
+        }
+    }
+
+    #[test]
+    fn odd_edges_fold_into_the_last_texel() {
+        let row = image::RgbaImage::from_fn(3, 1, |x, _| image::Rgba([x as u8 * 30, 0, 0, 255]));
+        let half = downsample(&row, ColorSpace::Linear);
+        assert_eq!(half.dimensions(), (1, 1));
+        assert_eq!(half.get_pixel(0, 0)[0], 30);
+    }
+}
@@ -0,0 +1,333 @@
+//! Getting data into GPU buffers without a new staging buffer per write.
+//!
+//! `StagingBelt` keeps a set of mappable chunks. A write lands in a chunk
+//! with room left and a copy into the target is recorded on the frame's
+//! encoder. If no chunk has room, a new one is created mapped with the
+//! write's data in it. Once the encoder is submitted the chunks are mapped
+//! again and come back as soon as the GPU is done reading them, so after the
+//! first few frames nothing gets allocated.
+//!
+//! `UniformArena` packs the uniforms of many objects into one buffer that is
+//! bound with a dynamic offset per object and uploaded with a single write.
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use wgpu::{BufferAddress, DynamicOffset};
+
+use crate::render::buffer;
+
+/// Buffer to buffer copies have to be 4 byte aligned
+const COPY_BUFFER_ALIGNMENT: BufferAddress = 4;
+
+type MapResult = Result<wgpu::BufferWriteMapping, wgpu::BufferAsyncErr>;
+
+/// Polls a mapping once, it only makes progress in `device.poll`
+fn poll_now(future: &mut Pin<Box<dyn Future<Output = MapResult>>>) -> Option<MapResult> {
+    let mut context = Context::from_waker(futures::task::noop_waker_ref());
+    match future.as_mut().poll(&mut context) {
+        Poll::Ready(result) => Some(result),
+        Poll::Pending => None,
+    }
+}
+
+struct Chunk {
+    // declared before the buffer so it is unmapped before the buffer goes
+    mapping: wgpu::BufferWriteMapping,
+    buffer: wgpu::Buffer,
+    size: BufferAddress,
+    offset: BufferAddress,
+}
+
+struct Recalled {
+    future: Pin<Box<dyn Future<Output = MapResult>>>,
+    buffer: wgpu::Buffer,
+    size: BufferAddress,
+}
+
+/// Ring of staging chunks, shared by everything that writes during a frame
+pub struct StagingBelt {
+    chunk_size: BufferAddress,
+    /// Mapped, written to this frame
+    active: Vec<Chunk>,
+    /// Unmapped, read by the copies of the current submission
+    closed: Vec<(wgpu::Buffer, BufferAddress)>,
+    /// Waiting for the GPU to let go of them
+    recalled: Vec<Recalled>,
+    /// Mapped and empty
+    free: Vec<Chunk>,
+}
+
+impl StagingBelt {
+    /// `chunk_size` is the size new chunks get, bigger writes get a chunk of
+    /// their own
+    pub fn new(chunk_size: BufferAddress) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1).next_multiple_of(COPY_BUFFER_ALIGNMENT),
+            active: Vec::new(),
+            closed: Vec::new(),
+            recalled: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Records a copy of `data` into `target` at `offset`. The size of `data`
+    /// has to be a multiple of 4.
+    pub fn write_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: BufferAddress,
+        data: &[u8],
+    ) {
+        let size = data.len() as BufferAddress;
+        assert_eq!(
+            size % COPY_BUFFER_ALIGNMENT,
+            0,
+            "buffer writes have to be a multiple of {} bytes",
+            COPY_BUFFER_ALIGNMENT
+        );
+        if size == 0 {
+            return;
+        }
+
+        let index = match self
+            .active
+            .iter()
+            .position(|chunk| chunk.offset + size <= chunk.size)
+        {
+            Some(index) => index,
+            None => match self.free.iter().position(|chunk| chunk.size >= size) {
+                Some(index) => {
+                    let chunk = self.free.swap_remove(index);
+                    self.active.push(chunk);
+                    self.active.len() - 1
+                }
+                None => {
+                    self.write_new_chunk(device, encoder, target, offset, data);
+                    return;
+                }
+            },
+        };
+
+        let chunk = &mut self.active[index];
+        let start = chunk.offset as usize;
+        chunk.mapping.as_slice()[start..start + data.len()].copy_from_slice(data);
+        encoder.copy_buffer_to_buffer(&chunk.buffer, chunk.offset, target, offset, size);
+        chunk.offset = (chunk.offset + size).next_multiple_of(COPY_BUFFER_ALIGNMENT);
+    }
+
+    /// Unmaps the chunks written this frame, call it before the encoder is
+    /// submitted
+    pub fn finish(&mut self) {
+        for chunk in self.active.drain(..) {
+            let Chunk {
+                mapping,
+                buffer,
+                size,
+                ..
+            } = chunk;
+            drop(mapping);
+            self.closed.push((buffer, size));
+        }
+    }
+
+    /// Maps the chunks of the last submission again, call it after the
+    /// encoder is submitted. Chunks the GPU still reads from come back in a
+    /// later frame.
+    pub fn recall(&mut self, device: &wgpu::Device) {
+        for (buffer, size) in self.closed.drain(..) {
+            let future = Box::pin(buffer.map_write(0, size));
+            self.recalled.push(Recalled {
+                future,
+                buffer,
+                size,
+            });
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut i = 0;
+        while i < self.recalled.len() {
+            match poll_now(&mut self.recalled[i].future) {
+                None => i += 1,
+                Some(result) => {
+                    let Recalled { buffer, size, .. } = self.recalled.swap_remove(i);
+                    let mapping = result.expect("failed to map a staging chunk");
+                    self.free.push(Chunk {
+                        mapping,
+                        buffer,
+                        size,
+                        offset: 0,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Number of chunks the belt owns, whatever state they are in
+    pub fn chunk_count(&self) -> usize {
+        self.active.len() + self.closed.len() + self.recalled.len() + self.free.len()
+    }
+
+    /// Records the copy of `data` from a new chunk. The chunk is only mapped
+    /// while it is created, so it takes no other writes this frame and joins
+    /// the others once it is recalled.
+    fn write_new_chunk(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: BufferAddress,
+        data: &[u8],
+    ) {
+        let size = data.len() as BufferAddress;
+        let chunk_size = size.max(self.chunk_size);
+        let mapped = device.create_buffer_mapped(&wgpu::BufferDescriptor {
+            label: Some("staging_chunk"),
+            size: chunk_size,
+            usage: wgpu::BufferUsage::MAP_WRITE | wgpu::BufferUsage::COPY_SRC,
+        });
+        mapped.data[..data.len()].copy_from_slice(data);
+        let buffer = mapped.finish();
+
+        encoder.copy_buffer_to_buffer(&buffer, 0, target, offset, size);
+        self.closed.push((buffer, chunk_size));
+    }
+}
+
+/// Per object uniforms of type `T` in one buffer. Push every object's value
+/// each frame, upload once and bind with the offset `push` returned.
+pub struct UniformArena<T> {
+    layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    binding: u32,
+    /// Size of one slot, offsets have to be multiples of
+    /// `wgpu::BIND_BUFFER_ALIGNMENT`
+    stride: BufferAddress,
+    capacity: usize,
+    data: Vec<u8>,
+    marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformArena<T> {
+    /// Arena with room for `capacity` values before it has to grow
+    pub fn new(
+        device: &wgpu::Device,
+        visibility: wgpu::ShaderStage,
+        binding: u32,
+        capacity: usize,
+    ) -> Self {
+        let stride = (std::mem::size_of::<T>() as BufferAddress)
+            .next_multiple_of(wgpu::BIND_BUFFER_ALIGNMENT);
+        let capacity = capacity.max(1).next_power_of_two();
+        let layout = buffer::create_dynamic_uniform_bind_group_layout(
+            device,
+            visibility,
+            binding,
+            "uniform_arena_layout",
+        );
+        let buffer = Self::create_buffer(device, stride, capacity);
+        let bind_group = Self::create_bind_group(device, &layout, binding, &buffer);
+
+        Self {
+            layout,
+            buffer,
+            bind_group,
+            binding,
+            stride,
+            capacity,
+            data: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Layout to build pipelines with, the binding is dynamic
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Values pushed since the last `clear`
+    fn len(&self) -> usize {
+        self.data.len() / self.stride as usize
+    }
+
+    /// Forgets last frame's values, offsets handed out before are reused
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Adds a value and returns the offset to bind it with
+    pub fn push(&mut self, value: T) -> DynamicOffset {
+        let offset = self.data.len();
+        self.data.extend_from_slice(bytemuck::bytes_of(&value));
+        self.data.resize(offset + self.stride as usize, 0);
+        offset as DynamicOffset
+    }
+
+    /// Writes every value pushed since the last `clear` in one go, the
+    /// buffer grows to the next power of two if they don't fit
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+    ) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        if self.len() > self.capacity {
+            self.capacity = self.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.stride, self.capacity);
+            self.bind_group =
+                Self::create_bind_group(device, &self.layout, self.binding, &self.buffer);
+        }
+
+        staging_belt.write_buffer(device, encoder, &self.buffer, 0, &self.data);
+    }
+
+    /// Binds the value that was pushed at `offset`
+    pub fn bind<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        index: u32,
+        offset: DynamicOffset,
+    ) {
+        render_pass.set_bind_group(index, &self.bind_group, &[offset]);
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        stride: BufferAddress,
+        capacity: usize,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("uniform_arena_buffer"),
+            size: stride * capacity as BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        binding: u32,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            bindings: &[wgpu::Binding {
+                binding,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer,
+                    range: 0..std::mem::size_of::<T>() as BufferAddress,
+                },
+            }],
+            label: Some("uniform_arena_bind_group"),
+        })
+    }
+}
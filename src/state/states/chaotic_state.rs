@@ -6,6 +6,7 @@ use crate::state::traits::Stateful;
 use crate::tools::camera;
 use crate::tools::uniforms;
 use crate::render::{buffer, texture, pipeline, shader};
+use crate::render::upload::StagingBelt;
 
 pub struct ChaoticState {
     vertex_buffer: wgpu::Buffer,
@@ -83,6 +84,7 @@ impl ChaoticState {
             sc_desc.format.clone(),
             texture::DEPTH_FORMAT,
             &[VertexC::desc()],
+            wgpu::IndexFormat::Uint16,
             true,
             "main",
         );
@@ -151,28 +153,22 @@ impl Stateful for ChaoticState {
         render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
     }
 
-    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+    ) {
         self.camera_controller.update(&mut self.camera);
         self.uniforms.update_view_proj(self.camera.to_matrix());
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Update Encoder"),
-        });
-
-        let staging_buffer = device.create_buffer_with_data(
-            bytemuck::cast_slice(&[self.uniforms]),
-            wgpu::BufferUsage::COPY_SRC,
-        );
-
-        encoder.copy_buffer_to_buffer(
-            &staging_buffer,
-            0,
+        staging_belt.write_buffer(
+            device,
+            encoder,
             &self.uniform_buffer,
             0,
-            std::mem::size_of::<uniforms::Uniforms>() as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[self.uniforms]),
         );
-
-        queue.submit(&[encoder.finish()]);
     }
 
     fn input(&mut self, input: &WinitInputHelper) -> bool {
@@ -1,3 +1,4 @@
+use crate::render::upload::StagingBelt;
 use crate::state::traits::Stateful;
 use std::any::Any;
 use wgpu::{Device, Queue, SwapChain, SwapChainDescriptor};
@@ -17,7 +18,12 @@ impl Stateful for MenuState {
         unimplemented!()
     }
 
-    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        _encoder: &mut wgpu::CommandEncoder,
+        _staging_belt: &mut StagingBelt,
+    ) {
         unimplemented!()
     }
 
@@ -2,3 +2,5 @@ pub mod chaotic_state;
 pub mod menu_state;
 pub mod none_sate;
 pub mod state_ids;
+pub mod textured_state;
+pub mod voxel_state;
@@ -1,3 +1,4 @@
+use crate::render::upload::StagingBelt;
 use crate::state::traits::Stateful;
 use std::any::Any;
 use winit::event::WindowEvent;
@@ -85,7 +86,12 @@ impl Stateful for NoneState {
         render_pass.draw(0..3, 0..1)
     }
 
-    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {}
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        _encoder: &mut wgpu::CommandEncoder,
+        _staging_belt: &mut StagingBelt,
+    ) {}
 
     fn input(&mut self, input: &WinitInputHelper) -> bool {
         false
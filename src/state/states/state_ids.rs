@@ -2,3 +2,5 @@ pub const NONE: usize = 0;
 pub const TEST: usize = 1;
 pub const MENU: usize = 2;
 pub const CHAOTIC: usize = 3;
+pub const TEXTURED: usize = 4;
+pub const VOXEL: usize = 5;
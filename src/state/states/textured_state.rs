@@ -0,0 +1,600 @@
+use std::any::Any;
+use std::path::Path;
+use std::rc::Rc;
+use wgpu::{Device, SwapChainDescriptor};
+use winit_input_helper::WinitInputHelper;
+
+use crate::geometry::mesh::GpuMesh;
+use crate::geometry::primitives;
+use crate::geometry::transform::Transform;
+use crate::loader::error::LoadError;
+use crate::loader::{gltf, obj};
+use crate::render::atlas::{AtlasBuilder, UvRect};
+use crate::render::instance::{Instance, InstanceBuffer};
+use crate::render::material::{Material, MaterialShader, ParamKind, PipelineCache};
+use crate::render::texture::{self, ColorSpace, SamplerPreset, Texture};
+use crate::render::upload::{StagingBelt, UniformArena};
+use crate::state::traits::Stateful;
+use crate::tools::camera;
+use crate::tools::uniforms::{self, ModelUniforms};
+use crate::types::{Vertex, VertexN};
+
+/// Cubes along each side of the floor, all drawn in one call
+const GRID_SIZE: i32 = 32;
+/// Radians the spinning cubes turn per update
+const SPIN_SPEED: f32 = 0.02;
+/// Texels around every atlas tile, keeps the first two mip levels clean
+const ATLAS_PADDING: u32 = 4;
+/// Side of the generated atlas tiles
+const TILE_SIZE: u32 = 16;
+/// Atlas tiles the spinning cubes and the props take turns with
+const TILES: [&str; 3] = ["checker", "stripes", "dots"];
+/// Distance of the props from the center of the floor
+const PROP_RING_RADIUS: f32 = 10.0;
+/// Height of the props' center above the floor's
+const PROP_HEIGHT: f32 = 3.5;
+/// How far the props bob up and down
+const BOB_HEIGHT: f32 = 0.5;
+/// Radians of the bobbing per update
+const BOB_SPEED: f32 = 0.03;
+/// Light the lit materials get where the sun doesn't reach
+const AMBIENT: f32 = 0.3;
+
+/// Pixel art tile of `TILE_SIZE` with `color` where `pattern` is true and a
+/// darker shade of it elsewhere
+fn tile(color: [u8; 3], pattern: impl Fn(u32, u32) -> bool) -> image::RgbaImage {
+    let dark = [color[0] / 3, color[1] / 3, color[2] / 3, 255];
+    let light = [color[0], color[1], color[2], 255];
+    image::RgbaImage::from_fn(TILE_SIZE, TILE_SIZE, |x, y| {
+        image::Rgba(if pattern(x, y) { light } else { dark })
+    })
+}
+
+struct Cube {
+    transform: Transform,
+    uv_rect: UvRect,
+}
+
+impl Cube {
+    fn instance(&self) -> Instance {
+        Instance::from_transform(&self.transform).with_uv_rect(self.uv_rect)
+    }
+}
+
+/// A lit primitive floating above the floor, drawn on its own
+struct Prop {
+    mesh: GpuMesh,
+    transform: Transform,
+    /// Where it is in its bobbing, in radians
+    phase: f32,
+    /// Of its `ModelUniforms` in this frame's arena
+    offset: wgpu::DynamicOffset,
+}
+
+/// A mesh of an imported model, where the file put it
+struct ImportedMesh {
+    mesh: GpuMesh,
+    transform: Transform,
+    /// Index into `TexturedState::imported_materials`
+    material: usize,
+    /// Of its `ModelUniforms` in this frame's arena
+    offset: wgpu::DynamicOffset,
+}
+
+/// Material of the lit shader, the texture defaults to white
+fn lit_material(
+    device: &wgpu::Device,
+    shader: &Rc<MaterialShader>,
+    tint: [f32; 4],
+    emissive: [f32; 3],
+    texture: Option<Rc<Texture>>,
+) -> Material {
+    let mut material = Material::new(device, shader);
+    material.set_color("tint", tint);
+    material.set_color("emissive", [emissive[0], emissive[1], emissive[2], 1.0]);
+    material.set_scalar("ambient", AMBIENT);
+    if let Some(texture) = texture {
+        material.set_texture("diffuse", texture);
+    }
+    material
+}
+
+/// A field of textured cubes with lit props above it, shows that materials,
+/// instancing, texture atlases and per object uniforms work
+pub struct TexturedState {
+    pipelines: PipelineCache,
+    /// The cat on the floor
+    floor_material: Material,
+    floor: InstanceBuffer,
+    /// Atlas tiles on the cubes above it
+    atlas_material: Material,
+    cubes: Vec<Cube>,
+    instances: InstanceBuffer,
+    cube: GpuMesh,
+    props_material: Material,
+    props: Vec<Prop>,
+    imported: Vec<ImportedMesh>,
+    imported_materials: Vec<Material>,
+    object_uniforms: UniformArena<ModelUniforms>,
+    depth_texture: texture::Texture,
+    format: wgpu::TextureFormat,
+
+    camera: camera::Camera,
+    camera_controller: camera::CameraController,
+
+    uniforms: uniforms::Uniforms,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+
+    size: winit::dpi::PhysicalSize<u32>,
+}
+
+impl TexturedState {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        size: &winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        // +y is down, like the voxel state
+        let mut camera = camera::Camera::new(sc_desc);
+        camera.set_rotation(camera::y_down());
+
+        let camera_controller = camera::CameraController::default();
+
+        let mut uniforms = uniforms::Uniforms::new();
+        uniforms.update_view_proj(camera.to_matrix());
+
+        let uniform_buffer = device.create_buffer_with_data(
+            bytemuck::cast_slice(&[uniforms]),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let mut pipelines = PipelineCache::new(device);
+        let uniform_bind_group = pipelines.create_camera_bind_group(device, &uniform_buffer);
+
+        let shader = Rc::new(MaterialShader::new(
+            device,
+            queue,
+            "textured_instanced",
+            include_str!("../../../assets/shaders/shader_instanced.vert"),
+            include_str!("../../../assets/shaders/shader_tex.frag"),
+            &[("diffuse", ParamKind::Texture)],
+        ));
+        pipelines.prepare_instanced::<Vertex>(device, &shader, sc_desc.format);
+
+        let (cat_texture, cmd_buffer) = texture::Texture::from_bytes(
+            device,
+            include_bytes!("../../../assets/images/cat.png"),
+            "cat_texture",
+            ColorSpace::Srgb,
+        )
+        .expect("assets/images/cat.png is broken");
+        queue.submit(&[cmd_buffer]);
+
+        let mut floor_material = Material::new(device, &shader);
+        floor_material.set_texture("diffuse", Rc::new(cat_texture));
+
+        let mut atlas_builder = AtlasBuilder::new(ATLAS_PADDING);
+        atlas_builder.add(
+            "checker",
+            tile([230, 120, 40], |x, y| (x / 4 + y / 4) % 2 == 0),
+        );
+        atlas_builder.add("stripes", tile([60, 170, 220], |x, y| (x + y) % 8 < 4));
+        atlas_builder.add(
+            "dots",
+            tile([120, 210, 90], |x, y| x % 4 != 0 || y % 4 != 0),
+        );
+        let atlas = atlas_builder
+            .build()
+            .expect("the generated tiles fit into an atlas");
+        let (atlas_texture, cmd_buffer) = atlas.upload(device, "atlas_texture", ColorSpace::Srgb);
+        queue.submit(&[cmd_buffer]);
+        let atlas_texture =
+            Rc::new(atlas_texture.with_sampler(device, &SamplerPreset::Nearest.descriptor()));
+
+        let mut atlas_material = Material::new(device, &shader);
+        atlas_material.set_texture("diffuse", Rc::clone(&atlas_texture));
+
+        let cube = primitives::cube(1.0).with_color([1.0; 4]).upload(device);
+
+        // a checkerboard of cubes below the camera, +y is down. The dark
+        // cells are the floor, the light ones are smaller cubes that spin
+        // in update.
+        let half = GRID_SIZE / 2;
+        let cells: Vec<(i32, i32)> = (-half..half)
+            .flat_map(|z| (-half..half).map(move |x| (x, z)))
+            .collect();
+        let position = |x: i32, z: i32| uv::Vec3::new(2.0 * x as f32, 2.0, 2.0 * z as f32);
+
+        let floor: Vec<Instance> = cells
+            .iter()
+            .filter(|&&(x, z)| (x + z) & 1 == 0)
+            .map(|&(x, z)| {
+                Instance::from_transform(&Transform::from_translation(position(x, z)))
+                    .with_color([0.6, 0.6, 0.6, 1.0])
+            })
+            .collect();
+        let floor = InstanceBuffer::with_instances(device, &floor);
+
+        let cubes: Vec<Cube> = cells
+            .iter()
+            .filter(|&&(x, z)| (x + z) & 1 == 1)
+            .map(|&(x, z)| {
+                let name = TILES[(x + 2 * z).rem_euclid(TILES.len() as i32) as usize];
+                Cube {
+                    transform: Transform::from_translation(position(x, z)).with_uniform_scale(0.6),
+                    uv_rect: atlas.uv_by_name(name).expect("every tile is in the atlas"),
+                }
+            })
+            .collect();
+        let grid: Vec<Instance> = cubes.iter().map(Cube::instance).collect();
+        let instances = InstanceBuffer::with_instances(device, &grid);
+
+        let props_shader = Rc::new(MaterialShader::new(
+            device,
+            queue,
+            "lit",
+            include_str!("../../../assets/shaders/shader_lit.vert"),
+            include_str!("../../../assets/shaders/shader_lit.frag"),
+            &[
+                ("tint", ParamKind::Color),
+                ("ambient", ParamKind::Scalar),
+                ("emissive", ParamKind::Color),
+                ("diffuse", ParamKind::Texture),
+            ],
+        ));
+        let props_material = lit_material(
+            device,
+            &props_shader,
+            [1.0, 0.95, 0.85, 1.0],
+            [0.0; 3],
+            Some(atlas_texture),
+        );
+
+        // the squashed and stretched ones only look right because their
+        // normals go through the normal matrix
+        let shapes = vec![
+            (
+                primitives::uv_sphere(1.0, 24, 16),
+                Transform::identity().with_scale(uv::Vec3::new(1.3, 0.7, 1.3)),
+            ),
+            (primitives::icosphere(1.0, 2), Transform::identity()),
+            (
+                primitives::torus(1.0, 0.35, 32, 12),
+                Transform::identity().with_rotation(uv::Rotor3::from_rotation_yz(0.6)),
+            ),
+            (primitives::cylinder(0.8, 2.0, 24), Transform::identity()),
+            (
+                primitives::cone(1.0, 2.0, 24),
+                Transform::identity().with_scale(uv::Vec3::new(0.6, 1.0, 1.4)),
+            ),
+            (
+                primitives::capsule(0.6, 1.2, 24, 8),
+                Transform::identity().with_rotation(uv::Rotor3::from_rotation_xy(0.8)),
+            ),
+            (primitives::plane(2.0, 2.0, 1, 1), Transform::identity()),
+        ];
+        let prop_count = shapes.len();
+        let props: Vec<Prop> = shapes
+            .into_iter()
+            .enumerate()
+            .map(|(i, (mesh, mut transform))| {
+                let uv_rect = atlas
+                    .uv_by_name(TILES[i % TILES.len()])
+                    .expect("every tile is in the atlas");
+                let mesh = mesh.map(|vertex| VertexN {
+                    tex_coords: uv_rect.map(vertex.tex_coords),
+                    ..vertex
+                });
+
+                // -y is up
+                let angle = 2.0 * std::f32::consts::PI * i as f32 / prop_count as f32;
+                transform.translate(uv::Vec3::new(
+                    PROP_RING_RADIUS * angle.cos(),
+                    2.0 - PROP_HEIGHT,
+                    PROP_RING_RADIUS * angle.sin(),
+                ));
+                Prop {
+                    mesh: mesh.upload(device),
+                    transform,
+                    phase: angle,
+                    offset: 0,
+                }
+            })
+            .collect();
+
+        let object_uniforms = UniformArena::new(device, wgpu::ShaderStage::VERTEX, 0, prop_count);
+        pipelines.prepare::<VertexN>(
+            device,
+            &props_shader,
+            sc_desc.format,
+            object_uniforms.layout(),
+        );
+
+        let depth_texture = texture::Texture::new_depth(device, sc_desc, "depth_texture");
+
+        Self {
+            pipelines,
+            floor_material,
+            floor,
+            atlas_material,
+            cubes,
+            instances,
+            cube,
+            props_material,
+            props,
+            imported: Vec::new(),
+            imported_materials: Vec::new(),
+            object_uniforms,
+            depth_texture,
+            format: sc_desc.format,
+            camera,
+            camera_controller,
+            uniforms,
+            uniform_bind_group,
+            uniform_buffer,
+            size: *size,
+        }
+    }
+
+    /// Adds the meshes of an .obj, .gltf or .glb file to the scene, where
+    /// the file placed them rather than on the ring of props. The camera
+    /// moves to the first camera of a glTF scene.
+    pub fn import(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        path: &Path,
+    ) -> Result<(), LoadError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("obj") => self.import_obj(device, queue, path),
+            Some("gltf") | Some("glb") => self.import_gltf(device, queue, sc_desc, path),
+            _ => Err(LoadError::format("unsupported model format").in_file(path)),
+        }
+    }
+
+    fn import_obj(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+    ) -> Result<(), LoadError> {
+        let model = obj::load(path)?;
+        let shader = Rc::clone(self.props_material.shader());
+
+        // texture coordinates of OBJ files often go past the edges
+        let sampler = SamplerPreset::Trilinear.with_address_mode(wgpu::AddressMode::Repeat);
+        let first = self.imported_materials.len();
+        for material in &model.materials {
+            // a missing texture shouldn't cost the whole model
+            let texture = material.diffuse_texture.as_ref().and_then(|file| {
+                match Texture::new(device, file, &material.name, ColorSpace::Srgb) {
+                    Ok((texture, cmd_buffer)) => {
+                        queue.submit(&[cmd_buffer]);
+                        Some(Rc::new(texture.with_sampler(device, &sampler)))
+                    }
+                    Err(e) => {
+                        eprintln!("warning: {}", e);
+                        None
+                    }
+                }
+            });
+            let [r, g, b] = material.diffuse;
+            self.imported_materials.push(lit_material(
+                device,
+                &shader,
+                [r, g, b, material.dissolve],
+                material.emissive,
+                texture,
+            ));
+        }
+        // for meshes before the first usemtl
+        let fallback = self.imported_materials.len();
+        self.imported_materials
+            .push(lit_material(device, &shader, [1.0; 4], [0.0; 3], None));
+
+        // OBJ is y-up, a half turn around x makes it y-down
+        let transform =
+            Transform::identity().with_rotation(uv::Rotor3::from_rotation_yz(std::f32::consts::PI));
+        for obj_mesh in model.meshes {
+            self.imported.push(ImportedMesh {
+                mesh: obj_mesh.mesh.upload(device),
+                transform,
+                material: obj_mesh.material.map_or(fallback, |i| first + i),
+                offset: 0,
+            });
+        }
+        Ok(())
+    }
+
+    fn import_gltf(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        path: &Path,
+    ) -> Result<(), LoadError> {
+        let scene = gltf::load(path)?;
+        let shader = Rc::clone(self.props_material.shader());
+
+        // glTF samplers repeat unless they say otherwise
+        let sampler = SamplerPreset::Trilinear.with_address_mode(wgpu::AddressMode::Repeat);
+        let first = self.imported_materials.len();
+        for material in &scene.materials {
+            let texture = material.base_color_texture.map(|image| {
+                let (texture, cmd_buffer) = scene.create_texture(device, image, &material.name);
+                queue.submit(&[cmd_buffer]);
+                Rc::new(texture.with_sampler(device, &sampler))
+            });
+            self.imported_materials.push(lit_material(
+                device,
+                &shader,
+                material.base_color_factor,
+                material.emissive_factor,
+                texture,
+            ));
+        }
+        // glTF's default material
+        let fallback = self.imported_materials.len();
+        self.imported_materials
+            .push(lit_material(device, &shader, [1.0; 4], [0.0; 3], None));
+
+        let mut camera = None;
+        for node in scene.scene_nodes() {
+            for primitive in scene.world_primitives(node) {
+                self.imported.push(ImportedMesh {
+                    mesh: primitive.mesh.upload(device),
+                    transform: Transform::identity(),
+                    material: primitive.material.map_or(fallback, |i| first + i),
+                    offset: 0,
+                });
+            }
+            camera = camera.or_else(|| scene.camera(node, sc_desc));
+        }
+        if let Some(camera) = camera {
+            self.camera = camera;
+        }
+        Ok(())
+    }
+}
+
+impl Stateful for TexturedState {
+    fn render(&mut self, frame: &wgpu::SwapChainOutput, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &frame.view,
+                resolve_target: None,
+                load_op: wgpu::LoadOp::Clear,
+                store_op: wgpu::StoreOp::Store,
+                clear_color: wgpu::Color {
+                    r: 0.1,
+                    g: 0.1,
+                    b: 0.1,
+                    a: 1.0,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                attachment: &self.depth_texture.view,
+                depth_load_op: wgpu::LoadOp::Clear,
+                depth_store_op: wgpu::StoreOp::Store,
+                clear_depth: 1.0,
+                stencil_load_op: wgpu::LoadOp::Clear,
+                stencil_store_op: wgpu::StoreOp::Store,
+                clear_stencil: 0,
+            }),
+        });
+
+        let pipeline = self
+            .pipelines
+            .get_instanced::<Vertex>(self.floor_material.shader(), self.format)
+            .expect("pipeline is prepared in new");
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+
+        // both materials share the shader, only bind group 0 changes
+        self.floor_material.bind(&mut render_pass);
+        self.floor.draw(&mut render_pass, &self.cube);
+        self.atlas_material.bind(&mut render_pass);
+        self.instances.draw(&mut render_pass, &self.cube);
+
+        let pipeline = self
+            .pipelines
+            .get::<VertexN>(self.props_material.shader(), self.format)
+            .expect("pipeline is prepared in new");
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+
+        self.props_material.bind(&mut render_pass);
+        for prop in &self.props {
+            self.object_uniforms.bind(&mut render_pass, 2, prop.offset);
+            prop.mesh.draw(&mut render_pass);
+        }
+        for imported in &self.imported {
+            self.imported_materials[imported.material].bind(&mut render_pass);
+            self.object_uniforms
+                .bind(&mut render_pass, 2, imported.offset);
+            imported.mesh.draw(&mut render_pass);
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+    ) {
+        self.camera_controller.update(&mut self.camera);
+        self.uniforms.update_view_proj(self.camera.to_matrix());
+
+        staging_belt.write_buffer(
+            device,
+            encoder,
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniforms]),
+        );
+
+        let spin = uv::Rotor3::from_rotation_xz(SPIN_SPEED);
+        for cube in self.cubes.iter_mut() {
+            cube.transform.rotate(spin);
+        }
+        let instances: Vec<Instance> = self.cubes.iter().map(Cube::instance).collect();
+        self.instances
+            .update(device, encoder, staging_belt, &instances);
+
+        self.object_uniforms.clear();
+        for prop in &mut self.props {
+            let height = BOB_HEIGHT * prop.phase.sin();
+            prop.phase += BOB_SPEED;
+            let bob = BOB_HEIGHT * prop.phase.sin() - height;
+            prop.transform.translate(uv::Vec3::new(0.0, -bob, 0.0));
+            prop.transform.rotate(spin);
+            prop.offset = self
+                .object_uniforms
+                .push(ModelUniforms::new(&prop.transform));
+        }
+        for imported in &mut self.imported {
+            imported.offset = self
+                .object_uniforms
+                .push(ModelUniforms::new(&imported.transform));
+        }
+        self.object_uniforms.upload(device, encoder, staging_belt);
+
+        self.floor_material.update(device, encoder, staging_belt);
+        self.atlas_material.update(device, encoder, staging_belt);
+        self.props_material.update(device, encoder, staging_belt);
+        for material in &mut self.imported_materials {
+            material.update(device, encoder, staging_belt);
+        }
+    }
+
+    fn input(&mut self, input: &WinitInputHelper) -> bool {
+        self.camera_controller.input(input);
+        false
+    }
+
+    fn resize(
+        &mut self,
+        device: &mut Device,
+        sc_desc: &mut SwapChainDescriptor,
+        size: &winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.size = *size;
+        self.depth_texture = texture::Texture::new_depth(device, sc_desc, "depth_texture");
+    }
+
+    fn id(&self) -> usize {
+        super::state_ids::TEXTURED
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        // there is only ever one of each state, like `id`
+        other.downcast_ref::<Self>().is_some()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
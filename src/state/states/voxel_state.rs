@@ -0,0 +1,711 @@
+use std::any::Any;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wgpu::{Device, SwapChainDescriptor};
+use winit::event::VirtualKeyCode as vkc;
+use winit_input_helper::WinitInputHelper;
+
+use glsl_to_spirv::ShaderType;
+use uv::projection::rh_ydown::perspective_vk;
+
+use crate::geometry::mesh::GpuMesh;
+use crate::loader::error::LoadError;
+use crate::loader::vox::{self, VoxFile};
+use crate::render::atlas::ArrayBuilder;
+use crate::render::texture::{ColorSpace, SamplerPreset};
+use crate::render::upload::StagingBelt;
+use crate::render::{buffer, pipeline, shader, texture};
+use crate::state::traits::Stateful;
+use crate::tools::camera;
+use crate::tools::uniforms;
+use crate::types::{VertexAO, VertexC};
+use crate::utils;
+use crate::voxel::chunk::BlockId;
+use crate::voxel::edit::{Brush, EditMode, Editor, Outline, Tool};
+use crate::voxel::grid::{Palette, VoxelGrid};
+use crate::voxel::light::LightMap;
+use crate::voxel::noise::splitmix64;
+use crate::voxel::raycast;
+use crate::voxel::region::RegionStore;
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::remesh::Remesher;
+use crate::voxel::streaming::{ChunkStreamer, StreamSettings};
+use crate::voxel::terrain::{self, Terrain, TerrainGenerator};
+use crate::voxel::world::World;
+
+const SEED: u64 = 42;
+/// Region files of the edited chunks, relative to the working directory
+const SAVE_DIRECTORY: &str = "saves/voxel";
+/// How often edits are written to disk
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Threads meshing chunks in the background
+const MESH_WORKERS: usize = 3;
+/// Far enough to see the whole load radius
+const Z_FAR: f32 = 400.0;
+const OUTLINE_COLOR: [f32; 4] = [0.05, 0.05, 0.05, 1.0];
+/// Blocks the largest flood fill may change
+const FILL_LIMIT: usize = 4096;
+/// Side of the cube around the edit target that gets exported
+const EXPORT_SIZE: usize = 32;
+/// Name of the exported .vox file in `SAVE_DIRECTORY`
+const EXPORT_FILE: &str = "export.vox";
+/// How far in front of where the camera starts imported .vox models go
+const MODEL_DISTANCE: f32 = 24.0;
+/// Space between imported models along x
+const MODEL_GAP: f32 = 2.0;
+/// Where the camera starts, above the terrain at the origin, up is -y
+const CAMERA_START: [f32; 3] = [0.0, -24.0, 0.0];
+/// Side of the generated block textures
+const BLOCK_TILE_SIZE: u32 = 16;
+/// Half the side of the box around the camera that blocks stop
+const CAMERA_RADIUS: f32 = 0.25;
+
+/// Minimum corner of an imported model of `size`, `MODEL_DISTANCE` in front
+/// of where the camera starts and `offset` along x from the first one. It
+/// stands on the highest terrain under it so it never ends up inside a hill.
+fn model_origin(generator: &TerrainGenerator, offset: f32, size: [usize; 3]) -> uv::Vec3 {
+    // the camera starts looking down +z
+    let x = (CAMERA_START[0] + offset).floor() as i32;
+    let z = (CAMERA_START[2] + MODEL_DISTANCE).floor() as i32;
+    let highest = (x..x + size[0] as i32)
+        .flat_map(|x| (z..z + size[2] as i32).map(move |z| (x, z)))
+        .map(|(x, z)| generator.column(x, z).height)
+        .max()
+        .unwrap_or(0);
+    // up is -y, the top of the surface block is at -highest
+    uv::Vec3::new(x as f32, (-highest - size[1] as i32) as f32, z as f32)
+}
+
+/// Light grey tile of `BLOCK_TILE_SIZE`, `shade` gives the brightness of every
+/// texel from 0 to 1. The block colors tint it.
+fn block_tile(shade: impl Fn(u32, u32) -> f32) -> image::RgbaImage {
+    image::RgbaImage::from_fn(BLOCK_TILE_SIZE, BLOCK_TILE_SIZE, |x, y| {
+        let value = (255.0 * (0.7 + 0.3 * shade(x, y))) as u8;
+        image::Rgba([value, value, value, 255])
+    })
+}
+
+/// The same value from 0 to 1 for the same arguments
+fn texel_noise(x: u32, y: u32, seed: u64) -> f32 {
+    let mut state = seed ^ (u64::from(x) << 32 | u64::from(y));
+    (splitmix64(&mut state) >> 40) as f32 / (1 << 24) as f32
+}
+
+/// Generated stand-ins for the textures `assets/blocks.json` names
+fn block_tiles() -> Vec<(&'static str, image::RgbaImage)> {
+    vec![
+        ("stone", block_tile(|x, y| texel_noise(x, y, 1))),
+        ("dirt", block_tile(|x, y| texel_noise(x / 2, y / 2, 2))),
+        ("grass_top", block_tile(|x, y| texel_noise(x, y, 3))),
+        (
+            "grass_side",
+            // grass hanging over the edge onto darker dirt, up is -y
+            block_tile(|x, y| {
+                if y < 3 + (texel_noise(x, 0, 4) * 3.0) as u32 {
+                    1.0
+                } else {
+                    0.4 * texel_noise(x / 2, y / 2, 2)
+                }
+            }),
+        ),
+        ("sand", block_tile(|x, y| 0.6 + 0.4 * texel_noise(x, y, 5))),
+        ("snow", block_tile(|x, y| 0.9 + 0.1 * texel_noise(x, y, 6))),
+        (
+            "glass",
+            block_tile(|x, y| {
+                let edge = BLOCK_TILE_SIZE - 1;
+                if x == 0 || y == 0 || x == edge || y == edge {
+                    0.0
+                } else {
+                    1.0
+                }
+            }),
+        ),
+        (
+            "leaves",
+            block_tile(|x, y| (texel_noise(x, y, 8) > 0.4) as u32 as f32),
+        ),
+        (
+            "lamp",
+            block_tile(|x, y| if x % 4 == 0 || y % 4 == 0 { 0.2 } else { 1.0 }),
+        ),
+        (
+            "fence",
+            block_tile(|x, y| {
+                if x % 8 == 0 {
+                    0.0
+                } else {
+                    0.5 + 0.5 * texel_noise(x, y / 4, 10)
+                }
+            }),
+        ),
+    ]
+}
+
+/// Generated terrain streamed in around the camera that can be edited.
+///
+/// The left mouse button applies the tool at the block in the center of the
+/// screen. 1, 2 and 3 switch between adding, removing and painting, 4 to 7
+/// pick a single block, sphere, box or flood fill, Tab cycles the block that
+/// gets placed and Z and Y undo and redo. X exports the blocks around the
+/// target to `SAVE_DIRECTORY`, imported .vox models float next to the start.
+/// I prints how much of the world is loaded and what streaming did last frame.
+/// Blocks with a collision box stop the camera, unless it is inside one
+/// already.
+pub struct VoxelState {
+    world: World,
+    streamer: ChunkStreamer,
+    registry: Arc<BlockRegistry>,
+    editor: Editor,
+    outline: Outline,
+    last_save: Instant,
+
+    // input is handled per window event, the edits happen once in update
+    apply: bool,
+    undo: bool,
+    redo: bool,
+    next_block: bool,
+    export: bool,
+
+    /// Imported .vox models, drawn where they are
+    models: Vec<GpuMesh>,
+    /// Where the next imported model starts along x
+    model_offset: f32,
+
+    terrain_pipeline: wgpu::RenderPipeline,
+    /// Layers of the textures the registry resolved, only kept alive for
+    /// `blocks_bind_group`
+    _blocks_texture: texture::Texture,
+    blocks_bind_group: wgpu::BindGroup,
+    model_pipeline: wgpu::RenderPipeline,
+    outline_pipeline: wgpu::RenderPipeline,
+    depth_texture: texture::Texture,
+
+    camera: camera::Camera,
+    camera_controller: camera::CameraController,
+
+    uniforms: uniforms::Uniforms,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+
+    size: winit::dpi::PhysicalSize<u32>,
+}
+
+impl VoxelState {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        size: &winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let aspect = sc_desc.width as f32 / sc_desc.height as f32;
+        let fov = utils::rad(60.0);
+        let mut camera = camera::Camera::with_projection(
+            uv::Isometry3::new(uv::Vec3::zero(), camera::y_down()),
+            perspective_vk(fov, aspect, 0.1, Z_FAR),
+            aspect,
+            fov,
+        );
+        camera.set_position(uv::Vec3::from(CAMERA_START));
+
+        let camera_controller = camera::CameraController::default();
+
+        let mut uniforms = uniforms::Uniforms::new();
+        uniforms.update_view_proj(camera.to_matrix());
+
+        let uniform_buffer = device.create_buffer_with_data(
+            bytemuck::cast_slice(&[uniforms]),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let (uniform_bind_group, uniform_bind_group_layout) = buffer::create_uniform_bind_group(
+            device,
+            wgpu::ShaderStage::VERTEX,
+            0,
+            "voxel_uniform_bind_group_layout",
+            0,
+            "voxel_uniform_bind_group",
+            &uniform_buffer,
+            std::mem::size_of_val(&uniforms),
+        );
+
+        let mut tiles = ArrayBuilder::new();
+        for (name, image) in block_tiles() {
+            tiles
+                .add(name, image)
+                .expect("the block tiles have the same size");
+        }
+        let mut registry = BlockRegistry::builtin();
+        registry
+            .resolve_textures(|name| tiles.layer(name))
+            .expect("every texture of assets/blocks.json has a tile");
+        let registry = Arc::new(registry);
+
+        let (blocks_texture, cmd_buffer) = tiles
+            .upload(device, "block_textures", ColorSpace::Srgb)
+            .expect("there are block tiles");
+        queue.submit(&[cmd_buffer]);
+        let blocks_texture = blocks_texture.with_sampler(
+            device,
+            &SamplerPreset::Nearest.with_address_mode(wgpu::AddressMode::Repeat),
+        );
+        let blocks_layout = pipeline::texture_bind_group_layout(
+            device,
+            wgpu::TextureViewDimension::D2Array,
+            "voxel_blocks_bind_group_layout",
+        );
+        let blocks_bind_group = pipeline::create_texture_bind_group(
+            device,
+            &blocks_layout,
+            &blocks_texture,
+            "voxel_blocks_bind_group",
+        );
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&uniform_bind_group_layout],
+        });
+        let terrain_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&uniform_bind_group_layout, &blocks_layout],
+        });
+        let voxel_vs = shader::create_shader_module(
+            include_str!("../../../assets/shaders/voxel_vertex.glsl"),
+            ShaderType::Vertex,
+            device,
+        );
+        let default_vs = shader::create_shader_module(
+            include_str!("../../../assets/shaders/default_vertex.glsl"),
+            ShaderType::Vertex,
+            device,
+        );
+        let fs_module = shader::create_shader_module(
+            include_str!("../../../assets/shaders/default_fragment.glsl"),
+            ShaderType::Fragment,
+            device,
+        );
+        let voxel_fs = shader::create_shader_module(
+            include_str!("../../../assets/shaders/voxel_fragment.glsl"),
+            ShaderType::Fragment,
+            device,
+        );
+
+        let terrain_pipeline = pipeline::create_render_pipeline(
+            device,
+            &terrain_layout,
+            wgpu::PrimitiveTopology::TriangleList,
+            &voxel_vs,
+            &voxel_fs,
+            sc_desc.format,
+            texture::DEPTH_FORMAT,
+            &[VertexAO::desc()],
+            wgpu::IndexFormat::Uint32,
+            false,
+            "main",
+        );
+        let model_pipeline = pipeline::create_render_pipeline(
+            device,
+            &layout,
+            wgpu::PrimitiveTopology::TriangleList,
+            &default_vs,
+            &fs_module,
+            sc_desc.format,
+            texture::DEPTH_FORMAT,
+            &[VertexC::desc()],
+            wgpu::IndexFormat::Uint32,
+            false,
+            "main",
+        );
+        let outline_pipeline = pipeline::create_render_pipeline(
+            device,
+            &layout,
+            wgpu::PrimitiveTopology::LineList,
+            &default_vs,
+            &fs_module,
+            sc_desc.format,
+            texture::DEPTH_FORMAT,
+            &[VertexC::desc()],
+            wgpu::IndexFormat::Uint32,
+            false,
+            "main",
+        );
+
+        let terrain = Terrain::new(TerrainGenerator::new(SEED));
+        let remesher = Remesher::new(MESH_WORKERS, Arc::clone(&registry));
+        let mut streamer = ChunkStreamer::new(terrain, remesher, StreamSettings::default());
+        streamer.set_light(Some(LightMap::new(Arc::clone(&registry))));
+        match RegionStore::open(SAVE_DIRECTORY) {
+            Ok(store) => streamer.set_store(Some(store)),
+            Err(e) => eprintln!("warning: edits won't be saved: {}", e),
+        }
+
+        let depth_texture = texture::Texture::new_depth(device, sc_desc, "depth_texture");
+
+        Self {
+            world: World::new(),
+            streamer,
+            registry,
+            editor: Editor::new(terrain::STONE),
+            outline: Outline::new(OUTLINE_COLOR),
+            last_save: Instant::now(),
+            apply: false,
+            undo: false,
+            redo: false,
+            next_block: false,
+            export: false,
+            models: Vec::new(),
+            model_offset: 0.0,
+            terrain_pipeline,
+            _blocks_texture: blocks_texture,
+            blocks_bind_group,
+            model_pipeline,
+            outline_pipeline,
+            depth_texture,
+            camera,
+            camera_controller,
+            uniforms,
+            uniform_bind_group,
+            uniform_buffer,
+            size: *size,
+        }
+    }
+
+    /// Adds the models of a .vox file to the scene, side by side
+    pub fn import(&mut self, device: &wgpu::Device, path: &Path) -> Result<(), LoadError> {
+        let file = vox::load(path)?;
+        for model in &file.models {
+            let generator = self.streamer.terrain().generator();
+            let offset = model_origin(generator, self.model_offset, model.size());
+            let mesh = model.mesh(&file.palette).map(|vertex| VertexC {
+                position: (uv::Vec3::from(vertex.position) + offset).into(),
+                ..vertex
+            });
+            self.models.push(mesh.upload(device));
+            self.model_offset += model.size()[0] as f32 + MODEL_GAP;
+        }
+        Ok(())
+    }
+
+    /// Writes the blocks in a cube around the edit target to a .vox file.
+    /// Block ids are the voxel values, the palette has the registry's colors.
+    fn export(&self) -> Result<(), LoadError> {
+        let center = match self.editor.target() {
+            Some(hit) => hit.position,
+            None => return Err(LoadError::format("aim at a block to export around it")),
+        };
+
+        let half = (EXPORT_SIZE / 2) as i32;
+        let mut grid = VoxelGrid::new([EXPORT_SIZE; 3]);
+        let mut skipped = 0;
+        for z in 0..EXPORT_SIZE {
+            for y in 0..EXPORT_SIZE {
+                for x in 0..EXPORT_SIZE {
+                    let block = self.world.get(
+                        center[0] - half + x as i32,
+                        center[1] - half + y as i32,
+                        center[2] - half + z as i32,
+                    );
+                    match u8::try_from(block) {
+                        Ok(value) => grid.set(x, y, z, value),
+                        Err(_) => skipped += 1,
+                    }
+                }
+            }
+        }
+        if skipped > 0 {
+            eprintln!(
+                "warning: {} blocks with ids above 255 weren't exported",
+                skipped
+            );
+        }
+
+        let mut palette: Palette = [[0; 4]; 256];
+        for (value, entry) in palette.iter_mut().enumerate().skip(1) {
+            let color = self.registry.color(value as BlockId);
+            for (channel, &c) in entry.iter_mut().zip(color.iter()) {
+                *channel = (c * 255.0).round() as u8;
+            }
+        }
+
+        let file = VoxFile {
+            models: vec![grid],
+            palette,
+        };
+        vox::save(Path::new(SAVE_DIRECTORY).join(EXPORT_FILE), &file)
+    }
+
+    fn print_stats(&self) {
+        let solid: usize = self
+            .world
+            .chunks()
+            .map(|(_, chunk)| chunk.solid_count())
+            .sum();
+        println!(
+            "{} chunks, {} solid blocks in {} KiB",
+            self.world.chunk_count(),
+            solid,
+            self.world.memory_usage() / 1024
+        );
+
+        let stats = self.streamer.stats();
+        println!(
+            "{} loaded, {} to generate, {} waiting for meshes, {} meshed",
+            stats.loaded, stats.missing, stats.pending, stats.meshed
+        );
+        println!(
+            "last update: {} generated, {} read, {} unloaded, {} saved, {} uploaded, {} warnings",
+            stats.generated,
+            stats.read,
+            stats.unloaded,
+            stats.saved,
+            stats.uploaded,
+            stats.warnings
+        );
+    }
+
+    /// Whether a block's collision box overlaps the camera's box at `position`
+    fn is_blocked(&self, position: uv::Vec3) -> bool {
+        let half = uv::Vec3::broadcast(CAMERA_RADIUS);
+        !self
+            .registry
+            .colliders(&self.world, position - half, position + half)
+            .is_empty()
+    }
+
+    /// Flies the camera, sliding along the blocks in its way axis by axis
+    fn move_camera(&mut self) {
+        let start = self.camera.position();
+        self.camera_controller.update(&mut self.camera);
+        if self.is_blocked(start) {
+            return;
+        }
+
+        let target = self.camera.position();
+        let mut position = start;
+        for &axis in [0, 1, 2].iter() {
+            let mut moved = position;
+            match axis {
+                0 => moved.x = target.x,
+                1 => moved.y = target.y,
+                _ => moved.z = target.z,
+            }
+            if !self.is_blocked(moved) {
+                position = moved;
+            }
+        }
+        // the boxes are only checked at the end of the step, a long one
+        // mustn't skip through a block in between
+        if !raycast::line_of_sight(&self.world, start, position) {
+            position = start;
+        }
+        self.camera.set_position(position);
+    }
+
+    /// The next solid block of the registry after the one placed now
+    fn cycle_block(&mut self) {
+        let current = self.editor.block;
+        let solid: Vec<_> = self
+            .registry
+            .iter()
+            .map(|block| block.id)
+            .filter(|&id| self.registry.is_solid(id))
+            .collect();
+        self.editor.block = solid
+            .iter()
+            .copied()
+            .find(|&id| id > current)
+            .or_else(|| solid.first().copied())
+            .unwrap_or(current);
+    }
+
+    fn edit(&mut self) {
+        self.editor.update_target(&self.world, &self.camera.ray());
+
+        if std::mem::take(&mut self.next_block) {
+            self.cycle_block();
+        }
+        if std::mem::take(&mut self.apply) {
+            if let Err(e) = self.editor.apply(&mut self.world) {
+                eprintln!("warning: can't edit: {}", e);
+            }
+        }
+        if std::mem::take(&mut self.undo) {
+            self.editor.undo(&mut self.world);
+        }
+        if std::mem::take(&mut self.redo) {
+            self.editor.redo(&mut self.world);
+        }
+
+        if std::mem::take(&mut self.export) {
+            if let Err(e) = self.export() {
+                eprintln!("warning: can't export: {}", e);
+            }
+        }
+
+        let changed = self.editor.take_changed();
+        if let (false, Some(light)) = (changed.is_empty(), self.streamer.light_mut()) {
+            light.update(&mut self.world, changed);
+        }
+    }
+}
+
+impl Stateful for VoxelState {
+    fn render(&mut self, frame: &wgpu::SwapChainOutput, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &frame.view,
+                resolve_target: None,
+                load_op: wgpu::LoadOp::Clear,
+                store_op: wgpu::StoreOp::Store,
+                clear_color: wgpu::Color {
+                    r: 0.45,
+                    g: 0.65,
+                    b: 0.9,
+                    a: 1.0,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                attachment: &self.depth_texture.view,
+                depth_load_op: wgpu::LoadOp::Clear,
+                depth_store_op: wgpu::StoreOp::Store,
+                clear_depth: 1.0,
+                stencil_load_op: wgpu::LoadOp::Clear,
+                stencil_store_op: wgpu::StoreOp::Store,
+                clear_stencil: 0,
+            }),
+        });
+
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+
+        render_pass.set_pipeline(&self.terrain_pipeline);
+        render_pass.set_bind_group(1, &self.blocks_bind_group, &[]);
+        self.streamer.remesher().draw(&mut render_pass);
+
+        render_pass.set_pipeline(&self.model_pipeline);
+        for model in &self.models {
+            model.draw(&mut render_pass);
+        }
+
+        render_pass.set_pipeline(&self.outline_pipeline);
+        self.outline.draw(&mut render_pass);
+    }
+
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+    ) {
+        self.move_camera();
+        self.uniforms.update_view_proj(self.camera.to_matrix());
+
+        staging_belt.write_buffer(
+            device,
+            encoder,
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniforms]),
+        );
+
+        // edits first so the chunks they dirty are remeshed this frame
+        self.edit();
+        self.streamer.update(&mut self.world, &self.camera, device);
+        self.outline.update(device, &self.editor);
+
+        for warning in self.streamer.take_warnings() {
+            eprintln!("warning: {}", warning);
+        }
+        if self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.last_save = Instant::now();
+            // drops the chunks edits emptied, they're saved all the same
+            self.world.compact();
+            if let Err(e) = self.streamer.save(&mut self.world) {
+                eprintln!("warning: can't save the world: {}", e);
+            }
+        }
+    }
+
+    fn input(&mut self, input: &WinitInputHelper) -> bool {
+        self.camera_controller.input(input);
+
+        if input.key_pressed(vkc::Key1) {
+            self.editor.mode = EditMode::Add;
+        }
+        if input.key_pressed(vkc::Key2) {
+            self.editor.mode = EditMode::Remove;
+        }
+        if input.key_pressed(vkc::Key3) {
+            self.editor.mode = EditMode::Paint;
+        }
+        if input.key_pressed(vkc::Key4) {
+            self.editor.tool = Tool::Brush(Brush::Block);
+        }
+        if input.key_pressed(vkc::Key5) {
+            self.editor.tool = Tool::Brush(Brush::Sphere { radius: 3 });
+        }
+        if input.key_pressed(vkc::Key6) {
+            self.editor.tool = Tool::Brush(Brush::Box { size: [3, 3, 3] });
+        }
+        if input.key_pressed(vkc::Key7) {
+            self.editor.tool = Tool::FloodFill { limit: FILL_LIMIT };
+        }
+
+        self.next_block |= input.key_pressed(vkc::Tab);
+        self.apply |= input.mouse_pressed(0);
+        self.undo |= input.key_pressed(vkc::Z);
+        self.redo |= input.key_pressed(vkc::Y);
+        self.export |= input.key_pressed(vkc::X);
+        if input.key_pressed(vkc::I) {
+            self.print_stats();
+        }
+        false
+    }
+
+    fn resize(
+        &mut self,
+        device: &mut Device,
+        sc_desc: &mut SwapChainDescriptor,
+        size: &winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.size = *size;
+        self.depth_texture = texture::Texture::new_depth(device, sc_desc, "depth_texture");
+    }
+
+    fn id(&self) -> usize {
+        super::state_ids::VOXEL
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        // there is only ever one of each state, like `id`
+        other.downcast_ref::<Self>().is_some()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imported_models_stay_out_of_the_terrain() {
+        let generator = TerrainGenerator::new(SEED);
+        let mut offset = 0.0;
+        for &size in [[8, 8, 8], [32, 64, 16], [40, 4, 40]].iter() {
+            let origin = model_origin(&generator, offset, size);
+            assert!(origin.z > CAMERA_START[2], "{:?} isn't in front", origin);
+
+            let bottom = origin.y as i32 + size[1] as i32;
+            let (x, z) = (origin.x as i32, origin.z as i32);
+            for x in x..x + size[0] as i32 {
+                for z in z..z + size[2] as i32 {
+                    let height = generator.column(x, z).height;
+                    assert!(bottom <= -height, "{:?} reaches into {} {}", origin, x, z);
+                }
+            }
+            offset += size[0] as f32 + MODEL_GAP;
+        }
+    }
+}
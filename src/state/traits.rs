@@ -2,9 +2,17 @@ use std::any::Any;
 use std::ops::Index;
 use winit_input_helper::WinitInputHelper;
 
+use crate::render::upload::StagingBelt;
+
 pub trait Stateful: Any {
     fn render(&mut self, frame: &wgpu::SwapChainOutput, encoder: &mut wgpu::CommandEncoder);
-    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue);
+    /// Records the frame's uploads, the encoder is submitted after `render`
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut StagingBelt,
+    );
     fn input(&mut self, input: &WinitInputHelper) -> bool;
     fn resize(
         &mut self,
@@ -6,10 +6,18 @@ use uv::mat::Mat4;
 use uv::vec::Vec3;
 use uv::rotor::Rotor3;
 use uv::Isometry3;
+use uv::geometry::Ray;
+use crate::tools::frustum::Frustum;
 use crate::utils;
 use crate::utils::{rotor_from_angles, rad};
 use std::f32::consts::PI;
 
+/// View rotation for the engine's world, where up is -y. Up on screen is +y
+/// in view space, so it's half a turn around x, like glTF's import.
+pub fn y_down() -> Rotor3 {
+    Rotor3::from_rotation_yz(PI)
+}
+
 pub struct Camera {
     perspective: Mat4,
     transformation: Isometry3,
@@ -36,6 +44,21 @@ impl Camera {
         }
     }
 
+    /// Camera with an existing view transformation and projection, e.g. from an imported scene
+    pub fn with_projection(
+        transformation: Isometry3,
+        perspective: Mat4,
+        aspect: f32,
+        fov: f32,
+    ) -> Self {
+        Self {
+            perspective,
+            transformation,
+            aspect,
+            fov,
+        }
+    }
+
     pub fn update_transformation(&mut self, transformation: Isometry3) {
         self.transformation = transformation;
     }
@@ -47,6 +70,41 @@ impl Camera {
     pub fn to_matrix(&self) -> Mat4 {
         self.perspective * self.transformation_matrix()
     }
+
+    /// Position of the camera in the world
+    pub fn position(&self) -> Vec3 {
+        self.transformation.inversed().translation
+    }
+
+    /// Moves the camera without turning it
+    pub fn set_position(&mut self, position: Vec3) {
+        let mut translation = -position;
+        self.transformation.rotation.rotate_vec(&mut translation);
+        self.transformation.translation = translation;
+    }
+
+    /// Turns the camera where it is, e.g. to `y_down`
+    pub fn set_rotation(&mut self, rotation: Rotor3) {
+        let position = self.position();
+        self.transformation.rotation = rotation;
+        self.set_position(position);
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.to_matrix())
+    }
+
+    /// Ray from the camera through the center of the screen, e.g. for picking blocks
+    pub fn ray(&self) -> Ray {
+        let inverse = self.transformation.inversed();
+        // the projection looks down -z
+        let mut direction = Vec3::new(0.0, 0.0, -1.0);
+        inverse.rotation.rotate_vec(&mut direction);
+        Ray {
+            origin: inverse.translation,
+            direction,
+        }
+    }
 }
 
 pub struct CameraController {
@@ -223,4 +281,36 @@ impl Default for CameraController {
             mouse_coords: (0.0, 0.0),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uv::vec::Vec4;
+
+    #[test]
+    fn y_down_cameras_show_minus_y_on_top() {
+        let perspective = perspective_vk(rad(60.0), 1.0, 0.1, 100.0);
+        let mut camera =
+            Camera::with_projection(Isometry3::new(Vec3::zero(), y_down()), perspective, 1.0, 1.0);
+        camera.set_position(Vec3::new(0.0, -24.0, 0.0));
+        assert!((camera.position() - Vec3::new(0.0, -24.0, 0.0)).mag() < 1e-4);
+
+        // looking down +z, wgpu's clip space has y pointing up
+        let ray = camera.ray();
+        assert!((ray.direction - Vec3::new(0.0, 0.0, 1.0)).mag() < 1e-4);
+        let clip = camera.to_matrix() * Vec4::new(0.0, -25.0, 10.0, 1.0);
+        assert!(clip.w > 0.0 && clip.y / clip.w > 0.0, "{:?}", clip);
+
+        let mut camera = Camera::new(&wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: 100,
+            height: 100,
+            present_mode: wgpu::PresentMode::Fifo,
+        });
+        let start = camera.position();
+        camera.set_rotation(y_down());
+        assert!((camera.position() - start).mag() < 1e-4);
+        assert!((camera.ray().direction - Vec3::new(0.0, 0.0, 1.0)).mag() < 1e-4);
+    }
+}
@@ -0,0 +1,73 @@
+use uv::mat::Mat4;
+use uv::vec::{Vec3, Vec4};
+
+/// Clip planes of a view projection matrix, points with a positive distance
+/// to all of them are visible
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far as (normal, distance)
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Planes of a projection with 0.0 - 1.0 depth like `perspective_vk`
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let row = |i: usize| {
+            let c = &matrix.cols;
+            match i {
+                0 => Vec4::new(c[0].x, c[1].x, c[2].x, c[3].x),
+                1 => Vec4::new(c[0].y, c[1].y, c[2].y, c[3].y),
+                2 => Vec4::new(c[0].z, c[1].z, c[2].z, c[3].z),
+                _ => Vec4::new(c[0].w, c[1].w, c[2].w, c[3].w),
+            }
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
+
+    /// Whether an axis aligned box is at least partly inside. Boxes close to
+    /// a corner of the frustum may pass without being visible.
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|p| {
+            // the corner furthest along the plane's normal
+            let x = if p.x >= 0.0 { max.x } else { min.x };
+            let y = if p.y >= 0.0 { max.y } else { min.y };
+            let z = if p.z >= 0.0 { max.z } else { min.z };
+            p.x * x + p.y * y + p.z * z + p.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uv::projection::rh_ydown::perspective_vk;
+
+    use super::*;
+
+    #[test]
+    fn boxes_outside_of_a_plane_are_culled() {
+        // at the origin looking down -z, 90 degrees wide
+        let frustum =
+            Frustum::from_matrix(perspective_vk(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0));
+        let unit = |x: f32, y: f32, z: f32| {
+            let min = Vec3::new(x, y, z);
+            frustum.intersects_aabb(min, min + Vec3::one())
+        };
+
+        assert!(unit(-0.5, -0.5, -10.0));
+        // partly inside
+        assert!(unit(9.5, -0.5, -10.0));
+        assert!(unit(-0.5, -0.5, -1.5));
+
+        // behind, beyond far, left, right, above and below
+        assert!(!unit(-0.5, -0.5, 5.0));
+        assert!(!unit(-0.5, -0.5, -110.0));
+        assert!(!unit(-12.0, -0.5, -10.0));
+        assert!(!unit(11.0, -0.5, -10.0));
+        assert!(!unit(-0.5, -12.0, -10.0));
+        assert!(!unit(-0.5, 11.0, -10.0));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod camera;
+pub mod frustum;
 pub mod uniforms;
\ No newline at end of file
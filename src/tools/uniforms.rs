@@ -1,3 +1,4 @@
+use crate::geometry::transform::Transform;
 use crate::tools::camera::Camera;
 
 #[repr(C)]
@@ -20,4 +21,26 @@ impl Uniforms {
 }
 
 unsafe impl bytemuck::Pod for Uniforms {}
-unsafe impl bytemuck::Zeroable for Uniforms {}
\ No newline at end of file
+unsafe impl bytemuck::Zeroable for Uniforms {}
+
+/// Matrices of one object, pushed into a `UniformArena` and bound with the
+/// object's dynamic offset. The normal matrix is stored as a `mat4` because
+/// std140 pads the columns of a `mat3`, shaders take `mat3(u_normal)`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ModelUniforms {
+    model: uv::Mat4,
+    normal: uv::Mat4,
+}
+
+impl ModelUniforms {
+    pub fn new(transform: &Transform) -> Self {
+        Self {
+            model: transform.to_matrix(),
+            normal: transform.normal_matrix().into_homogeneous(),
+        }
+    }
+}
+
+unsafe impl bytemuck::Pod for ModelUniforms {}
+unsafe impl bytemuck::Zeroable for ModelUniforms {}
@@ -1,6 +1,11 @@
 #![allow(dead_code, unused_imports, unused_variables)]
 use std::fmt;
 
+/// Vertex types with a buffer layout, e.g. to build pipelines for them
+pub trait VertexLayout: bytemuck::Pod + 'static {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a>;
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
@@ -40,6 +45,12 @@ impl Vertex {
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
 
+impl VertexLayout for Vertex {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        Vertex::desc()
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 /// A vertex with color instead of texture
@@ -73,6 +84,122 @@ impl VertexC {
 unsafe impl bytemuck::Pod for VertexC {}
 unsafe impl bytemuck::Zeroable for VertexC {}
 
+impl VertexLayout for VertexC {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        VertexC::desc()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// A colored vertex with a brightness factor from ambient occlusion, used for voxel meshes.
+/// `layer` is the layer of a texture array, negative for untextured vertices.
+pub struct VertexAO {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+    pub ao: f32,
+    pub tex_coords: [f32; 2],
+    pub layer: f32,
+}
+
+impl VertexAO {
+    pub fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        use std::mem;
+        wgpu::VertexBufferDescriptor {
+            stride: mem::size_of::<VertexAO>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (mem::size_of::<[f32; 3]>() + mem::size_of::<[f32; 4]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (mem::size_of::<[f32; 3]>()
+                        + mem::size_of::<[f32; 4]>()
+                        + mem::size_of::<f32>()) as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (mem::size_of::<[f32; 3]>()
+                        + mem::size_of::<[f32; 4]>()
+                        + mem::size_of::<f32>()
+                        + mem::size_of::<[f32; 2]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float,
+                },
+            ],
+        }
+    }
+}
+
+unsafe impl bytemuck::Pod for VertexAO {}
+unsafe impl bytemuck::Zeroable for VertexAO {}
+
+impl VertexLayout for VertexAO {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        VertexAO::desc()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// A vertex with a normal and texture coordinates, used by generated and imported meshes
+pub struct VertexN {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+impl VertexN {
+    pub fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        use std::mem;
+        wgpu::VertexBufferDescriptor {
+            stride: mem::size_of::<VertexN>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (mem::size_of::<[f32; 3]>() * 2) as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float2,
+                },
+            ],
+        }
+    }
+}
+
+unsafe impl bytemuck::Pod for VertexN {}
+unsafe impl bytemuck::Zeroable for VertexN {}
+
+impl VertexLayout for VertexN {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        VertexN::desc()
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Triangle {
     pub vertices: [Vertex; 3],
@@ -9,4 +9,29 @@ pub fn rad(angle: f32) -> f32 {
 /// zxy?!? where is the hidden camera
 pub fn rotor_from_angles(x: f32, y: f32, z: f32) -> uv::Rotor3 {
     uv::Rotor3::from_euler_angles(rad(z), rad(x), rad(y))
-}
\ No newline at end of file
+}
+
+/// glTF style [x, y, z, w] quaternion to rotor
+pub fn rotor_from_quaternion(q: [f32; 4]) -> uv::Rotor3 {
+    uv::Rotor3::new(q[3], uv::Bivec3::new(-q[2], q[1], -q[0])).normalized()
+}
+
+/// Rotation part of a matrix without scaling, via the quaternion of it
+pub fn rotor_from_matrix(m: uv::Mat3) -> uv::Rotor3 {
+    let c = m.cols;
+    let trace = c[0].x + c[1].y + c[2].z;
+    let q = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [(c[1].z - c[2].y) / s, (c[2].x - c[0].z) / s, (c[0].y - c[1].x) / s, s / 4.0]
+    } else if c[0].x > c[1].y && c[0].x > c[2].z {
+        let s = (1.0 + c[0].x - c[1].y - c[2].z).sqrt() * 2.0;
+        [s / 4.0, (c[1].x + c[0].y) / s, (c[2].x + c[0].z) / s, (c[1].z - c[2].y) / s]
+    } else if c[1].y > c[2].z {
+        let s = (1.0 + c[1].y - c[0].x - c[2].z).sqrt() * 2.0;
+        [(c[1].x + c[0].y) / s, s / 4.0, (c[2].y + c[1].z) / s, (c[2].x - c[0].z) / s]
+    } else {
+        let s = (1.0 + c[2].z - c[0].x - c[1].y).sqrt() * 2.0;
+        [(c[2].x + c[0].z) / s, (c[2].y + c[1].z) / s, s / 4.0, (c[0].y - c[1].x) / s]
+    };
+    rotor_from_quaternion(q)
+}
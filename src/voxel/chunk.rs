@@ -0,0 +1,344 @@
+//! Palette compressed block storage for one chunk.
+//!
+//! A chunk keeps the distinct block ids it contains in a palette and stores
+//! a bit packed palette index per block, so a chunk with few block types only
+//! needs a couple of bits per block. A chunk made of a single block type
+//! doesn't store any indices at all.
+
+pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    /// Block id of every palette entry
+    palette: Vec<BlockId>,
+    /// How many blocks use each palette entry, entries at 0 get reused
+    counts: Vec<u32>,
+    indices: PackedArray,
+}
+
+impl Chunk {
+    /// Chunk filled with a single block type
+    pub fn filled(block: BlockId) -> Self {
+        Self {
+            palette: vec![block],
+            counts: vec![CHUNK_VOLUME as u32],
+            indices: PackedArray::new(0),
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::filled(AIR)
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE);
+        (z * CHUNK_SIZE + y) * CHUNK_SIZE + x
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockId {
+        self.palette[self.indices.get(Self::index(x, y, z))]
+    }
+
+    /// Sets a block and returns the one it replaced
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockId) -> BlockId {
+        let index = Self::index(x, y, z);
+        let old_entry = self.indices.get(index);
+        let old = self.palette[old_entry];
+        if old == block {
+            return old;
+        }
+
+        // released first, so the last block of a type hands its entry over
+        self.counts[old_entry] -= 1;
+        let entry = self.palette_entry(block);
+        self.counts[entry] += 1;
+        self.indices.set(index, entry);
+        old
+    }
+
+    /// Palette entry for `block`, adding it and widening the indices if needed
+    fn palette_entry(&mut self, block: BlockId) -> usize {
+        if let Some(entry) = self.palette.iter().position(|&b| b == block) {
+            return entry;
+        }
+        if let Some(entry) = self.counts.iter().position(|&count| count == 0) {
+            self.palette[entry] = block;
+            return entry;
+        }
+
+        self.palette.push(block);
+        self.counts.push(0);
+        let bits = bits_for(self.palette.len());
+        if bits > self.indices.bits {
+            self.indices = self.indices.repacked(bits);
+        }
+        self.palette.len() - 1
+    }
+
+    /// Drops unused palette entries and narrows the indices to match
+    pub fn compact(&mut self) {
+        let used: Vec<usize> = (0..self.palette.len())
+            .filter(|&entry| self.counts[entry] > 0)
+            .collect();
+        if used.len() == self.palette.len() && bits_for(used.len()) == self.indices.bits {
+            return;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        for (new, &old) in used.iter().enumerate() {
+            remap[old] = new;
+        }
+
+        let mut indices = PackedArray::new(bits_for(used.len()));
+        if indices.bits > 0 {
+            for i in 0..CHUNK_VOLUME {
+                indices.set(i, remap[self.indices.get(i)]);
+            }
+        }
+
+        self.palette = used.iter().map(|&entry| self.palette[entry]).collect();
+        self.counts = used.iter().map(|&entry| self.counts[entry]).collect();
+        self.indices = indices;
+    }
+
+    /// The single block type this chunk is made of, if it is uniform
+    pub fn uniform(&self) -> Option<BlockId> {
+        let mut used = self
+            .counts
+            .iter()
+            .zip(self.palette.iter())
+            .filter(|(&count, _)| count > 0);
+        match (used.next(), used.next()) {
+            (Some((_, &block)), None) => Some(block),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uniform() == Some(AIR)
+    }
+
+    /// Number of blocks that aren't air
+    pub fn solid_count(&self) -> usize {
+        self.palette
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|(&block, _)| block != AIR)
+            .map(|(_, &count)| count as usize)
+            .sum()
+    }
+
+    /// Distinct block ids currently in use
+    pub fn palette(&self) -> impl Iterator<Item = BlockId> + '_ {
+        self.palette
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|(_, &count)| count > 0)
+            .map(|(&block, _)| block)
+    }
+
+    /// Every block with its local position, air included
+    pub fn iter(&self) -> impl Iterator<Item = ([usize; 3], BlockId)> + '_ {
+        (0..CHUNK_VOLUME).map(move |i| {
+            (
+                [
+                    i % CHUNK_SIZE,
+                    i / CHUNK_SIZE % CHUNK_SIZE,
+                    i / (CHUNK_SIZE * CHUNK_SIZE),
+                ],
+                self.palette[self.indices.get(i)],
+            )
+        })
+    }
+
+    /// Approximate heap usage in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.palette.len() * std::mem::size_of::<BlockId>()
+            + self.counts.len() * std::mem::size_of::<u32>()
+            + self.indices.words.len() * std::mem::size_of::<u64>()
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl PartialEq for Chunk {
+    /// Compares the blocks, not how they happen to be stored
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().zip(other.iter()).all(|(a, b)| a.1 == b.1)
+    }
+}
+
+/// Bits needed to tell `entries` palette entries apart
+fn bits_for(entries: usize) -> u8 {
+    let mut bits = 0;
+    while (1 << bits) < entries {
+        bits += 1;
+    }
+    bits
+}
+
+/// `CHUNK_VOLUME` unsigned integers of `bits` bits each. Values never straddle
+/// two words, so a few bits per word may be left unused.
+#[derive(Clone, Debug)]
+struct PackedArray {
+    bits: u8,
+    words: Vec<u64>,
+}
+
+impl PackedArray {
+    fn new(bits: u8) -> Self {
+        let words = if bits == 0 {
+            0
+        } else {
+            CHUNK_VOLUME.div_ceil(64 / bits as usize)
+        };
+        Self {
+            bits,
+            words: vec![0; words],
+        }
+    }
+
+    fn get(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) * self.bits as usize;
+        let mask = (1u64 << self.bits) - 1;
+        ((self.words[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn set(&mut self, index: usize, value: usize) {
+        debug_assert!(self.bits > 0 || value == 0);
+        if self.bits == 0 {
+            return;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) * self.bits as usize;
+        let mask = (1u64 << self.bits) - 1;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !(mask << shift)) | ((value as u64 & mask) << shift);
+    }
+
+    fn repacked(&self, bits: u8) -> Self {
+        let mut repacked = Self::new(bits);
+        for i in 0..CHUNK_VOLUME {
+            repacked.set(i, self.get(i));
+        }
+        repacked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap deterministic block pattern with `types` distinct ids
+    fn pattern(i: usize, types: usize) -> BlockId {
+        (i.wrapping_mul(2_654_435_761) % types) as BlockId
+    }
+
+    #[test]
+    fn bits_grow_with_the_palette() {
+        let mut chunk = Chunk::empty();
+        assert_eq!(chunk.indices.bits, 0);
+        assert_eq!(chunk.get(5, 6, 7), AIR);
+
+        let expected_bits = [(2, 1), (3, 2), (4, 2), (5, 3), (9, 4), (17, 5), (300, 9)];
+        for &(types, bits) in expected_bits.iter() {
+            for (i, ([x, y, z], _)) in Chunk::empty().iter().enumerate() {
+                chunk.set(x, y, z, pattern(i, types));
+            }
+            assert_eq!(chunk.indices.bits, bits, "{} block types", types);
+            for (i, (_, block)) in chunk.iter().enumerate() {
+                assert_eq!(block, pattern(i, types));
+            }
+        }
+    }
+
+    #[test]
+    fn set_returns_the_replaced_block() {
+        let mut chunk = Chunk::filled(3);
+        assert_eq!(chunk.set(0, 0, 0, 4), 3);
+        assert_eq!(chunk.set(0, 0, 0, 4), 4);
+        assert_eq!(
+            chunk.set(CHUNK_SIZE - 1, CHUNK_SIZE - 1, CHUNK_SIZE - 1, 5),
+            3
+        );
+        assert_eq!(chunk.get(0, 0, 0), 4);
+        assert_eq!(chunk.get(CHUNK_SIZE - 1, CHUNK_SIZE - 1, CHUNK_SIZE - 1), 5);
+        assert_eq!(chunk.get(1, 0, 0), 3);
+    }
+
+    #[test]
+    fn unused_entries_are_reused() {
+        let mut chunk = Chunk::empty();
+        chunk.set(1, 2, 3, 7);
+        assert_eq!(chunk.indices.bits, 1);
+
+        // 7 is gone, 8 takes its entry instead of widening the indices
+        chunk.set(1, 2, 3, 8);
+        assert_eq!(chunk.indices.bits, 1);
+        assert_eq!(chunk.palette().collect::<Vec<_>>(), vec![AIR, 8]);
+        assert_eq!(chunk.get(1, 2, 3), 8);
+    }
+
+    #[test]
+    fn compact_narrows_the_indices() {
+        let mut chunk = Chunk::empty();
+        for (i, ([x, y, z], _)) in Chunk::empty().iter().enumerate() {
+            chunk.set(x, y, z, pattern(i, 20));
+        }
+        for ([x, y, z], block) in Chunk::empty().iter() {
+            if chunk.get(x, y, z) > 2 {
+                chunk.set(x, y, z, block);
+            }
+        }
+        let before: Vec<BlockId> = chunk.iter().map(|(_, block)| block).collect();
+        assert_eq!(chunk.indices.bits, 5);
+
+        chunk.compact();
+        assert_eq!(chunk.indices.bits, 2);
+        assert_eq!(
+            chunk.iter().map(|(_, block)| block).collect::<Vec<_>>(),
+            before
+        );
+        assert!(chunk.memory_usage() < Chunk::filled(1).memory_usage() + CHUNK_VOLUME / 3);
+
+        // back to a single type, no indices at all
+        for ([x, y, z], _) in Chunk::empty().iter() {
+            chunk.set(x, y, z, 9);
+        }
+        chunk.compact();
+        assert_eq!(chunk.indices.bits, 0);
+        assert_eq!(chunk.uniform(), Some(9));
+    }
+
+    #[test]
+    fn counts_follow_the_blocks() {
+        let mut chunk = Chunk::empty();
+        assert!(chunk.is_empty());
+        assert_eq!(chunk.solid_count(), 0);
+
+        chunk.set(0, 0, 0, 1);
+        chunk.set(0, 1, 0, 1);
+        chunk.set(0, 2, 0, 2);
+        assert!(!chunk.is_empty());
+        assert_eq!(chunk.uniform(), None);
+        assert_eq!(chunk.solid_count(), 3);
+
+        chunk.set(0, 0, 0, AIR);
+        chunk.set(0, 1, 0, AIR);
+        chunk.set(0, 2, 0, AIR);
+        assert!(chunk.is_empty());
+        assert_eq!(chunk, Chunk::empty());
+    }
+}
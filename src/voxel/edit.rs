@@ -0,0 +1,588 @@
+//! Interactive editing of the voxel world.
+//!
+//! The `Editor` keeps the block under the crosshair up to date from a ray,
+//! applies the current tool there and records every applied edit so it can be
+//! undone and redone. All changes go through `World::set`, which marks the
+//! changed chunks and their neighbours on borders dirty for remeshing.
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use uv::geometry::Ray;
+
+use crate::geometry::mesh::Mesh;
+use crate::types::VertexC;
+use crate::voxel::chunk::{BlockId, AIR};
+use crate::voxel::raycast::{raycast, RayHit};
+use crate::voxel::world::{split_pos, World};
+
+/// Shape of the cells a brush edit touches around its center
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Brush {
+    Block,
+    /// Cells whose center is within `radius` blocks of the center's
+    Sphere {
+        radius: i32,
+    },
+    /// Box of `size` blocks centered on the target, even sizes reach one
+    /// block further towards -x, -y and -z
+    Box {
+        size: [i32; 3],
+    },
+}
+
+impl Brush {
+    /// Cells covered by the brush around `center`
+    pub fn cells(&self, center: [i32; 3]) -> Vec<[i32; 3]> {
+        match *self {
+            Brush::Block => vec![center],
+            Brush::Sphere { radius } => {
+                let radius = radius.max(0);
+                // half a block extra so the axes don't end in single cells
+                let limit = (radius as f32 + 0.5) * (radius as f32 + 0.5);
+                let mut cells = Vec::new();
+                for dz in -radius..=radius {
+                    for dy in -radius..=radius {
+                        for dx in -radius..=radius {
+                            if (dx * dx + dy * dy + dz * dz) as f32 <= limit {
+                                cells.push([center[0] + dx, center[1] + dy, center[2] + dz]);
+                            }
+                        }
+                    }
+                }
+                cells
+            }
+            Brush::Box { size } => {
+                let size = [size[0].max(1), size[1].max(1), size[2].max(1)];
+                let min = [
+                    center[0] - size[0] / 2,
+                    center[1] - size[1] / 2,
+                    center[2] - size[2] / 2,
+                ];
+                let mut cells = Vec::with_capacity((size[0] * size[1] * size[2]) as usize);
+                for z in min[2]..min[2] + size[2] {
+                    for y in min[1]..min[1] + size[1] {
+                        for x in min[0]..min[0] + size[0] {
+                            cells.push([x, y, z]);
+                        }
+                    }
+                }
+                cells
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    Brush(Brush),
+    /// Changes the face connected region of blocks equal to the target, gives
+    /// up on regions larger than `limit` blocks. Never spreads into chunks
+    /// that aren't loaded.
+    FloodFill {
+        limit: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditMode {
+    /// Places blocks in front of the targeted face, only replacing air
+    Add,
+    /// Clears the targeted blocks
+    Remove,
+    /// Replaces the targeted blocks, leaving air alone
+    Paint,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub position: [i32; 3],
+    pub before: BlockId,
+    pub after: BlockId,
+}
+
+/// Blocks changed by a single action, in the order they were changed
+#[derive(Clone, Debug, Default)]
+pub struct Edit {
+    changes: Vec<Change>,
+}
+
+impl Edit {
+    pub fn new() -> Self {
+        Self {
+            changes: Vec::new(),
+        }
+    }
+
+    /// Sets a block and records the change if it is one
+    pub fn set(&mut self, world: &mut World, position: [i32; 3], block: BlockId) {
+        let [x, y, z] = position;
+        let before = world.set(x, y, z, block);
+        if before != block {
+            self.changes.push(Change {
+                position,
+                before,
+                after: block,
+            });
+        }
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn undo(&self, world: &mut World) {
+        for change in self.changes.iter().rev() {
+            let [x, y, z] = change.position;
+            world.set(x, y, z, change.before);
+        }
+    }
+
+    fn redo(&self, world: &mut World) {
+        for change in self.changes.iter() {
+            let [x, y, z] = change.position;
+            world.set(x, y, z, change.after);
+        }
+    }
+}
+
+/// Undo and redo stacks of edits
+#[derive(Clone, Debug)]
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    /// Edits kept for undoing at most, the oldest are dropped first
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Records an edit that was just applied, which discards the redo stack.
+    /// Empty edits are ignored.
+    pub fn push(&mut self, edit: Edit) {
+        if edit.is_empty() || self.limit == 0 {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(edit);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Reverts the latest edit, returns false if there was nothing to undo
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        match self.undo.pop_back() {
+            Some(edit) => {
+                edit.undo(world);
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the latest undone edit again, returns false if there was none
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                edit.redo(world);
+                self.undo.push_back(edit);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+/// Why a flood fill left the world alone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillError {
+    /// The start is in a chunk that isn't loaded
+    Unloaded { start: [i32; 3] },
+    /// The region has more blocks than the limit
+    TooLarge { start: [i32; 3], limit: usize },
+}
+
+impl fmt::Display for FillError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FillError::Unloaded { start } => {
+                write!(f, "flood fill at {:?} starts in an unloaded chunk", start)
+            }
+            FillError::TooLarge { start, limit } => write!(
+                f,
+                "flood fill at {:?} covers more than {} blocks",
+                start, limit
+            ),
+        }
+    }
+}
+
+/// Changes the face connected region of blocks equal to the one at `start` to
+/// `block`. Changes nothing if the region has more than `limit` blocks or
+/// `start` isn't loaded. Unloaded chunks act as a border, so filling air only
+/// works in enclosed spaces or with a limit.
+pub fn flood_fill(
+    world: &mut World,
+    start: [i32; 3],
+    block: BlockId,
+    limit: usize,
+) -> Result<Edit, FillError> {
+    let loaded = |world: &World, [x, y, z]: [i32; 3]| world.contains_chunk(split_pos(x, y, z).0);
+    if !loaded(world, start) {
+        return Err(FillError::Unloaded { start });
+    }
+    let [x, y, z] = start;
+    let target = world.get(x, y, z);
+    if target == block {
+        return Ok(Edit::new());
+    }
+
+    let mut region = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(start);
+    queue.push_back(start);
+    while let Some(cell) = queue.pop_front() {
+        if region.len() == limit {
+            return Err(FillError::TooLarge { start, limit });
+        }
+        region.push(cell);
+        for axis in 0..3 {
+            for &offset in [-1, 1].iter() {
+                let mut next = cell;
+                next[axis] += offset;
+                let [x, y, z] = next;
+                if world.get(x, y, z) == target && loaded(world, next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    let mut edit = Edit::new();
+    for cell in region {
+        edit.set(world, cell, block);
+    }
+    Ok(edit)
+}
+
+pub struct Editor {
+    pub tool: Tool,
+    pub mode: EditMode,
+    /// Block placed by `Add` and `Paint`
+    pub block: BlockId,
+    /// How far away blocks can be targeted
+    pub reach: f32,
+    target: Option<RayHit>,
+    history: History,
+    /// Blocks changed since the last `take_changed`
+    changed: Vec<[i32; 3]>,
+}
+
+impl Editor {
+    pub fn new(block: BlockId) -> Self {
+        Self {
+            tool: Tool::Brush(Brush::Block),
+            mode: EditMode::Add,
+            block,
+            reach: 8.0,
+            target: None,
+            history: History::default(),
+            changed: Vec::new(),
+        }
+    }
+
+    /// Finds the targeted block along a ray, usually `Camera::ray`
+    pub fn update_target(&mut self, world: &World, ray: &Ray) -> Option<RayHit> {
+        self.target = raycast(world, ray, self.reach);
+        self.target
+    }
+
+    pub fn target(&self) -> Option<RayHit> {
+        self.target
+    }
+
+    /// Cell the tool is applied at for the current mode
+    fn center(&self, hit: &RayHit) -> [i32; 3] {
+        match self.mode {
+            EditMode::Add => hit.previous,
+            EditMode::Remove | EditMode::Paint => hit.position,
+        }
+    }
+
+    /// Applies the tool at the current target and records it in the history.
+    /// Returns how many blocks changed. The target is kept, update it before
+    /// applying again.
+    pub fn apply(&mut self, world: &mut World) -> Result<usize, FillError> {
+        let hit = match self.target {
+            Some(hit) => hit,
+            None => return Ok(0),
+        };
+        let center = self.center(&hit);
+
+        let edit = match self.tool {
+            Tool::Brush(brush) => {
+                let mut edit = Edit::new();
+                for cell in brush.cells(center) {
+                    let [x, y, z] = cell;
+                    let current = world.get(x, y, z);
+                    let block = match self.mode {
+                        EditMode::Add if current == AIR => self.block,
+                        EditMode::Remove => AIR,
+                        EditMode::Paint if current != AIR => self.block,
+                        _ => continue,
+                    };
+                    edit.set(world, cell, block);
+                }
+                edit
+            }
+            Tool::FloodFill { limit } => {
+                let block = match self.mode {
+                    EditMode::Remove => AIR,
+                    EditMode::Add | EditMode::Paint => self.block,
+                };
+                flood_fill(world, center, block, limit)?
+            }
+        };
+
+        let changed = edit.len();
+        self.changed
+            .extend(edit.changes().iter().map(|change| change.position));
+        self.history.push(edit);
+        Ok(changed)
+    }
+
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        let undone = self.history.undo(world);
+        if let (true, Some(edit)) = (undone, self.history.redo.last()) {
+            self.changed
+                .extend(edit.changes().iter().map(|change| change.position));
+        }
+        undone
+    }
+
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        let redone = self.history.redo(world);
+        if let (true, Some(edit)) = (redone, self.history.undo.back()) {
+            self.changed
+                .extend(edit.changes().iter().map(|change| change.position));
+        }
+        redone
+    }
+
+    /// Blocks changed by applying, undoing and redoing since the last call,
+    /// e.g. for `LightMap::update`
+    pub fn take_changed(&mut self) -> Vec<[i32; 3]> {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Line list outline of the targeted block, `None` without a target
+    pub fn outline(&self, color: [f32; 4]) -> Option<Mesh<VertexC>> {
+        self.target
+            .map(|hit| outline_mesh(hit.position, [1, 1, 1], color))
+    }
+}
+
+/// Edges of the box of `size` blocks starting at `min` as a line list, to be
+/// drawn with `PrimitiveTopology::LineList`. The box is grown a little so the
+/// lines aren't hidden by the faces of the block.
+pub fn outline_mesh(min: [i32; 3], size: [i32; 3], color: [f32; 4]) -> Mesh<VertexC> {
+    const GROW: f32 = 0.005;
+
+    let mut mesh = Mesh::with_capacity(8, 24);
+    for corner in 0..8 {
+        let mut position = [0.0; 3];
+        for axis in 0..3 {
+            position[axis] = if corner & (1 << axis) == 0 {
+                min[axis] as f32 - GROW
+            } else {
+                (min[axis] + size[axis]) as f32 + GROW
+            };
+        }
+        mesh.push_vertex(VertexC { position, color });
+    }
+    // every pair of corners that differs in a single axis
+    for corner in 0..8u32 {
+        for axis in 0..3 {
+            let other = corner | (1 << axis);
+            if other != corner {
+                mesh.indices.extend_from_slice(&[corner, other]);
+            }
+        }
+    }
+    mesh
+}
+
+/// GPU buffers of the target outline, rebuilt only when the target moves
+pub struct Outline {
+    color: [f32; 4],
+    position: Option<[i32; 3]>,
+    buffers: Option<(wgpu::Buffer, wgpu::Buffer)>,
+}
+
+impl Outline {
+    pub fn new(color: [f32; 4]) -> Self {
+        Self {
+            color,
+            position: None,
+            buffers: None,
+        }
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, editor: &Editor) {
+        let position = editor.target().map(|hit| hit.position);
+        if position == self.position {
+            return;
+        }
+        self.position = position;
+        self.buffers = editor.outline(self.color).map(|mesh| {
+            (
+                mesh.create_vertex_buffer(device),
+                mesh.create_index_buffer(device),
+            )
+        });
+    }
+
+    /// Draws the outline with whatever line list pipeline is set, the vertices
+    /// are `VertexC` and the indices `Uint32`
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some((vertex_buffer, index_buffer)) = &self.buffers {
+            render_pass.set_vertex_buffer(0, vertex_buffer, 0, 0);
+            render_pass.set_index_buffer(index_buffer, 0, 0);
+            render_pass.draw_indexed(0..24, 0, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16 by 16 stone floor at y 0 and the ray looking down on its center
+    fn floor() -> (World, Ray) {
+        let mut world = World::new();
+        for x in -8..8 {
+            for z in -8..8 {
+                world.set(x, 0, z, 1);
+            }
+        }
+        world.take_dirty();
+        // up is -y
+        let ray = Ray {
+            origin: uv::Vec3::new(0.5, -3.5, 0.5),
+            direction: uv::Vec3::new(0.0, 1.0, 0.0),
+        };
+        (world, ray)
+    }
+
+    #[test]
+    fn modes_add_remove_and_paint() {
+        let (mut world, ray) = floor();
+        let mut editor = Editor::new(2);
+
+        editor.update_target(&world, &ray);
+        assert_eq!(editor.apply(&mut world).unwrap(), 1);
+        assert_eq!(world.get(0, -1, 0), 2);
+
+        editor.mode = EditMode::Paint;
+        editor.block = 3;
+        editor.tool = Tool::Brush(Brush::Box { size: [3, 3, 3] });
+        editor.update_target(&world, &ray);
+        // only the solid blocks of the box around the added one
+        assert_eq!(editor.apply(&mut world).unwrap(), 10);
+        assert_eq!(world.get(1, 0, 1), 3);
+        assert_eq!(world.get(1, -1, 1), AIR);
+
+        editor.mode = EditMode::Remove;
+        editor.tool = Tool::Brush(Brush::Block);
+        editor.update_target(&world, &ray);
+        assert_eq!(editor.apply(&mut world).unwrap(), 1);
+        assert_eq!(world.get(0, -1, 0), AIR);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_blocks() {
+        let (mut world, ray) = floor();
+        let mut editor = Editor::new(2);
+        editor.update_target(&world, &ray);
+        editor.apply(&mut world).unwrap();
+        editor.take_changed();
+
+        assert!(editor.undo(&mut world));
+        assert_eq!(world.get(0, -1, 0), AIR);
+        assert!(!editor.undo(&mut world));
+        assert!(editor.redo(&mut world));
+        assert_eq!(world.get(0, -1, 0), 2);
+        assert_eq!(editor.take_changed(), vec![[0, -1, 0], [0, -1, 0]]);
+
+        // a new edit drops the undone ones
+        editor.undo(&mut world);
+        editor.update_target(&world, &ray);
+        editor.apply(&mut world).unwrap();
+        assert!(!editor.redo(&mut world));
+    }
+
+    #[test]
+    fn flood_fill_stays_within_the_limit() {
+        let (mut world, _) = floor();
+        assert_eq!(
+            flood_fill(&mut world, [0, 0, 0], 4, 100).err(),
+            Some(FillError::TooLarge {
+                start: [0, 0, 0],
+                limit: 100
+            })
+        );
+        assert_eq!(world.get(0, 0, 0), 1);
+
+        let edit = flood_fill(&mut world, [0, 0, 0], 4, 256).unwrap();
+        assert_eq!(edit.len(), 256);
+        assert_eq!(world.get(-8, 0, 7), 4);
+        assert_eq!(world.get(8, 0, 0), AIR);
+    }
+
+    #[test]
+    fn flood_fill_needs_a_loaded_start() {
+        let (mut world, _) = floor();
+        let far = 1000;
+        assert!(!world.contains_chunk(split_pos(far, 0, 0).0));
+        assert_eq!(
+            flood_fill(&mut world, [far, 0, 0], 4, 100).err(),
+            Some(FillError::Unloaded { start: [far, 0, 0] })
+        );
+        assert!(!world.contains_chunk(split_pos(far, 0, 0).0));
+    }
+
+    #[test]
+    fn outline_edges_follow_single_axes() {
+        let mesh = outline_mesh([1, 2, 3], [1, 1, 1], [1.0; 4]);
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(mesh.indices.len(), 24);
+        for line in mesh.indices.chunks(2) {
+            let a = mesh.vertices[line[0] as usize].position;
+            let b = mesh.vertices[line[1] as usize].position;
+            assert_eq!((0..3).filter(|&i| a[i] != b[i]).count(), 1);
+        }
+    }
+}
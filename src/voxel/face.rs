@@ -0,0 +1,81 @@
+/// One of the six sides of a voxel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::PosX,
+        Face::NegX,
+        Face::PosY,
+        Face::NegY,
+        Face::PosZ,
+        Face::NegZ,
+    ];
+
+    /// Position in `ALL`
+    pub fn index(self) -> usize {
+        match self {
+            Face::PosX => 0,
+            Face::NegX => 1,
+            Face::PosY => 2,
+            Face::NegY => 3,
+            Face::PosZ => 4,
+            Face::NegZ => 5,
+        }
+    }
+
+    /// 0 for x, 1 for y, 2 for z
+    pub fn axis(self) -> usize {
+        match self {
+            Face::PosX | Face::NegX => 0,
+            Face::PosY | Face::NegY => 1,
+            Face::PosZ | Face::NegZ => 2,
+        }
+    }
+
+    pub fn is_positive(self) -> bool {
+        matches!(self, Face::PosX | Face::PosY | Face::PosZ)
+    }
+
+    pub fn normal(self) -> [i32; 3] {
+        let mut normal = [0; 3];
+        normal[self.axis()] = if self.is_positive() { 1 } else { -1 };
+        normal
+    }
+
+    /// The two axes spanning the face, ordered so that u x v points along the normal
+    pub fn tangents(self) -> (usize, usize) {
+        match self {
+            Face::PosX => (1, 2),
+            Face::NegX => (2, 1),
+            Face::PosY => (2, 0),
+            Face::NegY => (0, 2),
+            Face::PosZ => (0, 1),
+            Face::NegZ => (1, 0),
+        }
+    }
+
+    /// Corners of this face on the unit cube, counter clockwise seen from outside
+    pub fn corners(self) -> [[f32; 3]; 4] {
+        let (u, v) = self.tangents();
+        let mut base = [0.0; 3];
+        base[self.axis()] = if self.is_positive() { 1.0 } else { 0.0 };
+
+        let mut corners = [base; 4];
+        for (corner, &(du, dv)) in corners
+            .iter_mut()
+            .zip([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter())
+        {
+            corner[u] = du;
+            corner[v] = dv;
+        }
+        corners
+    }
+}
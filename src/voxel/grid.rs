@@ -0,0 +1,144 @@
+use crate::geometry::mesh::Mesh;
+use crate::types::VertexC;
+use crate::voxel::face::Face;
+
+/// RGBA colors of the 255 voxel values, entry 0 is never drawn
+pub type Palette = [[u8; 4]; 256];
+
+/// Dense box of voxels holding palette indices, 0 is empty
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    size: [usize; 3],
+    voxels: Vec<u8>,
+}
+
+impl VoxelGrid {
+    pub fn new(size: [usize; 3]) -> Self {
+        Self {
+            size,
+            voxels: vec![0; size[0] * size[1] * size[2]],
+        }
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+
+    pub fn contains(&self, x: i32, y: i32, z: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && z >= 0
+            && (x as usize) < self.size[0]
+            && (y as usize) < self.size[1]
+            && (z as usize) < self.size[2]
+    }
+
+    /// Voxel value, everything outside of the grid is empty
+    pub fn get(&self, x: i32, y: i32, z: i32) -> u8 {
+        if self.contains(x, y, z) {
+            self.voxels[self.index(x as usize, y as usize, z as usize)]
+        } else {
+            0
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, value: u8) {
+        assert!(
+            x < self.size[0] && y < self.size[1] && z < self.size[2],
+            "voxel ({}, {}, {}) outside of {:?}",
+            x,
+            y,
+            z,
+            self.size
+        );
+        let index = self.index(x, y, z);
+        self.voxels[index] = value;
+    }
+
+    /// Positions and values of all non empty voxels
+    pub fn iter(&self) -> impl Iterator<Item = ([usize; 3], u8)> + '_ {
+        let [sx, sy, _] = self.size;
+        self.voxels
+            .iter()
+            .enumerate()
+            .filter(|(_, &value)| value != 0)
+            .map(move |(i, &value)| ([i % sx, i / sx % sy, i / (sx * sy)], value))
+    }
+
+    pub fn count(&self) -> usize {
+        self.voxels.iter().filter(|&&value| value != 0).count()
+    }
+
+    /// Unit cubes for the color pipeline, faces between two voxels are skipped
+    pub fn mesh(&self, palette: &Palette) -> Mesh<VertexC> {
+        let mut mesh = Mesh::new();
+        for ([x, y, z], value) in self.iter() {
+            let color = palette_color(palette, value);
+            for &face in Face::ALL.iter() {
+                let [nx, ny, nz] = face.normal();
+                if self.get(x as i32 + nx, y as i32 + ny, z as i32 + nz) != 0 {
+                    continue;
+                }
+
+                let mut ids = [0; 4];
+                for (id, corner) in ids.iter_mut().zip(face.corners().iter()) {
+                    *id = mesh.push_vertex(VertexC {
+                        position: [
+                            x as f32 + corner[0],
+                            y as f32 + corner[1],
+                            z as f32 + corner[2],
+                        ],
+                        color,
+                    });
+                }
+                mesh.push_quad(ids[0], ids[1], ids[2], ids[3]);
+            }
+        }
+        mesh
+    }
+}
+
+/// Palette entry as a 0.0 - 1.0 vertex color
+pub fn palette_color(palette: &Palette, value: u8) -> [f32; 4] {
+    let [r, g, b, a] = palette[value as usize];
+    [
+        r as f32 / 255.0,
+        g as f32 / 255.0,
+        b as f32 / 255.0,
+        a as f32 / 255.0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_has_the_outside_faces_wound_outwards() {
+        let mut grid = VoxelGrid::new([3, 4, 5]);
+        grid.set(0, 0, 0, 1);
+        grid.set(1, 0, 0, 2);
+        grid.set(2, 3, 4, 3);
+        let mesh = grid.mesh(&[[255; 4]; 256]);
+
+        // the two touching voxels hide one face each
+        assert_eq!(mesh.vertices.len(), (5 + 5 + 6) * 4);
+        for [a, b, c] in mesh.triangles() {
+            let position = |i: u32| uv::Vec3::from(mesh.vertices[i as usize].position);
+            let normal = (position(b) - position(a)).cross(position(c) - position(a));
+            let center = (position(a) + position(b) + position(c)) / 3.0;
+            let inside = center - normal.normalized() * 0.5;
+            let voxel = |v: f32| v.floor() as i32;
+            assert_ne!(
+                grid.get(voxel(inside.x), voxel(inside.y), voxel(inside.z)),
+                0,
+                "face at {:?} points inwards",
+                center
+            );
+        }
+    }
+}
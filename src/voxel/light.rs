@@ -0,0 +1,527 @@
+//! Flood fill lighting for the voxel world.
+//!
+//! Every cell that isn't opaque has a skylight and a block light level from 0
+//! to `MAX_LIGHT`. Skylight enters from the top and travels straight down
+//! through air and transparent blocks without getting weaker, block light
+//! starts at emissive blocks. Both lose a level per step in every other
+//! direction. Opaque blocks stop light, emissive ones only hold their own block
+//! light. Which blocks are opaque or emissive comes from the `BlockRegistry`.
+//!
+//! Chunks are lit when they are added and kept up to date on edits with
+//! breadth first searches that remove light that lost its source before
+//! spreading light again. Cells of chunks without light data count as open
+//! sky, so chunks that were never generated because they are all air don't
+//! throw shadows. Unloading a chunk leaves the light it gave its neighbours.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use crate::voxel::chunk::{CHUNK_SIZE, CHUNK_VOLUME};
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::world::{split_pos, world_pos, ChunkPos, World};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// How bright a light level looks, each level is 15% darker than the next
+pub fn brightness(level: u8) -> f32 {
+    0.85f32.powi(MAX_LIGHT as i32 - level.min(MAX_LIGHT) as i32)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+const CHANNELS: [Channel; 2] = [Channel::Sky, Channel::Block];
+
+/// Offsets to the six face neighbours, +y is down
+const NEIGHBOURS: [[i32; 3]; 6] = [
+    [-1, 0, 0],
+    [1, 0, 0],
+    [0, -1, 0],
+    [0, 1, 0],
+    [0, 0, -1],
+    [0, 0, 1],
+];
+
+const DOWN: [i32; 3] = [0, 1, 0];
+
+fn offset(position: [i32; 3], by: [i32; 3]) -> [i32; 3] {
+    [
+        position[0] + by[0],
+        position[1] + by[1],
+        position[2] + by[2],
+    ]
+}
+
+/// Light of one chunk, the skylight in the high and block light in the low
+/// four bits of each cell, in the same order as the chunk's blocks
+type ChunkLight = Box<[u8]>;
+
+fn light_index(local: [usize; 3]) -> usize {
+    (local[2] * CHUNK_SIZE + local[1]) * CHUNK_SIZE + local[0]
+}
+
+pub struct LightMap {
+    chunks: HashMap<ChunkPos, ChunkLight>,
+    registry: Arc<BlockRegistry>,
+}
+
+impl LightMap {
+    pub fn new(registry: Arc<BlockRegistry>) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            registry,
+        }
+    }
+
+    /// Both levels packed like `ChunkLight`, `None` for cells of unlit chunks
+    fn cell(&self, position: [i32; 3]) -> Option<u8> {
+        let (chunk, local) = split_pos(position[0], position[1], position[2]);
+        self.chunks
+            .get(&chunk)
+            .map(|light| light[light_index(local)])
+    }
+
+    fn get(&self, position: [i32; 3], channel: Channel) -> Option<u8> {
+        self.cell(position).map(|cell| match channel {
+            Channel::Sky => cell >> 4,
+            Channel::Block => cell & 0xf,
+        })
+    }
+
+    /// Only call for cells of lit chunks
+    fn set(&mut self, position: [i32; 3], channel: Channel, level: u8) {
+        let (chunk, local) = split_pos(position[0], position[1], position[2]);
+        let cell = &mut self.chunks.get_mut(&chunk).unwrap()[light_index(local)];
+        *cell = match channel {
+            Channel::Sky => (*cell & 0xf) | (level << 4),
+            Channel::Block => (*cell & 0xf0) | level,
+        };
+    }
+
+    pub fn sky(&self, x: i32, y: i32, z: i32) -> u8 {
+        self.get([x, y, z], Channel::Sky).unwrap_or(MAX_LIGHT)
+    }
+
+    pub fn block(&self, x: i32, y: i32, z: i32) -> u8 {
+        self.get([x, y, z], Channel::Block).unwrap_or(0)
+    }
+
+    /// The brighter of both levels
+    pub fn level(&self, x: i32, y: i32, z: i32) -> u8 {
+        self.sky(x, y, z).max(self.block(x, y, z))
+    }
+
+    /// Lights a chunk of the world from scratch, e.g. after it was generated or
+    /// loaded. Light spreads into lit neighbours and skylight the chunk now
+    /// blocks is taken from the chunks below it. Chunks whose light changed are
+    /// marked dirty.
+    pub fn add_chunk(&mut self, world: &mut World, pos: ChunkPos) {
+        let chunk = match world.chunk(pos) {
+            Some(chunk) => chunk,
+            None => {
+                self.chunks.remove(&pos);
+                return;
+            }
+        };
+        self.chunks
+            .insert(pos, vec![0; CHUNK_VOLUME].into_boxed_slice());
+
+        let mut touched = HashSet::new();
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+        let size = CHUNK_SIZE as i32;
+        let origin = world_pos(pos, [0, 0, 0]);
+
+        // skylight straight down from above
+        for z in 0..size {
+            for x in 0..size {
+                let above = [origin[0] + x, origin[1] - 1, origin[2] + z];
+                if self.get(above, Channel::Sky).unwrap_or(MAX_LIGHT) != MAX_LIGHT {
+                    continue;
+                }
+                for y in 0..size {
+                    if self
+                        .registry
+                        .is_opaque(chunk.get(x as usize, y as usize, z as usize))
+                    {
+                        break;
+                    }
+                    let position = [origin[0] + x, origin[1] + y, origin[2] + z];
+                    self.set(position, Channel::Sky, MAX_LIGHT);
+                    sky.push_back(position);
+                }
+            }
+        }
+
+        if chunk.palette().any(|b| self.registry.emission(b) > 0) {
+            for (local, b) in chunk.iter() {
+                let level = self.registry.emission(b);
+                if level > 0 {
+                    let position = world_pos(pos, local);
+                    self.set(position, Channel::Block, level);
+                    block.push_back(position);
+                }
+            }
+        }
+
+        // light from the borders of lit neighbours
+        for &direction in NEIGHBOURS.iter() {
+            let neighbour = offset(pos, direction);
+            if !self.chunks.contains_key(&neighbour) {
+                continue;
+            }
+            let axis = direction.iter().position(|&d| d != 0).unwrap();
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for j in 0..size {
+                for i in 0..size {
+                    let mut position = origin;
+                    position[axis] += if direction[axis] < 0 { -1 } else { size };
+                    position[u] += i;
+                    position[v] += j;
+                    if self.get(position, Channel::Sky).unwrap() > 1 {
+                        sky.push_back(position);
+                    }
+                    if self.get(position, Channel::Block).unwrap() > 1 {
+                        block.push_back(position);
+                    }
+                }
+            }
+        }
+
+        // the chunk below lit its top from the open sky it saw here before
+        let below = offset(pos, DOWN);
+        let mut removal = VecDeque::new();
+        if self.chunks.contains_key(&below) {
+            for z in 0..size {
+                for x in 0..size {
+                    let bottom = [origin[0] + x, origin[1] + size - 1, origin[2] + z];
+                    let top = offset(bottom, DOWN);
+                    if self.get(top, Channel::Sky) == Some(MAX_LIGHT)
+                        && self.get(bottom, Channel::Sky) != Some(MAX_LIGHT)
+                    {
+                        self.set(top, Channel::Sky, 0);
+                        touch(&mut touched, top);
+                        removal.push_back((top, MAX_LIGHT));
+                    }
+                }
+            }
+        }
+
+        self.unpropagate(world, Channel::Sky, removal, &mut sky, &mut touched);
+        self.propagate(world, Channel::Sky, sky, &mut touched);
+        self.propagate(world, Channel::Block, block, &mut touched);
+        mark_dirty(world, touched);
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) {
+        self.chunks.remove(&pos);
+    }
+
+    /// Updates the light after the blocks at `positions` changed, e.g. from
+    /// `Editor::take_changed`. Chunks whose light changed are marked dirty.
+    pub fn update<I>(&mut self, world: &mut World, positions: I)
+    where
+        I: IntoIterator<Item = [i32; 3]>,
+    {
+        let mut touched = HashSet::new();
+        let mut removals = [VecDeque::new(), VecDeque::new()];
+        let mut additions = [VecDeque::new(), VecDeque::new()];
+
+        for position in positions {
+            let (chunk, _) = split_pos(position[0], position[1], position[2]);
+            if !self.chunks.contains_key(&chunk) {
+                // a chunk the edit created
+                if world.contains_chunk(chunk) {
+                    self.add_chunk(world, chunk);
+                }
+                continue;
+            }
+
+            for (i, &channel) in CHANNELS.iter().enumerate() {
+                let level = self.get(position, channel).unwrap();
+                if level > 0 {
+                    self.set(position, channel, 0);
+                    touch(&mut touched, position);
+                    removals[i].push_back((position, level));
+                }
+            }
+
+            let block = world.get(position[0], position[1], position[2]);
+            let emitted = self.registry.emission(block);
+            if emitted > 0 {
+                self.set(position, Channel::Block, emitted);
+                touch(&mut touched, position);
+                additions[1].push_back(position);
+            }
+            if self.registry.is_opaque(block) {
+                continue;
+            }
+            // let the neighbours shine into the cell again
+            for &direction in NEIGHBOURS.iter() {
+                let neighbour = offset(position, direction);
+                if self.cell(neighbour).is_some() {
+                    additions[0].push_back(neighbour);
+                    additions[1].push_back(neighbour);
+                } else if direction == [0, -1, 0] {
+                    // open sky above
+                    self.set(position, Channel::Sky, MAX_LIGHT);
+                    additions[0].push_back(position);
+                }
+            }
+        }
+
+        let [sky_removal, block_removal] = removals;
+        let [mut sky, mut block] = additions;
+        self.unpropagate(world, Channel::Sky, sky_removal, &mut sky, &mut touched);
+        self.unpropagate(
+            world,
+            Channel::Block,
+            block_removal,
+            &mut block,
+            &mut touched,
+        );
+        self.propagate(world, Channel::Sky, sky, &mut touched);
+        self.propagate(world, Channel::Block, block, &mut touched);
+        mark_dirty(world, touched);
+    }
+
+    /// Spreads light outwards from the queued cells
+    fn propagate(
+        &mut self,
+        world: &World,
+        channel: Channel,
+        mut queue: VecDeque<[i32; 3]>,
+        touched: &mut HashSet<ChunkPos>,
+    ) {
+        while let Some(position) = queue.pop_front() {
+            let level = self.get(position, channel).unwrap_or(0);
+            if level <= 1 {
+                continue;
+            }
+            for &direction in NEIGHBOURS.iter() {
+                let neighbour = offset(position, direction);
+                let current = match self.get(neighbour, channel) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                if self
+                    .registry
+                    .is_opaque(world.get(neighbour[0], neighbour[1], neighbour[2]))
+                {
+                    continue;
+                }
+                let spread = if channel == Channel::Sky && direction == DOWN && level == MAX_LIGHT {
+                    MAX_LIGHT
+                } else {
+                    level - 1
+                };
+                if current < spread {
+                    self.set(neighbour, channel, spread);
+                    touch(touched, neighbour);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    /// Darkens the cells that got their light from the removed cells, queueing
+    /// brighter cells at the edge of the darkened area and emissive blocks in
+    /// it to spread light back in
+    fn unpropagate(
+        &mut self,
+        world: &World,
+        channel: Channel,
+        mut removal: VecDeque<([i32; 3], u8)>,
+        additions: &mut VecDeque<[i32; 3]>,
+        touched: &mut HashSet<ChunkPos>,
+    ) {
+        while let Some((position, level)) = removal.pop_front() {
+            for &direction in NEIGHBOURS.iter() {
+                let neighbour = offset(position, direction);
+                let current = match self.get(neighbour, channel) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                if current == 0 {
+                    continue;
+                }
+                let sunbeam = channel == Channel::Sky
+                    && direction == DOWN
+                    && level == MAX_LIGHT
+                    && current == MAX_LIGHT;
+                if current < level || sunbeam {
+                    self.set(neighbour, channel, 0);
+                    touch(touched, neighbour);
+                    removal.push_back((neighbour, current));
+
+                    if channel == Channel::Block {
+                        let block = world.get(neighbour[0], neighbour[1], neighbour[2]);
+                        let emitted = self.registry.emission(block);
+                        if emitted > 0 {
+                            self.set(neighbour, channel, emitted);
+                            additions.push_back(neighbour);
+                        }
+                    }
+                } else {
+                    additions.push_back(neighbour);
+                }
+            }
+        }
+    }
+}
+
+/// Remembers the chunk of a cell whose light changed, along with the
+/// neighbours that show it on their border
+fn touch(touched: &mut HashSet<ChunkPos>, position: [i32; 3]) {
+    let (chunk, local) = split_pos(position[0], position[1], position[2]);
+    touched.insert(chunk);
+    for axis in 0..3 {
+        let mut neighbour = chunk;
+        if local[axis] == 0 {
+            neighbour[axis] -= 1;
+        } else if local[axis] == CHUNK_SIZE - 1 {
+            neighbour[axis] += 1;
+        } else {
+            continue;
+        }
+        touched.insert(neighbour);
+    }
+}
+
+fn mark_dirty(world: &mut World, touched: HashSet<ChunkPos>) {
+    for chunk in touched {
+        world.mark_dirty(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::{BlockId, AIR};
+
+    const STONE: BlockId = 1;
+    const LAMP: BlockId = 8;
+
+    /// 2 by 2 by 2 chunks of stone from y 20 down with a pit under open sky
+    /// and a lamp lighting a cave
+    fn scene() -> World {
+        let mut world = World::new();
+        for x in 0..64 {
+            for z in 0..64 {
+                for y in 20..64 {
+                    world.set(x, y, z, STONE);
+                }
+            }
+        }
+        for x in 8..12 {
+            for z in 8..12 {
+                for y in 20..30 {
+                    world.set(x, y, z, AIR);
+                }
+            }
+        }
+        for x in 36..44 {
+            for z in 36..44 {
+                for y in 40..44 {
+                    world.set(x, y, z, AIR);
+                }
+            }
+        }
+        world.set(40, 42, 40, LAMP);
+        world
+    }
+
+    fn chunk_positions(world: &World) -> Vec<ChunkPos> {
+        let mut positions: Vec<ChunkPos> = world.chunks().map(|(&pos, _)| pos).collect();
+        positions.sort();
+        positions
+    }
+
+    fn lit(world: &mut World) -> LightMap {
+        let mut light = LightMap::new(Arc::new(BlockRegistry::builtin()));
+        for pos in chunk_positions(world) {
+            light.add_chunk(world, pos);
+        }
+        light
+    }
+
+    /// Sets a block, updates `light` for it and checks the result against a
+    /// light map lit from scratch
+    fn set_and_compare(
+        world: &mut World,
+        light: &mut LightMap,
+        position: [i32; 3],
+        block: BlockId,
+    ) {
+        let [x, y, z] = position;
+        world.set(x, y, z, block);
+        light.update(world, vec![position]);
+
+        let relit = lit(world);
+        for pos in chunk_positions(world) {
+            for (local, _) in world.chunk(pos).unwrap().iter() {
+                let [x, y, z] = world_pos(pos, local);
+                assert_eq!(
+                    (light.sky(x, y, z), light.block(x, y, z)),
+                    (relit.sky(x, y, z), relit.block(x, y, z)),
+                    "sky and block light at {:?} after setting {:?} to {}",
+                    [x, y, z],
+                    position,
+                    block
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_under_open_sky() {
+        let mut world = scene();
+        let mut light = lit(&mut world);
+        assert_eq!(light.sky(9, 29, 9), MAX_LIGHT);
+
+        // a roof over the pit shades it
+        set_and_compare(&mut world, &mut light, [9, 19, 9], STONE);
+        assert!(light.sky(9, 29, 9) < MAX_LIGHT);
+        set_and_compare(&mut world, &mut light, [9, 19, 9], AIR);
+        assert_eq!(light.sky(9, 29, 9), MAX_LIGHT);
+
+        // and so does a block at the bottom of a chunk
+        set_and_compare(&mut world, &mut light, [10, 31, 10], AIR);
+        set_and_compare(&mut world, &mut light, [10, 5, 10], STONE);
+        set_and_compare(&mut world, &mut light, [10, 5, 10], AIR);
+    }
+
+    #[test]
+    fn blocks_next_to_an_emitter() {
+        let mut world = scene();
+        let mut light = lit(&mut world);
+        assert_eq!(light.block(41, 42, 40), 13);
+
+        set_and_compare(&mut world, &mut light, [41, 42, 40], STONE);
+        assert_eq!(light.block(41, 42, 40), 0);
+        set_and_compare(&mut world, &mut light, [41, 42, 40], AIR);
+        assert_eq!(light.block(41, 42, 40), 13);
+
+        // walling the lamp in darkens the cave
+        for &position in [
+            [39, 42, 40],
+            [41, 42, 40],
+            [40, 41, 40],
+            [40, 43, 40],
+            [40, 42, 39],
+        ]
+        .iter()
+        {
+            set_and_compare(&mut world, &mut light, position, STONE);
+        }
+        assert!(light.block(43, 42, 40) > 0);
+        set_and_compare(&mut world, &mut light, [40, 42, 41], STONE);
+        assert_eq!(light.block(43, 42, 40), 0);
+
+        set_and_compare(&mut world, &mut light, [40, 42, 40], AIR);
+        set_and_compare(&mut world, &mut light, [37, 41, 37], LAMP);
+        assert_eq!(light.block(37, 41, 37), 14);
+    }
+}
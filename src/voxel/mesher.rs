@@ -0,0 +1,830 @@
+//! Turns chunks into meshes for `voxel_vertex.glsl` and `voxel_fragment.glsl`.
+//!
+//! Both meshers only emit faces of solid blocks that aren't covered by an
+//! opaque neighbour, looking one block into the neighbouring chunks so there
+//! are no walls along chunk borders. Which blocks are solid, opaque and what
+//! color they have comes from the `BlockRegistry`. `mesh_greedy` merges
+//! coplanar faces of the same block type into as few rectangles as possible,
+//! the tests check it against a naive mesher with one quad per visible face.
+//!
+//! Every corner gets an ambient occlusion level from the three opaque blocks in
+//! front of the face that touch it, so faces only merge if their corners match.
+//! Faces between two blocks of the same transparent type are left out, so glass
+//! walls don't show their insides.
+//!
+//! Distant chunks are meshed from a `Neighbourhood::downsampled` copy with
+//! fewer, bigger cells. Neighbouring chunks at different levels of detail
+//! don't line up, so chunks can be meshed with skirts: faces of surface cells
+//! on the chunk border are kept even if the neighbour covers them. That is a
+//! wall one cell deep under the surface along every side of the chunk, which
+//! hides a crack as long as the two surfaces are at most one cell of the
+//! coarser chunk apart. Deeper cracks, e.g. on cliffs, stay open. The walls
+//! are kept on every side, also towards neighbours at the same level of
+//! detail, where they are hidden inside the terrain.
+//!
+//! With light copied in through `Neighbourhood::with_light` every face is
+//! darkened by the light level of the cell in front of it.
+//!
+//! Faces of blocks with textures get the texture array layer the registry
+//! resolved for their side and texture coordinates that repeat every block,
+//! so merged faces tile their texture.
+use crate::geometry::mesh::Mesh;
+use crate::types::VertexAO;
+use crate::voxel::chunk::{BlockId, AIR, CHUNK_SIZE};
+use crate::voxel::face::Face;
+use crate::voxel::light::{brightness, LightMap, MAX_LIGHT};
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::world::{world_pos, ChunkPos, World};
+
+/// Brightness of a corner by occlusion level, 0 is the darkest
+const AO_BRIGHTNESS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+/// Texture layer of blocks without textures, `voxel_fragment.glsl` leaves
+/// their color as it is
+const UNTEXTURED: f32 = -1.0;
+
+/// Rectangle corners as signs along the face's tangents, counter clockwise
+const CORNERS: [(i32, i32); 4] = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+
+/// What has to match for two faces of a slice to be merged
+#[derive(Clone, Copy, PartialEq)]
+struct FaceKey {
+    block: BlockId,
+    ao: [u8; 4],
+    light: u8,
+}
+
+/// Copy of a chunk with a one cell border taken from its neighbours, so it
+/// can be meshed without access to the world. At level of detail `lod` every
+/// cell stands for a cube of 2^lod blocks.
+#[derive(Clone)]
+pub struct Neighbourhood {
+    pub chunk: ChunkPos,
+    lod: u32,
+    /// Cells along each axis inside of the chunk
+    size: usize,
+    skirts: bool,
+    blocks: Vec<BlockId>,
+    /// Light levels of the cells, everything is fully lit without them
+    light: Option<Vec<u8>>,
+}
+
+impl Neighbourhood {
+    fn filled_with_air(chunk: ChunkPos, lod: u32, skirts: bool) -> Self {
+        let size = CHUNK_SIZE >> lod;
+        let padded = size + 2;
+        Self {
+            chunk,
+            lod,
+            size,
+            skirts,
+            blocks: vec![AIR; padded * padded * padded],
+            light: None,
+        }
+    }
+
+    pub fn from_world(world: &World, chunk: ChunkPos) -> Self {
+        let mut blocks = Self::filled_with_air(chunk, 0, false);
+        let origin = world_pos(chunk, [0, 0, 0]);
+
+        if let Some(center) = world.chunk(chunk) {
+            for ([x, y, z], block) in center.iter() {
+                let index = blocks.index(x as i32, y as i32, z as i32);
+                blocks.blocks[index] = block;
+            }
+        }
+
+        // only the border has to be read through the slower world lookup
+        let border = |i: i32| i == -1 || i == CHUNK_SIZE as i32;
+        for z in -1..=CHUNK_SIZE as i32 {
+            for y in -1..=CHUNK_SIZE as i32 {
+                for x in -1..=CHUNK_SIZE as i32 {
+                    if border(x) || border(y) || border(z) {
+                        let index = blocks.index(x, y, z);
+                        blocks.blocks[index] =
+                            world.get(origin[0] + x, origin[1] + y, origin[2] + z);
+                    }
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Copy at a coarser level of detail. A cell becomes the most common solid
+    /// block of the cube it covers if at least half of that cube is solid.
+    /// Border cells only see the single layer of neighbouring blocks that was
+    /// copied.
+    pub fn downsampled(&self, lod: u32, registry: &BlockRegistry) -> Self {
+        assert!(
+            lod >= self.lod && CHUNK_SIZE >> lod > 0,
+            "can't downsample level of detail {} to {}",
+            self.lod,
+            lod
+        );
+        let factor = 1 << (lod - self.lod);
+        let mut result = Self::filled_with_air(self.chunk, lod, self.skirts);
+        let size = result.size as i32;
+
+        // the cells of this copy that a cell of the result covers along one axis
+        let cover = |c: i32| {
+            if c < 0 {
+                -1..0
+            } else if c >= size {
+                self.size as i32..self.size as i32 + 1
+            } else {
+                c * factor..(c + 1) * factor
+            }
+        };
+
+        let mut counts: Vec<(BlockId, u32)> = Vec::new();
+        for z in -1..=size {
+            for y in -1..=size {
+                for x in -1..=size {
+                    counts.clear();
+                    let (mut total, mut solid) = (0, 0);
+                    for sz in cover(z) {
+                        for sy in cover(y) {
+                            for sx in cover(x) {
+                                total += 1;
+                                let block = self.get(sx, sy, sz);
+                                if !registry.is_solid(block) {
+                                    continue;
+                                }
+                                solid += 1;
+                                match counts.iter_mut().find(|(b, _)| *b == block) {
+                                    Some(entry) => entry.1 += 1,
+                                    None => counts.push((block, 1)),
+                                }
+                            }
+                        }
+                    }
+
+                    if solid * 2 >= total {
+                        let index = result.index(x, y, z);
+                        result.blocks[index] = counts
+                            .iter()
+                            .max_by_key(|&&(_, count)| count)
+                            .map_or(AIR, |&(block, _)| block);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Copies the light of the chunk and its border. Only used at full detail,
+    /// downsampled copies are fully lit.
+    pub fn with_light(mut self, light: &LightMap) -> Self {
+        if self.lod > 0 {
+            return self;
+        }
+        let origin = world_pos(self.chunk, [0, 0, 0]);
+        let mut levels = vec![0; self.blocks.len()];
+        for z in -1..=CHUNK_SIZE as i32 {
+            for y in -1..=CHUNK_SIZE as i32 {
+                for x in -1..=CHUNK_SIZE as i32 {
+                    levels[self.index(x, y, z)] =
+                        light.level(origin[0] + x, origin[1] + y, origin[2] + z);
+                }
+            }
+        }
+        self.light = Some(levels);
+        self
+    }
+
+    /// Keeps the faces of surface cells on the chunk border, see the module docs
+    pub fn with_skirts(mut self, skirts: bool) -> Self {
+        self.skirts = skirts;
+        self
+    }
+
+    /// Blocks along each axis of one cell
+    pub fn scale(&self) -> i32 {
+        1 << self.lod
+    }
+
+    fn index(&self, x: i32, y: i32, z: i32) -> usize {
+        let padded = self.size + 2;
+        ((z + 1) as usize * padded + (y + 1) as usize) * padded + (x + 1) as usize
+    }
+
+    /// Cell at a chunk local position, -1 and `size` reach into the neighbours
+    pub fn get(&self, x: i32, y: i32, z: i32) -> BlockId {
+        self.blocks[self.index(x, y, z)]
+    }
+
+    fn light(&self, position: [i32; 3]) -> u8 {
+        match &self.light {
+            Some(levels) => levels[self.index(position[0], position[1], position[2])],
+            None => MAX_LIGHT,
+        }
+    }
+
+    fn is_opaque(&self, registry: &BlockRegistry, position: [i32; 3]) -> bool {
+        registry.is_opaque(self.get(position[0], position[1], position[2]))
+    }
+
+    fn is_outside(&self, position: [i32; 3]) -> bool {
+        position.iter().any(|&c| c < 0 || c >= self.size as i32)
+    }
+
+    /// Whether a cell touches a cell that isn't opaque on any side
+    fn is_surface(&self, registry: &BlockRegistry, position: [i32; 3]) -> bool {
+        Face::ALL.iter().any(|face| {
+            let normal = face.normal();
+            !self.is_opaque(
+                registry,
+                [
+                    position[0] + normal[0],
+                    position[1] + normal[1],
+                    position[2] + normal[2],
+                ],
+            )
+        })
+    }
+
+    /// The block, corner occlusion and light of `face` at `position`, if it can
+    /// be seen
+    fn visible_face(
+        &self,
+        registry: &BlockRegistry,
+        position: [i32; 3],
+        face: Face,
+    ) -> Option<FaceKey> {
+        let block = self.get(position[0], position[1], position[2]);
+        if !registry.is_solid(block) {
+            return None;
+        }
+        let normal = face.normal();
+        let front = [
+            position[0] + normal[0],
+            position[1] + normal[1],
+            position[2] + normal[2],
+        ];
+        let skirt = self.skirts && self.is_outside(front) && self.is_surface(registry, position);
+        let neighbour = self.get(front[0], front[1], front[2]);
+        let covered = registry.is_opaque(neighbour)
+            || (neighbour == block && registry.get(block).transparent);
+        if covered && !skirt {
+            return None;
+        }
+
+        let (u, v) = face.tangents();
+        let mut ao = [0; 4];
+        for (level, &(su, sv)) in ao.iter_mut().zip(CORNERS.iter()) {
+            let mut side_u = front;
+            side_u[u] += su;
+            let mut side_v = front;
+            side_v[v] += sv;
+            let mut corner = side_u;
+            corner[v] += sv;
+            *level = occlusion(
+                self.is_opaque(registry, side_u),
+                self.is_opaque(registry, side_v),
+                self.is_opaque(registry, corner),
+            );
+        }
+        Some(FaceKey {
+            block,
+            ao,
+            light: self.light(front),
+        })
+    }
+}
+
+/// Visible faces merged into maximal rectangles of the same block type,
+/// occlusion and light
+pub fn mesh_greedy(blocks: &Neighbourhood, registry: &BlockRegistry) -> Mesh<VertexAO> {
+    let mut mesh = Mesh::new();
+    let origin = world_pos(blocks.chunk, [0, 0, 0]);
+    let size = blocks.size;
+    let mut mask: Vec<Option<FaceKey>> = vec![None; size * size];
+
+    for &face in Face::ALL.iter() {
+        let axis = face.axis();
+        let (u, v) = face.tangents();
+
+        for slice in 0..size {
+            for j in 0..size {
+                for i in 0..size {
+                    let mut position = [0; 3];
+                    position[axis] = slice as i32;
+                    position[u] = i as i32;
+                    position[v] = j as i32;
+                    mask[j * size + i] = blocks.visible_face(registry, position, face);
+                }
+            }
+
+            for j in 0..size {
+                let mut i = 0;
+                while i < size {
+                    let key = match mask[j * size + i] {
+                        Some(key) => key,
+                        None => {
+                            i += 1;
+                            continue;
+                        }
+                    };
+
+                    let mut width = 1;
+                    while i + width < size && mask[j * size + i + width] == Some(key) {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    'grow: while j + height < size {
+                        for k in 0..width {
+                            if mask[(j + height) * size + i + k] != Some(key) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+
+                    for row in 0..height {
+                        for k in 0..width {
+                            mask[(j + row) * size + i + k] = None;
+                        }
+                    }
+
+                    let mut start = [0; 3];
+                    start[axis] = slice as i32 + if face.is_positive() { 1 } else { 0 };
+                    start[u] = i as i32;
+                    start[v] = j as i32;
+                    let rect = Rect {
+                        start,
+                        axes: (u, v),
+                        size: [width, height],
+                    };
+                    let color = lit(registry.color(key.block), key.light);
+                    let texture = registry.get(key.block).texture(face);
+                    push_quad(
+                        &mut mesh,
+                        origin,
+                        blocks.scale(),
+                        &rect,
+                        color,
+                        key.ao,
+                        texture,
+                    );
+
+                    i += width;
+                }
+            }
+        }
+    }
+    mesh
+}
+
+fn lit(color: [f32; 4], light: u8) -> [f32; 4] {
+    let brightness = brightness(light);
+    [
+        color[0] * brightness,
+        color[1] * brightness,
+        color[2] * brightness,
+        color[3],
+    ]
+}
+
+/// Rectangle in a plane of the chunk, starting at the chunk local corner
+/// `start` and spanning `size` cells along the two `axes`
+struct Rect {
+    start: [i32; 3],
+    axes: (usize, usize),
+    size: [usize; 2],
+}
+
+/// Occlusion level of a corner from the blocks touching it in front of the face,
+/// two sides block the corner block's light no matter what it is
+fn occlusion(side_u: bool, side_v: bool, corner: bool) -> u8 {
+    if side_u && side_v {
+        0
+    } else {
+        3 - side_u as u8 - side_v as u8 - corner as u8
+    }
+}
+
+/// Texture coordinates of a chunk local corner in blocks. On the sides the
+/// second one runs along +y, which is down, so textures stand upright.
+fn tex_coords(axis: usize, corner: [i32; 3], scale: i32) -> [f32; 2] {
+    let [s, t] = match axis {
+        0 => [corner[2], corner[1]],
+        1 => [corner[0], corner[2]],
+        _ => [corner[0], corner[1]],
+    };
+    [(s * scale) as f32, (t * scale) as f32]
+}
+
+/// Counter clockwise as the first axis crossed with the second faces out.
+/// The quad is split along the diagonal whose corners are brighter, otherwise
+/// a single dark corner would bleed along the whole diagonal. The texture, a
+/// layer of the block texture array, repeats once per block.
+fn push_quad(
+    mesh: &mut Mesh<VertexAO>,
+    origin: [i32; 3],
+    scale: i32,
+    rect: &Rect,
+    color: [f32; 4],
+    ao: [u8; 4],
+    texture: Option<u32>,
+) {
+    let (u, v) = rect.axes;
+    let axis = 3 - u - v;
+    let [width, height] = rect.size;
+    let mut ids = [0; 4];
+    for ((id, &(su, sv)), &level) in ids.iter_mut().zip(CORNERS.iter()).zip(ao.iter()) {
+        let mut corner = rect.start;
+        if su > 0 {
+            corner[u] += width as i32;
+        }
+        if sv > 0 {
+            corner[v] += height as i32;
+        }
+        *id = mesh.push_vertex(VertexAO {
+            position: [
+                (origin[0] + corner[0] * scale) as f32,
+                (origin[1] + corner[1] * scale) as f32,
+                (origin[2] + corner[2] * scale) as f32,
+            ],
+            color,
+            ao: AO_BRIGHTNESS[level as usize],
+            tex_coords: tex_coords(axis, corner, scale),
+            layer: texture.map_or(UNTEXTURED, |layer| layer as f32),
+        });
+    }
+    if ao[0] + ao[2] >= ao[1] + ao[3] {
+        mesh.push_quad(ids[0], ids[1], ids[2], ids[3]);
+    } else {
+        mesh.push_quad(ids[1], ids[2], ids[3], ids[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::voxel::chunk::Chunk;
+
+    /// One quad per visible block face, the reference for `mesh_greedy`
+    fn mesh_naive(blocks: &Neighbourhood, registry: &BlockRegistry) -> Mesh<VertexAO> {
+        let mut mesh = Mesh::new();
+        let origin = world_pos(blocks.chunk, [0, 0, 0]);
+        let size = blocks.size as i32;
+
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    for &face in Face::ALL.iter() {
+                        if let Some(key) = blocks.visible_face(registry, [x, y, z], face) {
+                            let mut start = [x, y, z];
+                            if face.is_positive() {
+                                start[face.axis()] += 1;
+                            }
+                            let rect = Rect {
+                                start,
+                                axes: face.tangents(),
+                                size: [1, 1],
+                            };
+                            let color = lit(registry.color(key.block), key.light);
+                            let texture = registry.get(key.block).texture(face);
+                            push_quad(
+                                &mut mesh,
+                                origin,
+                                blocks.scale(),
+                                &rect,
+                                color,
+                                key.ao,
+                                texture,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Unit square of a face: its minimum corner, outward normal and color bits
+    type UnitFace = ([i32; 3], [i32; 3], [u32; 4]);
+
+    /// xorshift, good enough to scatter blocks
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    /// Random blocks in the chunk at the origin and all of its neighbours, so
+    /// the border faces depend on other chunks. Glass and leaves are in the
+    /// mix so transparent culling is covered as well.
+    fn random_world(seed: u32, percent_solid: u32) -> World {
+        const BLOCKS: [BlockId; 4] = [1, 2, 6, 7];
+        let mut rng = Rng(seed);
+        let mut world = World::new();
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let mut chunk = Chunk::empty();
+                    for ([bx, by, bz], _) in Chunk::empty().iter() {
+                        if rng.next() % 100 < percent_solid {
+                            chunk.set(bx, by, bz, BLOCKS[rng.next() as usize % BLOCKS.len()]);
+                        }
+                    }
+                    world.insert_chunk([x, y, z], chunk);
+                }
+            }
+        }
+        world
+    }
+
+    /// Splits every quad of a mesh into the unit squares it covers
+    fn unit_faces(mesh: &Mesh<VertexAO>) -> Vec<UnitFace> {
+        let triangles: Vec<[u32; 3]> = mesh.triangles().collect();
+        let mut faces = Vec::new();
+        for (quad, corners) in mesh.vertices.chunks(4).enumerate() {
+            let position = |i: u32| uv::Vec3::from(mesh.vertices[i as usize].position);
+            let [a, b, c] = triangles[quad * 2];
+            let normal = (position(b) - position(a)).cross(position(c) - position(a));
+            let normal = [
+                normal.x.signum() as i32 * (normal.x != 0.0) as i32,
+                normal.y.signum() as i32 * (normal.y != 0.0) as i32,
+                normal.z.signum() as i32 * (normal.z != 0.0) as i32,
+            ];
+
+            let mut min = [i32::MAX; 3];
+            let mut max = [i32::MIN; 3];
+            for corner in corners {
+                for k in 0..3 {
+                    min[k] = min[k].min(corner.position[k] as i32);
+                    max[k] = max[k].max(corner.position[k] as i32);
+                }
+            }
+            let color = corners[0].color;
+            let color = [
+                color[0].to_bits(),
+                color[1].to_bits(),
+                color[2].to_bits(),
+                color[3].to_bits(),
+            ];
+
+            let axis = (0..3).find(|&k| normal[k] != 0).unwrap();
+            assert_eq!(min[axis], max[axis], "quad {} isn't flat", quad);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for i in min[u]..max[u] {
+                for j in min[v]..max[v] {
+                    let mut corner = min;
+                    corner[u] = i;
+                    corner[v] = j;
+                    faces.push((corner, normal, color));
+                }
+            }
+        }
+        faces
+    }
+
+    /// The faces of the chunk at the origin worked out block by block from the
+    /// world instead of a `Neighbourhood`
+    fn expected_faces(world: &World, registry: &BlockRegistry) -> HashSet<UnitFace> {
+        let mut faces = HashSet::new();
+        for ([x, y, z], block) in world.chunk([0, 0, 0]).unwrap().iter() {
+            if !registry.is_solid(block) {
+                continue;
+            }
+            let position = [x as i32, y as i32, z as i32];
+            for &face in Face::ALL.iter() {
+                let normal = face.normal();
+                let neighbour = world.get(
+                    position[0] + normal[0],
+                    position[1] + normal[1],
+                    position[2] + normal[2],
+                );
+                if registry.is_opaque(neighbour)
+                    || (neighbour == block && registry.get(block).transparent)
+                {
+                    continue;
+                }
+                let mut corner = position;
+                if face.is_positive() {
+                    corner[face.axis()] += 1;
+                }
+                let color = registry.color(block);
+                let color = [
+                    color[0].to_bits(),
+                    color[1].to_bits(),
+                    color[2].to_bits(),
+                    color[3].to_bits(),
+                ];
+                faces.insert((corner, normal, color));
+            }
+        }
+        faces
+    }
+
+    fn area(mesh: &Mesh<VertexAO>) -> f32 {
+        mesh.triangles()
+            .map(|[a, b, c]| {
+                let position = |i: u32| uv::Vec3::from(mesh.vertices[i as usize].position);
+                (position(b) - position(a))
+                    .cross(position(c) - position(a))
+                    .mag()
+                    / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn greedy_covers_the_faces_of_naive() {
+        let registry = BlockRegistry::builtin();
+        for &(seed, percent_solid) in [(1, 5), (2, 30), (3, 60), (4, 95)].iter() {
+            let world = random_world(seed, percent_solid);
+            let blocks = Neighbourhood::from_world(&world, [0, 0, 0]);
+            let naive = mesh_naive(&blocks, &registry);
+            let greedy = mesh_greedy(&blocks, &registry);
+
+            let expected = expected_faces(&world, &registry);
+            let naive_faces = unit_faces(&naive);
+            let greedy_faces = unit_faces(&greedy);
+            assert_eq!(naive_faces.len(), expected.len(), "seed {}", seed);
+            assert_eq!(greedy_faces.len(), expected.len(), "seed {}", seed);
+            assert_eq!(naive_faces.into_iter().collect::<HashSet<_>>(), expected);
+            assert_eq!(greedy_faces.into_iter().collect::<HashSet<_>>(), expected);
+
+            assert_eq!(area(&naive), expected.len() as f32);
+            assert_eq!(area(&greedy), expected.len() as f32);
+            assert!(greedy.num_indices() <= naive.num_indices());
+        }
+    }
+
+    #[test]
+    fn neighbouring_chunks_cull_border_faces() {
+        let registry = BlockRegistry::builtin();
+        let mut world = World::new();
+        for &chunk in [[0, 0, 0], [1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0]].iter() {
+            world.insert_chunk(chunk, Chunk::filled(1));
+        }
+        // glass next door doesn't hide anything
+        world.insert_chunk([0, 0, 1], Chunk::filled(6));
+
+        let blocks = Neighbourhood::from_world(&world, [0, 0, 0]);
+        for mesh in [
+            mesh_naive(&blocks, &registry),
+            mesh_greedy(&blocks, &registry),
+        ]
+        .iter()
+        {
+            let faces = unit_faces(mesh);
+            assert_eq!(faces.len(), 2 * CHUNK_SIZE * CHUNK_SIZE);
+            assert!(faces
+                .iter()
+                .all(|(_, normal, _)| normal[0] == 0 && normal[1] == 0));
+            assert_eq!(
+                faces.iter().filter(|(_, normal, _)| normal[2] > 0).count(),
+                CHUNK_SIZE * CHUNK_SIZE
+            );
+        }
+        assert_eq!(mesh_greedy(&blocks, &registry).num_indices(), 12);
+    }
+
+    #[test]
+    fn two_sides_occlude_a_corner_fully() {
+        assert_eq!(occlusion(false, false, false), 3);
+        assert_eq!(occlusion(false, false, true), 2);
+        assert_eq!(occlusion(true, false, false), 2);
+        assert_eq!(occlusion(false, true, true), 1);
+        assert_eq!(occlusion(true, true, false), 0);
+        assert_eq!(occlusion(true, true, true), 0);
+    }
+
+    /// The two corners both triangles of the only quad of a mesh share
+    fn diagonal(mesh: &Mesh<VertexAO>) -> Vec<u32> {
+        let triangles: Vec<[u32; 3]> = mesh.triangles().collect();
+        assert_eq!(triangles.len(), 2);
+        let mut shared: Vec<u32> = triangles[0]
+            .iter()
+            .copied()
+            .filter(|i| triangles[1].contains(i))
+            .collect();
+        shared.sort();
+        shared
+    }
+
+    #[test]
+    fn quads_split_along_the_brighter_diagonal() {
+        let rect = Rect {
+            start: [0, 0, 0],
+            axes: (0, 1),
+            size: [1, 1],
+        };
+        let quad = |ao: [u8; 4]| {
+            let mut mesh = Mesh::new();
+            push_quad(&mut mesh, [0, 0, 0], 1, &rect, [1.0; 4], ao, None);
+            let brightness: Vec<f32> = mesh.vertices.iter().map(|v| v.ao).collect();
+            let expected: Vec<f32> = ao.iter().map(|&l| AO_BRIGHTNESS[l as usize]).collect();
+            assert_eq!(brightness, expected);
+            mesh
+        };
+
+        assert_eq!(diagonal(&quad([3, 3, 3, 3])), [0, 2]);
+        assert_eq!(diagonal(&quad([3, 0, 3, 2])), [0, 2]);
+        assert_eq!(diagonal(&quad([0, 3, 3, 3])), [1, 3]);
+        assert_eq!(diagonal(&quad([1, 2, 1, 2])), [1, 3]);
+    }
+
+    #[test]
+    fn faces_with_different_occlusion_dont_merge() {
+        let registry = BlockRegistry::builtin();
+        let mut world = World::new();
+        for x in 0..4 {
+            world.set(x, 5, 5, 1);
+        }
+        // the -y faces of the row, the only quads in the y = 5 plane along z = 5
+        let row_quads = |world: &World| {
+            let mesh = mesh_greedy(&Neighbourhood::from_world(world, [0, 0, 0]), &registry);
+            mesh.vertices
+                .chunks(4)
+                .filter(|quad| {
+                    quad.iter().all(|v| {
+                        v.position[1] == 5.0 && v.position[2] >= 5.0 && v.position[2] <= 6.0
+                    })
+                })
+                .count()
+        };
+        assert_eq!(row_quads(&world), 1);
+
+        // darkens corners of the first two faces, each in a different way
+        world.set(0, 4, 6, 1);
+        assert_eq!(row_quads(&world), 3);
+    }
+
+    /// The two by two by two cells at the origin of a copy of the chunk at the
+    /// origin, in x, y, z order
+    fn cube(blocks: [BlockId; 8]) -> Neighbourhood {
+        let mut world = World::new();
+        for (i, &block) in blocks.iter().enumerate() {
+            world.set(i as i32 & 1, i as i32 >> 1 & 1, i as i32 >> 2, block);
+        }
+        Neighbourhood::from_world(&world, [0, 0, 0])
+    }
+
+    #[test]
+    fn downsampling_keeps_cubes_that_are_half_solid() {
+        let registry = BlockRegistry::builtin();
+        let half = cube([1, 1, 1, 1, AIR, AIR, AIR, AIR]).downsampled(1, &registry);
+        assert_eq!(half.get(0, 0, 0), 1);
+        assert_eq!(half.scale(), 2);
+        assert_eq!(half.size, CHUNK_SIZE / 2);
+
+        let less = cube([1, 1, 1, AIR, AIR, AIR, AIR, AIR]).downsampled(1, &registry);
+        assert_eq!(less.get(0, 0, 0), AIR);
+    }
+
+    #[test]
+    fn downsampling_picks_the_most_common_solid_block() {
+        let registry = BlockRegistry::builtin();
+        // air is the most common, but half of the cube is solid
+        let blocks = cube([2, AIR, 2, AIR, 1, AIR, 2, AIR]);
+        assert_eq!(blocks.downsampled(1, &registry).get(0, 0, 0), 2);
+        assert_eq!(blocks.downsampled(2, &registry).get(0, 0, 0), AIR);
+
+        let blocks = cube([1, 1, 1, 2, 2, AIR, 1, AIR]);
+        assert_eq!(blocks.downsampled(1, &registry).get(0, 0, 0), 1);
+    }
+
+    #[test]
+    fn skirts_keep_the_border_faces_of_surface_cells() {
+        let registry = BlockRegistry::builtin();
+        let mut world = World::new();
+        // open towards -y, which is up
+        for &chunk in [
+            [0, 0, 0],
+            [1, 0, 0],
+            [-1, 0, 0],
+            [0, 1, 0],
+            [0, 0, 1],
+            [0, 0, -1],
+        ]
+        .iter()
+        {
+            world.insert_chunk(chunk, Chunk::filled(1));
+        }
+        let blocks = Neighbourhood::from_world(&world, [0, 0, 0]);
+
+        let faces = unit_faces(&mesh_greedy(&blocks, &registry));
+        assert_eq!(faces.len(), CHUNK_SIZE * CHUNK_SIZE);
+
+        let faces = unit_faces(&mesh_greedy(&blocks.with_skirts(true), &registry));
+        let sides: Vec<_> = faces
+            .iter()
+            .filter(|(_, normal, _)| normal[1] == 0)
+            .collect();
+        assert_eq!(faces.len(), CHUNK_SIZE * CHUNK_SIZE + 4 * CHUNK_SIZE);
+        // only the top layer is next to air
+        assert!(sides.iter().all(|(corner, _, _)| corner[1] == 0));
+    }
+}
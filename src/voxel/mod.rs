@@ -0,0 +1,17 @@
+pub mod chunk;
+pub mod edit;
+pub mod face;
+pub mod grid;
+pub mod light;
+pub mod mesher;
+pub mod noise;
+// nothing in the game uses it yet, only its tests and `benches/octree.rs`
+#[cfg(test)]
+pub mod octree;
+pub mod raycast;
+pub mod region;
+pub mod registry;
+pub mod remesh;
+pub mod streaming;
+pub mod terrain;
+pub mod world;
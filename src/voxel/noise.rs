@@ -0,0 +1,263 @@
+//! Seeded gradient noise for terrain generation.
+//!
+//! The output only depends on the seed and the sample position, nothing is
+//! taken from the system or another crate, so a seed always produces the same
+//! world. All functions return values of roughly -1.0 to 1.0.
+
+/// Next value of a splitmix64 sequence, used to derive permutations and sub seeds
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Gradients of simplex noise, the edge centers of a cube
+const GRAD3: [[f64; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// 3D perlin and 2D simplex noise over a permutation table shuffled by the seed
+#[derive(Clone)]
+pub struct Noise {
+    perm: [u8; 512],
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut table = [0u8; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = i as u8;
+        }
+        let mut state = seed;
+        for i in (1..256).rev() {
+            let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        let mut perm = [0u8; 512];
+        for (i, entry) in perm.iter_mut().enumerate() {
+            *entry = table[i & 255];
+        }
+        Self { perm }
+    }
+
+    fn hash(&self, i: i32) -> usize {
+        self.perm[(i & 255) as usize] as usize
+    }
+
+    fn hash2(&self, x: i32, y: i32) -> usize {
+        self.perm[self.hash(x) + (y & 255) as usize] as usize
+    }
+
+    fn hash3(&self, x: i32, y: i32, z: i32) -> usize {
+        self.perm[self.hash2(x, y) + (z & 255) as usize] as usize
+    }
+
+    pub fn perlin3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (xi, yi, zi) = (x.floor(), y.floor(), z.floor());
+        let (xf, yf, zf) = (x - xi, y - yi, z - zi);
+        let (xi, yi, zi) = (xi as i32, yi as i32, zi as i32);
+        let (u, v, w) = (fade(xf), fade(yf), fade(zf));
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            grad3(
+                self.hash3(xi + dx, yi + dy, zi + dz),
+                xf - dx as f64,
+                yf - dy as f64,
+                zf - dz as f64,
+            )
+        };
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+                lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+            ),
+            lerp(
+                v,
+                lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+                lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+            ),
+        )
+    }
+
+    pub fn simplex2(&self, x: f64, y: f64) -> f64 {
+        let f2 = 0.5 * (3f64.sqrt() - 1.0);
+        let g2 = (3.0 - 3f64.sqrt()) / 6.0;
+
+        let s = (x + y) * f2;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * g2;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        let (i, j) = (i as i32, j as i32);
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let corners = [
+            (0, 0, x0, y0),
+            (i1, j1, x0 - i1 as f64 + g2, y0 - j1 as f64 + g2),
+            (1, 1, x0 - 1.0 + 2.0 * g2, y0 - 1.0 + 2.0 * g2),
+        ];
+        let mut sum = 0.0;
+        for &(di, dj, x, y) in corners.iter() {
+            let t = 0.5 - x * x - y * y;
+            if t > 0.0 {
+                let g = GRAD3[self.hash2(i + di, j + dj) % 12];
+                sum += t * t * t * t * (g[0] * x + g[1] * y);
+            }
+        }
+        70.0 * sum
+    }
+}
+
+/// Fractal sum of noise octaves, each `lacunarity` times the frequency and
+/// `gain` times the amplitude of the previous one
+#[derive(Clone, Copy, Debug)]
+pub struct Fbm {
+    pub octaves: u32,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Fbm {
+    pub fn new(octaves: u32, frequency: f64) -> Self {
+        Self {
+            octaves,
+            frequency,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    /// Sums `noise` over the octaves, normalized back to its own range
+    pub fn sample2<F>(&self, noise: F, x: f64, y: f64) -> f64
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        self.sum(|frequency| noise(x * frequency, y * frequency))
+    }
+
+    pub fn sample3<F>(&self, noise: F, x: f64, y: f64, z: f64) -> f64
+    where
+        F: Fn(f64, f64, f64) -> f64,
+    {
+        self.sum(|frequency| noise(x * frequency, y * frequency, z * frequency))
+    }
+
+    fn sum<F>(&self, octave: F) -> f64
+    where
+        F: Fn(f64) -> f64,
+    {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        for _ in 0..self.octaves {
+            sum += octave(frequency) * amplitude;
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad3(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [(f64, f64, f64); 3] = [
+        (0.5, 0.25, 0.75),
+        (-12.3, 4.56, 7.89),
+        (100.1, -200.2, 300.3),
+    ];
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-12, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn a_seed_always_gives_the_same_noise() {
+        // pinned, a change here changes every world generated so far
+        let expected = [
+            (0.10619449615478516, 0.5697604448111988),
+            (-0.18724449387759706, -0.1508805731560852),
+            (0.3796566729718923, -0.45465800703378073),
+        ];
+        let noise = Noise::new(1);
+        for (&(x, y, z), &(perlin, simplex)) in POINTS.iter().zip(expected.iter()) {
+            assert_close(noise.perlin3(x, y, z), perlin);
+            assert_close(noise.simplex2(x, y), simplex);
+        }
+
+        let fbm = Fbm::new(4, 0.01);
+        assert_close(
+            fbm.sample2(|x, y| noise.simplex2(x, y), 123.0, -456.0),
+            0.0968659026419619,
+        );
+    }
+
+    #[test]
+    fn seeds_give_different_noise() {
+        let (a, b) = (Noise::new(1), Noise::new(2));
+        for &(x, y, z) in POINTS.iter() {
+            assert_ne!(a.perlin3(x, y, z), b.perlin3(x, y, z));
+            assert_ne!(a.simplex2(x, y), b.simplex2(x, y));
+        }
+    }
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice() {
+        let noise = Noise::new(3);
+        for i in -5..5 {
+            let i = i as f64;
+            assert_eq!(noise.perlin3(i, -i, 7.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn splitmix64_sequence() {
+        let mut state = 0;
+        assert_eq!(splitmix64(&mut state), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(splitmix64(&mut state), 0x6e78_9e6a_a1b9_65f4);
+    }
+}
@@ -0,0 +1,566 @@
+//! Sparse voxel octree, an alternative to the chunk grid for big scenes that
+//! are mostly empty or made of large uniform regions.
+//!
+//! Every node either is a leaf holding a single block for its whole cube or
+//! splits into eight children. Nodes whose children end up as the same leaf
+//! are collapsed again, so empty space costs a single leaf no matter how large.
+//!
+//! It only pays off for sparse scenes: dense, noisy terrain takes several times
+//! the memory of palette compressed chunks. `memory_usage` on both sides tells
+//! which one suits a scene better.
+use uv::geometry::Ray;
+use uv::Vec3;
+
+use crate::voxel::chunk::{BlockId, Chunk, AIR, CHUNK_SIZE};
+use crate::voxel::world::{split_pos, world_pos, World};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Leaf(BlockId),
+    Branch(Box<[Node; 8]>),
+}
+
+impl Node {
+    fn split(block: BlockId) -> Self {
+        use Node::Leaf;
+        Node::Branch(Box::new([
+            Leaf(block),
+            Leaf(block),
+            Leaf(block),
+            Leaf(block),
+            Leaf(block),
+            Leaf(block),
+            Leaf(block),
+            Leaf(block),
+        ]))
+    }
+
+    /// Turns a branch of eight equal leaves back into a leaf
+    fn collapse(&mut self) {
+        let uniform = match self {
+            Node::Branch(children) => match children[0] {
+                Node::Leaf(block) if children.iter().all(|c| *c == Node::Leaf(block)) => {
+                    Some(block)
+                }
+                _ => None,
+            },
+            Node::Leaf(_) => None,
+        };
+        if let Some(block) = uniform {
+            *self = Node::Leaf(block);
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Branch(children) => 1 + children.iter().map(Node::count).sum::<usize>(),
+        }
+    }
+}
+
+/// Child index of a position inside of a node, one bit per axis
+fn child_index(local: [i32; 3], half: i32) -> usize {
+    (local[0] >= half) as usize
+        | ((local[1] >= half) as usize) << 1
+        | ((local[2] >= half) as usize) << 2
+}
+
+fn child_offset(index: usize, half: i32) -> [i32; 3] {
+    [
+        (index & 1) as i32 * half,
+        (index >> 1 & 1) as i32 * half,
+        (index >> 2 & 1) as i32 * half,
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OctreeHit {
+    pub position: [i32; 3],
+    pub block: BlockId,
+    /// Normal of the face the ray entered through, zero if it started inside
+    pub normal: [i32; 3],
+    pub distance: f32,
+}
+
+/// Cube of 2^depth blocks along each axis starting at `origin`
+#[derive(Clone, Debug)]
+pub struct Octree {
+    origin: [i32; 3],
+    depth: u32,
+    root: Node,
+}
+
+impl Octree {
+    pub fn new(origin: [i32; 3], depth: u32) -> Self {
+        assert!(depth < 31, "octree depth {} is too deep", depth);
+        Self {
+            origin,
+            depth,
+            root: Node::Leaf(AIR),
+        }
+    }
+
+    /// Blocks along each axis
+    pub fn size(&self) -> i32 {
+        1 << self.depth
+    }
+
+    pub fn contains(&self, x: i32, y: i32, z: i32) -> bool {
+        let size = self.size();
+        [x, y, z]
+            .iter()
+            .zip(self.origin.iter())
+            .all(|(&p, &o)| p >= o && p - o < size)
+    }
+
+    /// Block at a world position, everything outside of the octree is air
+    pub fn get(&self, x: i32, y: i32, z: i32) -> BlockId {
+        if !self.contains(x, y, z) {
+            return AIR;
+        }
+        let mut local = [x - self.origin[0], y - self.origin[1], z - self.origin[2]];
+        let mut node = &self.root;
+        let mut half = self.size() / 2;
+        loop {
+            match node {
+                Node::Leaf(block) => return *block,
+                Node::Branch(children) => {
+                    let index = child_index(local, half);
+                    let offset = child_offset(index, half);
+                    for axis in 0..3 {
+                        local[axis] -= offset[axis];
+                    }
+                    node = &children[index];
+                    half /= 2;
+                }
+            }
+        }
+    }
+
+    /// Sets a block and returns the one it replaced
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block: BlockId) -> BlockId {
+        self.fill([x, y, z], 1, block)
+    }
+
+    pub fn remove(&mut self, x: i32, y: i32, z: i32) -> BlockId {
+        self.set(x, y, z, AIR)
+    }
+
+    /// Sets a whole cube of `size` blocks, which has to be a power of two and
+    /// aligned to it relative to the origin. Returns the block that was at `min`.
+    pub fn fill(&mut self, min: [i32; 3], size: i32, block: BlockId) -> BlockId {
+        assert!(
+            self.contains(min[0], min[1], min[2]),
+            "block {:?} outside of the octree at {:?} with size {}",
+            min,
+            self.origin,
+            self.size()
+        );
+        assert!(size > 0 && size & (size - 1) == 0 && size <= self.size());
+        let local = [
+            min[0] - self.origin[0],
+            min[1] - self.origin[1],
+            min[2] - self.origin[2],
+        ];
+        assert!(
+            local.iter().all(|&c| c % size == 0),
+            "cube at {:?} isn't aligned to its size {}",
+            min,
+            size
+        );
+        let old = self.get(min[0], min[1], min[2]);
+        let root_size = self.size();
+        fill(&mut self.root, root_size, local, size, block);
+        old
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root == Node::Leaf(AIR)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.root.count()
+    }
+
+    /// Approximate heap usage in bytes
+    pub fn memory_usage(&self) -> usize {
+        // every node but the root lives in its parent's boxed array
+        (self.node_count() - 1) * std::mem::size_of::<Node>()
+    }
+
+    /// Calls `f` with the minimum corner, size and block of every leaf
+    pub fn for_each_leaf<F>(&self, mut f: F)
+    where
+        F: FnMut([i32; 3], i32, BlockId),
+    {
+        visit(&self.root, self.origin, self.size(), &mut f);
+    }
+
+    /// Nearest solid block along the ray within `max_distance`. The ray's
+    /// direction doesn't have to be normalized, distances are in its units.
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<OctreeHit> {
+        let query = RayQuery {
+            ray,
+            inverse: Vec3::new(
+                1.0 / ray.direction.x,
+                1.0 / ray.direction.y,
+                1.0 / ray.direction.z,
+            ),
+            max_distance,
+        };
+        let mut hit = None;
+        traverse(&self.root, self.origin, self.size(), &query, &mut hit);
+        hit
+    }
+
+    /// Copies the blocks of all chunks overlapping the octree. With an origin
+    /// aligned to the chunk grid uniform chunks become a single node.
+    pub fn from_world(world: &World, origin: [i32; 3], depth: u32) -> Self {
+        let mut octree = Self::new(origin, depth);
+        let aligned = origin.iter().all(|&c| c % CHUNK_SIZE as i32 == 0)
+            && octree.size() >= CHUNK_SIZE as i32;
+
+        for (&pos, chunk) in world.chunks() {
+            let min = world_pos(pos, [0, 0, 0]);
+            if aligned && octree.contains(min[0], min[1], min[2]) {
+                if let Some(block) = chunk.uniform() {
+                    octree.fill(min, CHUNK_SIZE as i32, block);
+                    continue;
+                }
+            }
+            for (local, block) in chunk.iter() {
+                let [x, y, z] = world_pos(pos, local);
+                if block != AIR && octree.contains(x, y, z) {
+                    octree.set(x, y, z, block);
+                }
+            }
+        }
+        octree
+    }
+
+    /// Writes every solid block into the world, leaves covering whole chunks
+    /// replace them in one go
+    pub fn to_world(&self, world: &mut World) {
+        self.for_each_leaf(|min, size, block| {
+            if block == AIR {
+                return;
+            }
+            if size >= CHUNK_SIZE as i32 && min.iter().all(|&c| c % CHUNK_SIZE as i32 == 0) {
+                let chunks = size / CHUNK_SIZE as i32;
+                let (first, _) = split_pos(min[0], min[1], min[2]);
+                for z in 0..chunks {
+                    for y in 0..chunks {
+                        for x in 0..chunks {
+                            let pos = [first[0] + x, first[1] + y, first[2] + z];
+                            world.insert_chunk(pos, Chunk::filled(block));
+                        }
+                    }
+                }
+                return;
+            }
+            for z in 0..size {
+                for y in 0..size {
+                    for x in 0..size {
+                        world.set(min[0] + x, min[1] + y, min[2] + z, block);
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn fill(node: &mut Node, size: i32, local: [i32; 3], fill_size: i32, block: BlockId) {
+    if size == fill_size {
+        *node = Node::Leaf(block);
+        return;
+    }
+    if let Node::Leaf(current) = *node {
+        if current == block {
+            return;
+        }
+        *node = Node::split(current);
+    }
+
+    let half = size / 2;
+    if let Node::Branch(children) = node {
+        let index = child_index(local, half);
+        let offset = child_offset(index, half);
+        let local = [
+            local[0] - offset[0],
+            local[1] - offset[1],
+            local[2] - offset[2],
+        ];
+        fill(&mut children[index], half, local, fill_size, block);
+    }
+    node.collapse();
+}
+
+fn visit<F>(node: &Node, min: [i32; 3], size: i32, f: &mut F)
+where
+    F: FnMut([i32; 3], i32, BlockId),
+{
+    match node {
+        Node::Leaf(block) => f(min, size, *block),
+        Node::Branch(children) => {
+            let half = size / 2;
+            for (index, child) in children.iter().enumerate() {
+                let offset = child_offset(index, half);
+                let min = [min[0] + offset[0], min[1] + offset[1], min[2] + offset[2]];
+                visit(child, min, half, f);
+            }
+        }
+    }
+}
+
+struct RayQuery<'a> {
+    ray: &'a Ray,
+    /// Reciprocal of the direction, infinite along axes the ray is parallel to
+    inverse: Vec3,
+    max_distance: f32,
+}
+
+/// Entry and exit distance of a ray through a cube and the axis it enters on
+fn intersect(min: [i32; 3], size: i32, query: &RayQuery) -> Option<(f32, f32, usize)> {
+    let origin = [query.ray.origin.x, query.ray.origin.y, query.ray.origin.z];
+    let inverse = [query.inverse.x, query.inverse.y, query.inverse.z];
+    let (mut enter, mut exit, mut axis) = (f32::NEG_INFINITY, f32::INFINITY, 0);
+    for i in 0..3 {
+        if inverse[i].is_infinite() {
+            // parallel to this axis, either always inside of the slab or never
+            if origin[i] < min[i] as f32 || origin[i] >= (min[i] + size) as f32 {
+                return None;
+            }
+            continue;
+        }
+        let near = (min[i] as f32 - origin[i]) * inverse[i];
+        let far = ((min[i] + size) as f32 - origin[i]) * inverse[i];
+        let (near, far) = if near <= far {
+            (near, far)
+        } else {
+            (far, near)
+        };
+        if near > enter {
+            enter = near;
+            axis = i;
+        }
+        if far < exit {
+            exit = far;
+        }
+    }
+    if enter <= exit && exit >= 0.0 {
+        Some((enter, exit, axis))
+    } else {
+        None
+    }
+}
+
+fn traverse(node: &Node, min: [i32; 3], size: i32, query: &RayQuery, hit: &mut Option<OctreeHit>) {
+    let ray = query.ray;
+    let (enter, _, axis) = match intersect(min, size, query) {
+        Some(range) if range.0 <= query.max_distance => range,
+        _ => return,
+    };
+    if let Some(closest) = hit {
+        if enter >= closest.distance {
+            return;
+        }
+    }
+
+    match node {
+        Node::Leaf(AIR) => {}
+        Node::Leaf(block) => {
+            let distance = enter.max(0.0);
+            let mut normal = [0; 3];
+            if enter > 0.0 {
+                let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+                normal[axis] = if direction[axis] > 0.0 { -1 } else { 1 };
+            }
+
+            // the block the ray enters, nudged off the face it crosses
+            let point = ray.origin + ray.direction * distance;
+            let point = [point.x, point.y, point.z];
+            let mut position = [0; 3];
+            for i in 0..3 {
+                let inside = point[i] - normal[i] as f32 * 0.5;
+                position[i] = (inside.floor() as i32).clamp(min[i], min[i] + size - 1);
+            }
+            *hit = Some(OctreeHit {
+                position,
+                block: *block,
+                normal,
+                distance,
+            });
+        }
+        Node::Branch(children) => {
+            let half = size / 2;
+            let mut order: Vec<(f32, usize)> = (0..8)
+                .filter_map(|index| {
+                    let offset = child_offset(index, half);
+                    let min = [min[0] + offset[0], min[1] + offset[1], min[2] + offset[2]];
+                    intersect(min, half, query).map(|(enter, _, _)| (enter, index))
+                })
+                .collect();
+            order.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+            for (_, index) in order {
+                let offset = child_offset(index, half);
+                let min = [min[0] + offset[0], min[1] + offset[1], min[2] + offset[2]];
+                traverse(&children[index], min, half, query, hit);
+                if hit.is_some() {
+                    // children are visited front to back, the first hit is the nearest
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every block of a cube, minimum corner first
+    fn cube(min: [i32; 3], size: i32) -> impl Iterator<Item = [i32; 3]> {
+        (0..size).flat_map(move |z| {
+            (0..size)
+                .flat_map(move |y| (0..size).map(move |x| [min[0] + x, min[1] + y, min[2] + z]))
+        })
+    }
+
+    fn leaves(octree: &Octree) -> Vec<([i32; 3], i32, BlockId)> {
+        let mut leaves = Vec::new();
+        octree.for_each_leaf(|min, size, block| leaves.push((min, size, block)));
+        leaves
+    }
+
+    #[test]
+    fn single_blocks_split_and_collapse() {
+        let mut octree = Octree::new([-64, -64, -64], 7);
+        assert!(octree.is_empty());
+        assert_eq!(octree.set(1, 2, 3, 5), AIR);
+        assert_eq!(octree.get(1, 2, 3), 5);
+        assert_eq!(octree.get(1, 2, 4), AIR);
+        // one branch of eight per level down to the block
+        assert_eq!(octree.node_count(), 1 + 8 * 7);
+        assert_eq!(octree.memory_usage(), 8 * 7 * std::mem::size_of::<Node>());
+
+        assert_eq!(octree.remove(1, 2, 3), 5);
+        assert!(octree.is_empty());
+        assert_eq!(octree.node_count(), 1);
+
+        // outside of the octree is air and can't be set
+        assert!(!octree.contains(64, 0, 0));
+        assert_eq!(octree.get(64, 0, 0), AIR);
+    }
+
+    #[test]
+    fn uniform_regions_collapse() {
+        let mut octree = Octree::new([0, 0, 0], 5);
+        for [x, y, z] in cube([8, 8, 8], 8) {
+            octree.set(x, y, z, 2);
+        }
+        // the eight by eight cube ends up as a single leaf two levels down
+        assert_eq!(octree.node_count(), 1 + 8 * 2);
+        assert!(leaves(&octree).contains(&([8, 8, 8], 8, 2)));
+
+        // growing it to the whole octree leaves nothing but the root
+        for [x, y, z] in cube([0, 0, 0], 32) {
+            octree.set(x, y, z, 2);
+        }
+        assert_eq!(leaves(&octree), vec![([0, 0, 0], 32, 2)]);
+
+        // punching a hole splits it again, filling it collapses
+        octree.set(31, 0, 17, 3);
+        assert_eq!(octree.node_count(), 1 + 8 * 5);
+        octree.set(31, 0, 17, 2);
+        assert_eq!(octree.node_count(), 1);
+
+        octree.fill([16, 0, 16], 16, AIR);
+        assert_eq!(octree.get(20, 3, 20), AIR);
+        assert_eq!(octree.get(15, 3, 20), 2);
+        octree.fill([0, 0, 0], 32, AIR);
+        assert!(octree.is_empty());
+    }
+
+    #[test]
+    fn chunk_grid_round_trip() {
+        let mut world = World::new();
+        // two uniform chunks, a mixed one and one outside of the octree
+        world.insert_chunk([0, 0, 0], Chunk::filled(1));
+        let mut mixed = Chunk::empty();
+        for ([x, y, z], _) in Chunk::empty().iter() {
+            if (x * 7 + y * 3 + z) % 5 == 0 {
+                mixed.set(x, y, z, 1 + (x % 3) as BlockId);
+            }
+        }
+        world.insert_chunk([-1, 0, 0], mixed);
+        world.insert_chunk([0, -1, -1], Chunk::filled(4));
+        world.insert_chunk([5, 5, 5], Chunk::filled(4));
+
+        let octree = Octree::from_world(&world, [-32, -32, -32], 7);
+        for [x, y, z] in cube([-32, -32, -32], 128) {
+            assert_eq!(octree.get(x, y, z), world.get(x, y, z), "{} {} {}", x, y, z);
+        }
+        // the uniform chunk is a single node
+        assert!(leaves(&octree).contains(&([0, 0, 0], 32, 1)));
+
+        let mut back = World::new();
+        octree.to_world(&mut back);
+        assert_eq!(back.chunk([0, 0, 0]), world.chunk([0, 0, 0]));
+        assert_eq!(back.chunk([-1, 0, 0]), world.chunk([-1, 0, 0]));
+        assert_eq!(back.chunk([0, -1, -1]), world.chunk([0, -1, -1]));
+        // outside of the octree
+        assert!(!back.contains_chunk([5, 5, 5]));
+        assert_eq!(back.chunk_count(), 3);
+
+        // and the same octree again from the copy
+        let again = Octree::from_world(&back, [-32, -32, -32], 7);
+        assert_eq!(leaves(&again), leaves(&octree));
+    }
+
+    #[test]
+    fn rays_hit_the_nearest_block() {
+        let mut octree = Octree::new([-16, -16, -16], 5);
+        octree.set(5, 0, 0, 1);
+        octree.fill([8, 0, 0], 8, 2);
+
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.5, 0.5),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        let hit = octree.raycast(&ray, 100.0).unwrap();
+        assert_eq!(hit.position, [5, 0, 0]);
+        assert_eq!(hit.block, 1);
+        assert_eq!(hit.normal, [-1, 0, 0]);
+        assert!((hit.distance - 4.5).abs() < 1e-6);
+        assert!(octree.raycast(&ray, 4.0).is_none());
+
+        // behind the single block into the big cube, entering from above
+        let ray = Ray {
+            origin: Vec3::new(12.5, -3.5, 2.5),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+        };
+        let hit = octree.raycast(&ray, 100.0).unwrap();
+        assert_eq!(hit.position, [12, 0, 2]);
+        assert_eq!(hit.block, 2);
+        assert_eq!(hit.normal, [0, -1, 0]);
+
+        // starting inside of a block
+        let ray = Ray {
+            origin: Vec3::new(5.5, 0.5, 0.5),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+        };
+        let hit = octree.raycast(&ray, 100.0).unwrap();
+        assert_eq!(hit.position, [5, 0, 0]);
+        assert_eq!(hit.normal, [0, 0, 0]);
+        assert_eq!(hit.distance, 0.0);
+
+        // below everything, up is -y
+        let ray = Ray {
+            origin: Vec3::new(0.5, 9.5, 0.5),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        assert!(octree.raycast(&ray, 100.0).is_none());
+    }
+}
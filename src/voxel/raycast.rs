@@ -0,0 +1,227 @@
+//! Exact ray casts through the block grid for picking and line of sight.
+//!
+//! Steps from cell to cell along the ray as described by Amanatides and Woo,
+//! "A Fast Voxel Traversal Algorithm for Ray Tracing", so every cell the ray
+//! touches is visited in order and none is skipped, no matter how thin the
+//! slice of it the ray passes through.
+use uv::geometry::Ray;
+
+use crate::voxel::chunk::{BlockId, AIR};
+use crate::voxel::world::World;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub position: [i32; 3],
+    pub block: BlockId,
+    /// Normal of the face the ray entered through, zero if it started inside
+    pub normal: [i32; 3],
+    /// Empty cell in front of the hit face, where a placed block goes. The
+    /// hit position itself if the ray started inside the block.
+    pub previous: [i32; 3],
+    pub distance: f32,
+}
+
+/// Longest distance a ray is cast, in units of its direction. Unloaded chunks
+/// count as air, so without a limit a ray into empty space would never end.
+pub const MAX_DISTANCE: f32 = 4096.0;
+
+/// First solid block of the world along the ray within `max_distance`. The
+/// ray's direction doesn't have to be normalized, distances are in its units.
+/// Longer distances, e.g. `f32::INFINITY` for no limit, stop at
+/// `MAX_DISTANCE`.
+pub fn raycast(world: &World, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+    raycast_with(ray, max_distance, |[x, y, z]| world.get(x, y, z))
+}
+
+/// Like `raycast` but reads the blocks through `block`, e.g. from an octree or
+/// a grid that isn't part of a world
+pub fn raycast_with<F>(ray: &Ray, max_distance: f32, mut block: F) -> Option<RayHit>
+where
+    F: FnMut([i32; 3]) -> BlockId,
+{
+    // also turns NaN into the maximum
+    let max_distance = max_distance.min(MAX_DISTANCE);
+    let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+    let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+    let mut cell = [
+        origin[0].floor() as i32,
+        origin[1].floor() as i32,
+        origin[2].floor() as i32,
+    ];
+    let first = block(cell);
+    if first != AIR {
+        return Some(RayHit {
+            position: cell,
+            block: first,
+            normal: [0; 3],
+            previous: cell,
+            distance: 0.0,
+        });
+    }
+
+    let mut step = [0; 3];
+    // distance along the ray to the next cell border on each axis
+    let mut next = [f32::INFINITY; 3];
+    // distance along the ray between two borders on each axis
+    let mut delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        let d = direction[axis];
+        if d > 0.0 {
+            step[axis] = 1;
+            next[axis] = (cell[axis] as f32 + 1.0 - origin[axis]) / d;
+            delta[axis] = 1.0 / d;
+        } else if d < 0.0 {
+            step[axis] = -1;
+            next[axis] = (cell[axis] as f32 - origin[axis]) / d;
+            delta[axis] = -1.0 / d;
+        }
+    }
+
+    loop {
+        let axis = if next[0] < next[1] {
+            if next[0] < next[2] {
+                0
+            } else {
+                2
+            }
+        } else if next[1] < next[2] {
+            1
+        } else {
+            2
+        };
+        let distance = next[axis];
+        // also stops rays without a direction
+        if !distance.is_finite() || distance > max_distance {
+            return None;
+        }
+
+        let previous = cell;
+        cell[axis] += step[axis];
+        next[axis] += delta[axis];
+
+        let hit = block(cell);
+        if hit != AIR {
+            let mut normal = [0; 3];
+            normal[axis] = -step[axis];
+            return Some(RayHit {
+                position: cell,
+                block: hit,
+                normal,
+                previous,
+                distance,
+            });
+        }
+    }
+}
+
+/// Whether nothing solid is between two points, the cells of both ends are
+/// not checked so it works from and to the inside of blocks
+pub fn line_of_sight(world: &World, from: uv::Vec3, to: uv::Vec3) -> bool {
+    let target = [
+        to.x.floor() as i32,
+        to.y.floor() as i32,
+        to.z.floor() as i32,
+    ];
+    let start = [
+        from.x.floor() as i32,
+        from.y.floor() as i32,
+        from.z.floor() as i32,
+    ];
+    let ray = Ray {
+        origin: from,
+        direction: to - from,
+    };
+    // the direction isn't normalized, so the target is at distance 1.0
+    raycast_with(&ray, 1.0, |pos| {
+        if pos == start || pos == target {
+            AIR
+        } else {
+            world.get(pos[0], pos[1], pos[2])
+        }
+    })
+    .is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: uv::Vec3::from(origin),
+            direction: uv::Vec3::from(direction),
+        }
+    }
+
+    #[test]
+    fn hits_the_first_solid_block() {
+        let mut world = World::new();
+        world.set(0, 5, 0, 1);
+        world.set(0, 8, 0, 2);
+
+        let hit = raycast(&world, &ray([0.5, 0.5, 0.5], [0.0, 1.0, 0.0]), 10.0).unwrap();
+        assert_eq!(hit.position, [0, 5, 0]);
+        assert_eq!(hit.block, 1);
+        assert_eq!(hit.normal, [0, -1, 0]);
+        assert_eq!(hit.previous, [0, 4, 0]);
+        assert_eq!(hit.distance, 4.5);
+
+        assert!(raycast(&world, &ray([0.5, 0.5, 0.5], [0.0, 1.0, 0.0]), 4.0).is_none());
+        assert!(raycast(&world, &ray([0.5, 0.5, 0.5], [0.0, -1.0, 0.0]), 100.0).is_none());
+    }
+
+    #[test]
+    fn diagonal_rays_visit_every_cell() {
+        let mut world = World::new();
+        world.set(1, 0, 0, 1);
+        // passes the corner between [0, 0, 0], [1, 0, 0] and [0, 1, 0]
+        // slightly below the diagonal, so it has to touch [1, 0, 0]
+        let hit = raycast(&world, &ray([0.5, 0.49, 0.5], [1.0, 1.0, 0.0]), 10.0).unwrap();
+        assert_eq!(hit.position, [1, 0, 0]);
+        assert_eq!(hit.normal, [-1, 0, 0]);
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it() {
+        let mut world = World::new();
+        world.set(-1, -1, -1, 3);
+        let hit = raycast(&world, &ray([-0.5, -0.5, -0.5], [1.0, 0.0, 0.0]), 1.0).unwrap();
+        assert_eq!(hit.position, [-1, -1, -1]);
+        assert_eq!(hit.normal, [0, 0, 0]);
+        assert_eq!(hit.previous, hit.position);
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn rays_without_a_direction_miss() {
+        let world = World::new();
+        assert!(raycast(&world, &ray([0.5, 0.5, 0.5], [0.0, 0.0, 0.0]), 100.0).is_none());
+    }
+
+    #[test]
+    fn infinite_distances_stop_at_the_maximum() {
+        let mut world = World::new();
+        world.set(0, 1000, 0, 1);
+        let up = ray([0.5, 0.5, 0.5], [0.0, 1.0, 0.0]);
+        let hit = raycast(&world, &up, f32::INFINITY).unwrap();
+        assert_eq!(hit.position, [0, 1000, 0]);
+
+        let far = MAX_DISTANCE as i32 + 10;
+        world.set(far, 0, 0, 1);
+        let right = ray([0.5, 0.5, 0.5], [1.0, 0.0, 0.0]);
+        assert!(raycast(&world, &right, f32::INFINITY).is_none());
+        assert!(raycast(&world, &right, f32::NAN).is_none());
+    }
+
+    #[test]
+    fn line_of_sight_ignores_the_end_cells() {
+        let mut world = World::new();
+        world.set(0, 0, 0, 1);
+        world.set(5, 0, 0, 1);
+        let (from, to) = (uv::Vec3::new(0.5, 0.5, 0.5), uv::Vec3::new(5.5, 0.5, 0.5));
+        assert!(line_of_sight(&world, from, to));
+        world.set(3, 0, 0, 1);
+        assert!(!line_of_sight(&world, from, to));
+    }
+}
@@ -0,0 +1,463 @@
+//! Saving the voxel world to region files.
+//!
+//! Chunks are grouped into regions of `REGION_SIZE`^3 chunks, each stored in
+//! its own file named after the region's position. A file starts with a header:
+//!
+//! ```text
+//! magic "TRXR" | version: u32 | REGION_VOLUME * (offset: u32, length: u32, crc32: u32)
+//! ```
+//!
+//! followed by the zlib compressed chunks the table points at, all little
+//! endian. An offset of 0 means the chunk isn't stored. A compressed chunk is
+//! its palette size as u16, the palette's block ids as u16 and one palette
+//! index per block, a byte each for palettes of up to 256 entries and a u16
+//! otherwise, in the same x, y, z order as `Chunk::iter`.
+//!
+//! Loading is tolerant to damaged files: chunks that fail their checksum or
+//! don't decode are skipped and get generated again, a file with a broken
+//! header is moved aside to `<name>.corrupt` the next time its region is saved.
+//! What was skipped is collected as warnings, see `RegionStore::take_warnings`.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::loader::error::LoadError;
+use crate::voxel::chunk::{BlockId, Chunk, CHUNK_SIZE, CHUNK_VOLUME};
+use crate::voxel::world::{ChunkPos, World};
+
+/// Chunks along each axis of a region
+pub const REGION_SIZE: i32 = 8;
+pub const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"TRXR";
+/// Bumped whenever the layout changes, older versions must stay readable
+pub const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8 + REGION_VOLUME * 12;
+const COMPRESSION_LEVEL: u8 = 6;
+
+pub type RegionPos = [i32; 3];
+
+/// Region of a chunk and the chunk's index in the region's offset table
+pub fn region_pos(chunk: ChunkPos) -> (RegionPos, usize) {
+    let region = [
+        chunk[0].div_euclid(REGION_SIZE),
+        chunk[1].div_euclid(REGION_SIZE),
+        chunk[2].div_euclid(REGION_SIZE),
+    ];
+    let local = [
+        chunk[0].rem_euclid(REGION_SIZE) as usize,
+        chunk[1].rem_euclid(REGION_SIZE) as usize,
+        chunk[2].rem_euclid(REGION_SIZE) as usize,
+    ];
+    let size = REGION_SIZE as usize;
+    (region, (local[2] * size + local[1]) * size + local[0])
+}
+
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let palette: Vec<BlockId> = chunk.palette().collect();
+    let wide = palette.len() > 256;
+
+    let mut bytes = Vec::with_capacity(2 + palette.len() * 2 + CHUNK_VOLUME);
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for block in palette.iter() {
+        bytes.extend_from_slice(&block.to_le_bytes());
+    }
+    let mut last = (palette[0], 0usize);
+    for (_, block) in chunk.iter() {
+        // runs of the same block are common, skip the palette search for them
+        if block != last.0 {
+            last = (block, palette.iter().position(|&b| b == block).unwrap());
+        }
+        if wide {
+            bytes.extend_from_slice(&(last.1 as u16).to_le_bytes());
+        } else {
+            bytes.push(last.1 as u8);
+        }
+    }
+    miniz_oxide::deflate::compress_to_vec_zlib(&bytes, COMPRESSION_LEVEL)
+}
+
+pub fn decode_chunk(compressed: &[u8]) -> Result<Chunk, LoadError> {
+    let bytes = miniz_oxide::inflate::decompress_to_vec_zlib(compressed)
+        .map_err(|e| LoadError::format(format!("can't decompress chunk: {:?}", e)))?;
+
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    if bytes.len() < 2 {
+        return Err(LoadError::format("chunk without palette"));
+    }
+    let palette_len = u16_at(0) as usize;
+    let wide = palette_len > 256;
+    let indices_start = 2 + palette_len * 2;
+    let index_size = if wide { 2 } else { 1 };
+    if palette_len == 0 || bytes.len() != indices_start + CHUNK_VOLUME * index_size {
+        return Err(LoadError::format(format!(
+            "chunk of {} bytes doesn't fit a palette of {}",
+            bytes.len(),
+            palette_len
+        )));
+    }
+    let palette: Vec<BlockId> = (0..palette_len).map(|i| u16_at(2 + i * 2)).collect();
+
+    let mut chunk = Chunk::filled(palette[0]);
+    for i in 0..CHUNK_VOLUME {
+        let index = if wide {
+            u16_at(indices_start + i * 2) as usize
+        } else {
+            bytes[indices_start + i] as usize
+        };
+        let block = *palette.get(index).ok_or_else(|| {
+            LoadError::format(format!("palette index {} out of {}", index, palette_len))
+        })?;
+        let (x, y, z) = (
+            i % CHUNK_SIZE,
+            i / CHUNK_SIZE % CHUNK_SIZE,
+            i / (CHUNK_SIZE * CHUNK_SIZE),
+        );
+        chunk.set(x, y, z, block);
+    }
+    Ok(chunk)
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Compressed chunks of one region file
+#[derive(Default)]
+struct Region {
+    chunks: HashMap<usize, Vec<u8>>,
+    /// Changed since it was read or last written
+    dirty: bool,
+    /// The file on disk couldn't be read and is kept instead of overwritten
+    corrupt: bool,
+}
+
+impl Region {
+    /// Reads every chunk whose table entry and checksum are fine, the skipped
+    /// ones are added to `warnings`
+    fn parse(bytes: &[u8], warnings: &mut Vec<LoadError>) -> Result<Self, LoadError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(LoadError::format("not a region file"));
+        }
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let version = u32_at(4);
+        if version == 0 || version > VERSION {
+            return Err(LoadError::format(format!(
+                "unsupported region version {}",
+                version
+            )));
+        }
+
+        let mut region = Region::default();
+        for index in 0..REGION_VOLUME {
+            let entry = 8 + index * 12;
+            let (offset, length, crc) = (
+                u32_at(entry) as usize,
+                u32_at(entry + 4) as usize,
+                u32_at(entry + 8),
+            );
+            if offset == 0 {
+                continue;
+            }
+            let data = match bytes.get(offset..offset.saturating_add(length)) {
+                Some(data) if offset >= HEADER_SIZE => data,
+                _ => {
+                    warnings.push(LoadError::format(format!(
+                        "chunk {} points outside of the file, skipped",
+                        index
+                    )));
+                    continue;
+                }
+            };
+            if checksum(data) != crc {
+                warnings.push(LoadError::format(format!(
+                    "chunk {} fails its checksum, skipped",
+                    index
+                )));
+                continue;
+            }
+            region.chunks.insert(index, data.to_vec());
+        }
+        Ok(region)
+    }
+
+    fn write(&self) -> Vec<u8> {
+        let size = HEADER_SIZE + self.chunks.values().map(Vec::len).sum::<usize>();
+        let mut bytes = Vec::with_capacity(size);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.resize(HEADER_SIZE, 0);
+
+        let mut indices: Vec<usize> = self.chunks.keys().copied().collect();
+        indices.sort();
+        for index in indices {
+            let data = &self.chunks[&index];
+            let entry = 8 + index * 12;
+            let offset = bytes.len() as u32;
+            bytes[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+            bytes[entry + 4..entry + 8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes[entry + 8..entry + 12].copy_from_slice(&checksum(data).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+}
+
+/// Directory of region files. Regions are read on first use and stay cached,
+/// saved chunks only reach the disk with `flush`.
+pub struct RegionStore {
+    directory: PathBuf,
+    regions: HashMap<RegionPos, Region>,
+    /// Damaged files and chunks skipped since the last `take_warnings`
+    warnings: Vec<LoadError>,
+}
+
+impl RegionStore {
+    /// Opens a save directory, creating it if needed
+    pub fn open(directory: impl Into<PathBuf>) -> Result<Self, LoadError> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).map_err(|e| LoadError::from(e).in_file(&directory))?;
+        Ok(Self {
+            directory,
+            regions: HashMap::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    pub fn region_path(&self, region: RegionPos) -> PathBuf {
+        self.directory.join(format!(
+            "r.{}.{}.{}.region",
+            region[0], region[1], region[2]
+        ))
+    }
+
+    fn region(&mut self, pos: RegionPos) -> &mut Region {
+        if !self.regions.contains_key(&pos) {
+            let path = self.region_path(pos);
+            let mut warnings = Vec::new();
+            let region = match fs::read(&path) {
+                Ok(bytes) => Region::parse(&bytes, &mut warnings).map_err(|e| vec![e]),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Region::default()),
+                Err(e) => Err(vec![LoadError::from(e)]),
+            };
+            let region = region.unwrap_or_else(|errors| {
+                warnings.extend(errors);
+                Region {
+                    corrupt: true,
+                    ..Region::default()
+                }
+            });
+            self.warnings
+                .extend(warnings.into_iter().map(|e| e.in_file(&path)));
+            self.regions.insert(pos, region);
+        }
+        self.regions.get_mut(&pos).unwrap()
+    }
+
+    /// The saved chunk, `None` if it wasn't saved or is damaged
+    pub fn load_chunk(&mut self, chunk: ChunkPos) -> Option<Chunk> {
+        let (region_pos, index) = region_pos(chunk);
+        let path = self.region_path(region_pos);
+        let region = self.region(region_pos);
+        let result = decode_chunk(region.chunks.get(&index)?);
+        match result {
+            Ok(chunk) => Some(chunk),
+            Err(e) => {
+                region.chunks.remove(&index);
+                let message = format!("chunk {:?} is damaged, skipped: {}", chunk, e);
+                self.warnings.push(LoadError::format(message).in_file(path));
+                None
+            }
+        }
+    }
+
+    /// Damaged region files and chunks that were skipped since the last call.
+    /// Loading goes on without them, so these are only worth reporting.
+    pub fn take_warnings(&mut self) -> Vec<LoadError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn save_chunk(&mut self, pos: ChunkPos, chunk: &Chunk) {
+        let data = encode_chunk(chunk);
+        let (region, index) = region_pos(pos);
+        let region = self.region(region);
+        region.chunks.insert(index, data);
+        region.dirty = true;
+    }
+
+    /// Saves the world's chunks at `chunks`, chunks that aren't loaded are
+    /// saved as air so removed chunks don't come back
+    pub fn save_chunks<I>(&mut self, world: &World, chunks: I)
+    where
+        I: IntoIterator<Item = ChunkPos>,
+    {
+        let empty = Chunk::empty();
+        for pos in chunks {
+            self.save_chunk(pos, world.chunk(pos).unwrap_or(&empty));
+        }
+    }
+
+    /// Saves the chunks modified since the last save and writes them to disk
+    pub fn save_modified(&mut self, world: &mut World) -> Result<(), LoadError> {
+        let modified = world.take_modified();
+        self.save_chunks(world, modified);
+        self.flush()
+    }
+
+    /// Writes every region with unsaved chunks. Files are replaced through a
+    /// temporary file, so a crash while saving leaves the old file intact.
+    pub fn flush(&mut self) -> Result<(), LoadError> {
+        let dirty: Vec<RegionPos> = self
+            .regions
+            .iter()
+            .filter(|(_, region)| region.dirty)
+            .map(|(&pos, _)| pos)
+            .collect();
+        for pos in dirty {
+            let path = self.region_path(pos);
+            let region = self.regions.get_mut(&pos).unwrap();
+            if region.corrupt {
+                let mut backup = path.clone().into_os_string();
+                backup.push(".corrupt");
+                fs::rename(&path, &backup).map_err(|e| LoadError::from(e).in_file(&path))?;
+                region.corrupt = false;
+            }
+            let temporary = path.with_extension("region.tmp");
+            fs::write(&temporary, region.write())
+                .and_then(|_| fs::rename(&temporary, &path))
+                .map_err(|e| LoadError::from(e).in_file(&path))?;
+            region.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// An empty directory in the system's temporary one, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("troxel_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn same(a: &Chunk, b: &Chunk) -> bool {
+        a.iter().zip(b.iter()).all(|(a, b)| a == b)
+    }
+
+    /// Chunks with a narrow and a wide palette in two regions
+    fn chunks() -> Vec<(ChunkPos, Chunk)> {
+        let mut narrow = Chunk::filled(1);
+        narrow.set(3, 4, 5, 2);
+        let mut wide = Chunk::empty();
+        for (i, ([x, y, z], _)) in Chunk::empty().iter().enumerate().take(1000) {
+            wide.set(x, y, z, i as BlockId);
+        }
+        vec![
+            ([0, 0, 0], narrow.clone()),
+            ([1, 0, 0], narrow),
+            ([-1, 2, 9], wide),
+        ]
+    }
+
+    fn save(directory: &Path) -> World {
+        let mut world = World::new();
+        for (pos, chunk) in chunks() {
+            world.insert_chunk(pos, chunk);
+        }
+        let mut store = RegionStore::open(directory).unwrap();
+        store.save_chunks(&world, chunks().into_iter().map(|(pos, _)| pos));
+        store.flush().unwrap();
+        world
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let directory = TempDir::new("region_round_trip");
+        let world = save(&directory.0);
+
+        let mut store = RegionStore::open(&directory.0).unwrap();
+        for (pos, chunk) in world.chunks() {
+            assert!(same(&store.load_chunk(*pos).unwrap(), chunk), "{:?}", pos);
+        }
+        assert!(store.load_chunk([100, 0, 0]).is_none());
+        assert!(store.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn damaged_chunks_are_skipped_and_reported() {
+        let directory = TempDir::new("region_damaged_chunk");
+        save(&directory.0);
+
+        let (region, index) = region_pos([0, 0, 0]);
+        let path = RegionStore::open(&directory.0).unwrap().region_path(region);
+        let mut bytes = fs::read(&path).unwrap();
+        let entry = 8 + index * 12;
+        let offset = u32::from_le_bytes([
+            bytes[entry],
+            bytes[entry + 1],
+            bytes[entry + 2],
+            bytes[entry + 3],
+        ]) as usize;
+        bytes[offset + 5] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        let mut store = RegionStore::open(&directory.0).unwrap();
+        assert!(store.load_chunk([0, 0, 0]).is_none());
+        assert!(store.load_chunk([1, 0, 0]).is_some());
+        let warnings = store.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].to_string().contains("checksum"),
+            "{}",
+            warnings[0]
+        );
+        assert!(store.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn broken_files_are_reported_and_kept() {
+        let directory = TempDir::new("region_broken_file");
+        let world = save(&directory.0);
+
+        let mut store = RegionStore::open(&directory.0).unwrap();
+        let path = store.region_path(region_pos([0, 0, 0]).0);
+        fs::write(&path, b"garbage").unwrap();
+        assert!(store.load_chunk([0, 0, 0]).is_none());
+        let warnings = store.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].to_string().contains("not a region file"),
+            "{}",
+            warnings[0]
+        );
+
+        store.save_chunks(&world, vec![[0, 0, 0]]);
+        store.flush().unwrap();
+        let mut backup = path.into_os_string();
+        backup.push(".corrupt");
+        assert_eq!(fs::read(&backup).unwrap(), b"garbage");
+        let mut store = RegionStore::open(&directory.0).unwrap();
+        assert!(same(
+            &store.load_chunk([0, 0, 0]).unwrap(),
+            world.chunk([0, 0, 0]).unwrap()
+        ));
+    }
+}
@@ -0,0 +1,455 @@
+//! Block types and their properties, loaded from a JSON file like
+//! `assets/blocks.json`:
+//!
+//! ```text
+//! { "blocks": [ { "id": 1, "name": "stone", "color": [0.5, 0.5, 0.5, 1.0], "textures": "stone" }, ... ] }
+//! ```
+//!
+//! Besides `id` and `name` every field is optional. `textures` names the
+//! texture of all faces, `{ "top": .., "bottom": .., "side": .. }` or one name
+//! per face in `Face::ALL` order. The names are layers of a texture array,
+//! `resolve_textures` looks them up once it is built. Block 0 has to be the
+//! non solid air.
+//!
+//! Solid blocks are meshed as cubes. Transparent ones let light through and
+//! don't hide the faces of their neighbours. `collision` is `"none"`, `"full"`
+//! or `{ "box": { "min": [..], "max": [..] } }` in block units and follows
+//! `solid` if it's left out.
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use uv::Vec3;
+
+use crate::loader::error::LoadError;
+use crate::voxel::chunk::{BlockId, AIR};
+use crate::voxel::face::Face;
+use crate::voxel::light::MAX_LIGHT;
+use crate::voxel::world::World;
+
+/// Color of ids the registry doesn't know
+const UNKNOWN_COLOR: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Collision {
+    None,
+    Full,
+    /// Box relative to the block's minimum corner, may reach out of the cell
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+}
+
+impl Collision {
+    /// World space box of a block at `position`
+    pub fn aabb(&self, position: [i32; 3]) -> Option<(Vec3, Vec3)> {
+        let corner = Vec3::new(position[0] as f32, position[1] as f32, position[2] as f32);
+        match *self {
+            Collision::None => None,
+            Collision::Full => Some((corner, corner + Vec3::one())),
+            Collision::Box { min, max } => {
+                Some((corner + Vec3::from(min), corner + Vec3::from(max)))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockType {
+    pub id: BlockId,
+    pub name: String,
+    pub color: [f32; 4],
+    /// Texture of every face in `Face::ALL` order
+    pub textures: Option<[String; 6]>,
+    /// Texture array layers of `textures`, see `BlockRegistry::resolve_textures`
+    layers: Option<[u32; 6]>,
+    pub solid: bool,
+    pub transparent: bool,
+    /// Block light level it gives off
+    pub emission: u8,
+    pub collision: Collision,
+}
+
+impl BlockType {
+    fn unknown(id: BlockId) -> Self {
+        Self {
+            id,
+            name: "unknown".to_string(),
+            color: UNKNOWN_COLOR,
+            textures: None,
+            layers: None,
+            solid: true,
+            transparent: false,
+            emission: 0,
+            collision: Collision::Full,
+        }
+    }
+
+    /// Solid and not transparent: hides neighbouring faces and stops light
+    pub fn is_opaque(&self) -> bool {
+        self.solid && !self.transparent
+    }
+
+    /// Texture array layer of a face, `None` until the textures are resolved
+    pub fn texture(&self, face: Face) -> Option<u32> {
+        self.layers.map(|layers| layers[face.index()])
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TexturesDef {
+    All(String),
+    Sides {
+        top: String,
+        bottom: String,
+        side: String,
+    },
+    Faces([String; 6]),
+}
+
+impl TexturesDef {
+    fn per_face(&self) -> [String; 6] {
+        let name = |i: usize| {
+            let face = Face::ALL[i];
+            match self {
+                TexturesDef::All(name) => name.clone(),
+                TexturesDef::Faces(names) => names[i].clone(),
+                // up is -y
+                TexturesDef::Sides { top, .. } if face == Face::NegY => top.clone(),
+                TexturesDef::Sides { bottom, .. } if face == Face::PosY => bottom.clone(),
+                TexturesDef::Sides { side, .. } => side.clone(),
+            }
+        };
+        [name(0), name(1), name(2), name(3), name(4), name(5)]
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDef {
+    id: BlockId,
+    name: String,
+    #[serde(default = "white")]
+    color: [f32; 4],
+    textures: Option<TexturesDef>,
+    #[serde(default = "yes")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    emission: u8,
+    collision: Option<Collision>,
+}
+
+fn white() -> [f32; 4] {
+    [1.0; 4]
+}
+
+fn yes() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct RegistryDef {
+    blocks: Vec<BlockDef>,
+}
+
+pub struct BlockRegistry {
+    /// Indexed by id, gaps hold unknown blocks
+    types: Vec<BlockType>,
+    unknown: BlockType,
+    names: HashMap<String, BlockId>,
+}
+
+impl BlockRegistry {
+    /// The block types of `assets/blocks.json`
+    pub fn builtin() -> Self {
+        Self::parse(include_str!("../../assets/blocks.json")).expect("assets/blocks.json is broken")
+    }
+
+    pub fn parse(json: &str) -> Result<Self, LoadError> {
+        let definition: RegistryDef = serde_json::from_str(json)?;
+
+        let mut types: Vec<BlockType> = Vec::new();
+        let mut defined = Vec::new();
+        let mut names = HashMap::new();
+        for block in definition.blocks {
+            let id = block.id as usize;
+            if block.emission > MAX_LIGHT {
+                return Err(LoadError::format(format!(
+                    "{} emits light level {}, the maximum is {}",
+                    block.name, block.emission, MAX_LIGHT
+                )));
+            }
+            if names.insert(block.name.clone(), block.id).is_some() {
+                return Err(LoadError::format(format!(
+                    "block name {} used twice",
+                    block.name
+                )));
+            }
+            if id < defined.len() && defined[id] {
+                return Err(LoadError::format(format!("block id {} used twice", id)));
+            }
+
+            while types.len() <= id {
+                defined.push(false);
+                types.push(BlockType::unknown(types.len() as BlockId));
+            }
+            defined[id] = true;
+            let collision = block.collision.unwrap_or(if block.solid {
+                Collision::Full
+            } else {
+                Collision::None
+            });
+            types[id] = BlockType {
+                id: block.id,
+                name: block.name,
+                color: block.color,
+                textures: block.textures.as_ref().map(TexturesDef::per_face),
+                layers: None,
+                solid: block.solid,
+                transparent: block.transparent,
+                emission: block.emission,
+                collision,
+            };
+        }
+
+        match types.first() {
+            Some(air) if !air.solid => {}
+            _ => {
+                return Err(LoadError::format(format!(
+                    "block {} has to be non solid air",
+                    AIR
+                )))
+            }
+        }
+
+        Ok(Self {
+            types,
+            unknown: BlockType::unknown(0),
+            names,
+        })
+    }
+
+    /// Properties of a block, ids that weren't defined are solid and magenta
+    pub fn get(&self, id: BlockId) -> &BlockType {
+        self.types.get(id as usize).unwrap_or(&self.unknown)
+    }
+
+    /// Finds the texture array layer of every face's texture with `layer`,
+    /// e.g. `ArrayBuilder::layer`. Textures it doesn't know are an error.
+    pub fn resolve_textures<F>(&mut self, layer: F) -> Result<(), LoadError>
+    where
+        F: Fn(&str) -> Option<u32>,
+    {
+        for block in &mut self.types {
+            if let Some(textures) = &block.textures {
+                let mut layers = [0; 6];
+                for (l, name) in layers.iter_mut().zip(textures.iter()) {
+                    *l = layer(name).ok_or_else(|| {
+                        LoadError::format(format!("{} has no texture {}", block.name, name))
+                    })?;
+                }
+                block.layers = Some(layers);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_solid(&self, id: BlockId) -> bool {
+        self.get(id).solid
+    }
+
+    pub fn is_opaque(&self, id: BlockId) -> bool {
+        self.get(id).is_opaque()
+    }
+
+    pub fn emission(&self, id: BlockId) -> u8 {
+        self.get(id).emission
+    }
+
+    pub fn color(&self, id: BlockId) -> [f32; 4] {
+        self.get(id).color
+    }
+
+    /// Every defined block type
+    pub fn iter(&self) -> impl Iterator<Item = &BlockType> {
+        self.types
+            .iter()
+            .filter(move |block| self.names.get(&block.name) == Some(&block.id))
+    }
+
+    /// Collision boxes of the blocks near the box `min` - `max`, e.g. to move
+    /// a body through the world. Looks one block further in every direction,
+    /// as collision boxes may reach out of their cells.
+    pub fn colliders(&self, world: &World, min: Vec3, max: Vec3) -> Vec<(Vec3, Vec3)> {
+        let from = [
+            min.x.floor() as i32 - 1,
+            min.y.floor() as i32 - 1,
+            min.z.floor() as i32 - 1,
+        ];
+        let to = [
+            max.x.floor() as i32 + 1,
+            max.y.floor() as i32 + 1,
+            max.z.floor() as i32 + 1,
+        ];
+
+        let mut boxes = Vec::new();
+        for z in from[2]..=to[2] {
+            for y in from[1]..=to[1] {
+                for x in from[0]..=to[0] {
+                    let block = world.get(x, y, z);
+                    if block == AIR {
+                        continue;
+                    }
+                    if let Some((lo, hi)) = self.get(block).collision.aabb([x, y, z]) {
+                        let overlaps = lo.x < max.x
+                            && hi.x > min.x
+                            && lo.y < max.y
+                            && hi.y > min.y
+                            && lo.z < max.z
+                            && hi.z > min.z;
+                        if overlaps {
+                            boxes.push((lo, hi));
+                        }
+                    }
+                }
+            }
+        }
+        boxes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Air and `blocks`, which are JSON objects
+    fn parse(blocks: &str) -> Result<BlockRegistry, LoadError> {
+        BlockRegistry::parse(&format!(
+            r#"{{ "blocks": [ {{ "id": 0, "name": "air", "solid": false }}, {} ] }}"#,
+            blocks
+        ))
+    }
+
+    #[test]
+    fn textures_are_given_for_all_sides_or_every_face() {
+        let mut registry = parse(
+            r#"
+            { "id": 1, "name": "all", "textures": "a" },
+            { "id": 2, "name": "sides", "textures": { "top": "t", "bottom": "b", "side": "s" } },
+            { "id": 3, "name": "faces", "textures": ["a", "b", "c", "d", "e", "f"] },
+            { "id": 4, "name": "plain" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(registry.get(1).texture(Face::PosX), None);
+
+        const LAYERS: [&str; 9] = ["a", "b", "c", "d", "e", "f", "t", "s", "unused"];
+        registry
+            .resolve_textures(|name| LAYERS.iter().position(|&l| l == name).map(|l| l as u32))
+            .unwrap();
+        let layers = |id: BlockId| {
+            let block = registry.get(id);
+            Face::ALL
+                .iter()
+                .map(|&face| block.texture(face))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(layers(1), [Some(0); 6]);
+        // up is -y
+        assert_eq!(
+            layers(2),
+            [Some(7), Some(7), Some(1), Some(6), Some(7), Some(7)]
+        );
+        assert_eq!(
+            layers(3),
+            [Some(0), Some(1), Some(2), Some(3), Some(4), Some(5)]
+        );
+        assert_eq!(layers(4), [None; 6]);
+    }
+
+    #[test]
+    fn unknown_textures_are_an_error() {
+        let mut registry = parse(r#"{ "id": 1, "name": "stone", "textures": "stone" }"#).unwrap();
+        assert!(registry.resolve_textures(|_| None).is_err());
+    }
+
+    #[test]
+    fn ids_and_names_are_unique() {
+        assert!(parse(r#"{ "id": 1, "name": "stone" }, { "id": 2, "name": "dirt" }"#).is_ok());
+        assert!(parse(r#"{ "id": 1, "name": "stone" }, { "id": 1, "name": "dirt" }"#).is_err());
+        assert!(parse(r#"{ "id": 1, "name": "stone" }, { "id": 2, "name": "stone" }"#).is_err());
+        assert!(parse(r#"{ "id": 0, "name": "void", "solid": false }"#).is_err());
+    }
+
+    #[test]
+    fn air_is_not_solid() {
+        assert!(BlockRegistry::parse(r#"{ "blocks": [ { "id": 0, "name": "air" } ] }"#).is_err());
+        // missing ids are solid
+        assert!(BlockRegistry::parse(r#"{ "blocks": [ { "id": 1, "name": "stone" } ] }"#).is_err());
+
+        let registry = parse(r#"{ "id": 2, "name": "stone" }"#).unwrap();
+        assert!(!registry.is_solid(AIR));
+        assert!(registry.is_solid(1));
+        assert_eq!(registry.iter().count(), 2);
+    }
+
+    #[test]
+    fn emission_is_at_most_max_light() {
+        let lamp = |emission: u8| {
+            parse(&format!(
+                r#"{{ "id": 1, "name": "lamp", "emission": {} }}"#,
+                emission
+            ))
+        };
+        assert_eq!(lamp(MAX_LIGHT).unwrap().emission(1), MAX_LIGHT);
+        assert!(lamp(MAX_LIGHT + 1).is_err());
+    }
+
+    #[test]
+    fn collision_follows_solid_unless_given() {
+        let registry = parse(
+            r#"
+            { "id": 1, "name": "stone" },
+            { "id": 2, "name": "flower", "solid": false },
+            { "id": 3, "name": "ghost", "collision": "none" },
+            { "id": 4, "name": "slab", "collision": { "box": { "min": [0, 0.5, 0], "max": [1, 1, 1] } } }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(registry.get(1).collision, Collision::Full);
+        assert_eq!(registry.get(2).collision, Collision::None);
+        assert_eq!(registry.get(3).collision, Collision::None);
+        let (min, max) = registry.get(4).collision.aabb([1, 2, 3]).unwrap();
+        assert_eq!((min.x, min.y, min.z), (1.0, 2.5, 3.0));
+        assert_eq!((max.x, max.y, max.z), (2.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn colliders_are_the_overlapping_boxes() {
+        let registry = BlockRegistry::builtin();
+        let mut world = World::new();
+        world.set(0, 0, 0, 1);
+        // the fence post reaches half a block up into the cell above
+        world.set(0, 2, 0, 9);
+
+        let around = |x: f32, y: f32| {
+            let center = Vec3::new(x, y, 0.5);
+            registry.colliders(
+                &world,
+                center - Vec3::broadcast(0.1),
+                center + Vec3::broadcast(0.1),
+            )
+        };
+        assert_eq!(around(0.5, 0.5).len(), 1);
+        assert!(around(1.5, 0.5).is_empty());
+        assert_eq!(around(0.5, 1.6).len(), 1);
+        // next to the post, still in its cell
+        assert!(around(0.1, 2.5).is_empty());
+        assert!(around(0.5, 1.2).is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+//! Background meshing of dirty chunks.
+//!
+//! The main thread copies each dirty chunk with its border into a
+//! `Neighbourhood` and queues it for a pool of worker threads. Finished meshes
+//! come back over a channel and get uploaded on the main thread, at most
+//! `UPLOAD_BUDGET` bytes per frame so a burst of edits doesn't stall it.
+//!
+//! Every job carries a generation. Scheduling a chunk again bumps it, which
+//! makes workers skip the older job and the main thread drop its result.
+//! Jobs for a level of detail above 0 are downsampled on the worker as well.
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::geometry::mesh::Mesh;
+use crate::types::VertexAO;
+use crate::voxel::light::LightMap;
+use crate::voxel::mesher::{mesh_greedy, Neighbourhood};
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::world::{ChunkPos, World};
+
+/// Bytes of vertex and index data uploaded per `Remesher::update`. At least
+/// one mesh is uploaded per call, even if it is bigger than that.
+const UPLOAD_BUDGET: usize = 4 * 1024 * 1024;
+
+struct Job {
+    chunk: ChunkPos,
+    generation: u64,
+    lod: u32,
+    blocks: Neighbourhood,
+}
+
+struct Finished {
+    chunk: ChunkPos,
+    generation: u64,
+    mesh: Mesh<VertexAO>,
+}
+
+impl Finished {
+    fn size(&self) -> usize {
+        self.mesh.vertices.len() * std::mem::size_of::<VertexAO>()
+            + self.mesh.indices.len() * std::mem::size_of::<u32>()
+    }
+}
+
+/// GPU buffers of one chunk, indices are `wgpu::IndexFormat::Uint32`
+pub struct ChunkMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+}
+
+pub struct Remesher {
+    jobs: Option<Sender<Job>>,
+    finished: Receiver<Finished>,
+    workers: Vec<JoinHandle<()>>,
+    /// Newest generation of every chunk that is queued, being meshed or waiting
+    /// for upload, shared so workers can skip outdated jobs
+    latest: Arc<Mutex<HashMap<ChunkPos, u64>>>,
+    next_generation: u64,
+    ready: VecDeque<Finished>,
+    meshes: HashMap<ChunkPos, ChunkMesh>,
+    /// Level of detail each chunk was last scheduled with
+    lods: HashMap<ChunkPos, u32>,
+    skirts: bool,
+}
+
+impl Remesher {
+    pub fn new(workers: usize, registry: Arc<BlockRegistry>) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (finished_sender, finished) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let latest = Arc::new(Mutex::new(HashMap::new()));
+
+        let workers = (0..workers.max(1))
+            .map(|i| {
+                let jobs = Arc::clone(&job_receiver);
+                let finished = finished_sender.clone();
+                let latest = Arc::clone(&latest);
+                let registry = Arc::clone(&registry);
+                thread::Builder::new()
+                    .name(format!("chunk mesher {}", i))
+                    .spawn(move || worker(jobs, finished, latest, registry))
+                    .expect("failed to spawn a chunk mesher thread")
+            })
+            .collect();
+
+        Self {
+            jobs: Some(job_sender),
+            finished,
+            workers,
+            latest,
+            next_generation: 0,
+            ready: VecDeque::new(),
+            meshes: HashMap::new(),
+            lods: HashMap::new(),
+            skirts: false,
+        }
+    }
+
+    /// Meshes chunks with skirts from now on, needed once neighbouring chunks
+    /// can have different levels of detail
+    pub fn set_skirts(&mut self, skirts: bool) {
+        self.skirts = skirts;
+    }
+
+    /// Queues a chunk, replacing any job for it that hasn't been uploaded yet.
+    /// The chunk is lit at full detail if a light map is given.
+    pub fn schedule(&mut self, world: &World, light: Option<&LightMap>, chunk: ChunkPos, lod: u32) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.latest.lock().unwrap().insert(chunk, generation);
+        self.lods.insert(chunk, lod);
+
+        let mut blocks = Neighbourhood::from_world(world, chunk).with_skirts(self.skirts);
+        if let (Some(light), 0) = (light, lod) {
+            blocks = blocks.with_light(light);
+        }
+        let job = Job {
+            chunk,
+            generation,
+            lod,
+            blocks,
+        };
+        if let Some(jobs) = &self.jobs {
+            // the workers only hang up when they panicked
+            jobs.send(job).expect("chunk mesher threads stopped");
+        }
+    }
+
+    /// Drops the mesh of a chunk along with any job for it
+    pub fn remove(&mut self, chunk: ChunkPos) -> Option<ChunkMesh> {
+        self.latest.lock().unwrap().remove(&chunk);
+        self.lods.remove(&chunk);
+        self.meshes.remove(&chunk)
+    }
+
+    /// Level of detail of the latest job for a chunk, whether it is done or not
+    pub fn lod(&self, chunk: ChunkPos) -> Option<u32> {
+        self.lods.get(&chunk).copied()
+    }
+
+    /// Collects finished meshes and uploads them within the budget, returns how
+    /// many chunks got new buffers
+    pub fn update(&mut self, device: &wgpu::Device) -> usize {
+        self.ready.extend(self.finished.try_iter());
+
+        let mut latest = self.latest.lock().unwrap();
+        let mut uploaded = 0;
+        let mut budget = UPLOAD_BUDGET;
+        while let Some(finished) = self.ready.front() {
+            if latest.get(&finished.chunk) != Some(&finished.generation) {
+                self.ready.pop_front();
+                continue;
+            }
+            let size = finished.size();
+            if uploaded > 0 && size > budget {
+                break;
+            }
+            budget = budget.saturating_sub(size);
+
+            let finished = self.ready.pop_front().unwrap();
+            latest.remove(&finished.chunk);
+            if finished.mesh.is_empty() {
+                self.meshes.remove(&finished.chunk);
+            } else {
+                self.meshes.insert(
+                    finished.chunk,
+                    ChunkMesh {
+                        vertex_buffer: finished.mesh.create_vertex_buffer(device),
+                        index_buffer: finished.mesh.create_index_buffer(device),
+                        num_indices: finished.mesh.num_indices(),
+                    },
+                );
+            }
+            uploaded += 1;
+        }
+        uploaded
+    }
+
+    /// Chunks that were scheduled but haven't been uploaded yet
+    pub fn pending(&self) -> usize {
+        self.latest.lock().unwrap().len()
+    }
+
+    pub fn meshes(&self) -> impl Iterator<Item = (&ChunkPos, &ChunkMesh)> {
+        self.meshes.iter()
+    }
+
+    /// Draws every uploaded chunk with whatever pipeline and bind groups are set
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        for mesh in self.meshes.values() {
+            render_pass.set_vertex_buffer(0, &mesh.vertex_buffer, 0, 0);
+            render_pass.set_index_buffer(&mesh.index_buffer, 0, 0);
+            render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+        }
+    }
+}
+
+impl Drop for Remesher {
+    fn drop(&mut self) {
+        // closing the job channel ends the worker loops
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker(
+    jobs: Arc<Mutex<Receiver<Job>>>,
+    finished: Sender<Finished>,
+    latest: Arc<Mutex<HashMap<ChunkPos, u64>>>,
+    registry: Arc<BlockRegistry>,
+) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        if latest.lock().unwrap().get(&job.chunk) != Some(&job.generation) {
+            continue;
+        }
+
+        let blocks = if job.lod > 0 {
+            job.blocks.downsampled(job.lod, &registry)
+        } else {
+            job.blocks
+        };
+        let mesh = mesh_greedy(&blocks, &registry);
+        let result = Finished {
+            chunk: job.chunk,
+            generation: job.generation,
+            mesh,
+        };
+        if finished.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn rescheduling_drops_the_older_job() {
+        let mut world = World::new();
+        world.set(1, 1, 1, 1);
+        let mut remesher = Remesher::new(3, Arc::new(BlockRegistry::builtin()));
+        remesher.schedule(&world, None, [0, 0, 0], 0);
+        world.set(2, 1, 1, 1);
+        remesher.schedule(&world, None, [0, 0, 0], 0);
+        assert_eq!(remesher.pending(), 1);
+
+        // the first job may or may not have been skipped by a worker
+        let mut finished = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !finished.iter().any(|f: &Finished| f.generation == 1) {
+            assert!(Instant::now() < deadline, "the newer job never finished");
+            finished.extend(remesher.finished.try_iter());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let newest = finished.iter().find(|f| f.generation == 1).unwrap();
+        // two blocks side by side, the greedy mesher merges all their faces
+        assert_eq!(newest.mesh.num_indices(), 6 * 6);
+
+        remesher.remove([0, 0, 0]);
+        assert_eq!(remesher.pending(), 0);
+    }
+}
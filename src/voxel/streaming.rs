@@ -0,0 +1,441 @@
+//! Keeps the chunks around the camera loaded, generated and meshed.
+//!
+//! Chunks are generated within `load_radius` chunks of the camera and only
+//! unloaded once they are further than `unload_radius`, so moving back and
+//! forth over a chunk border doesn't regenerate the same chunks over and over.
+//! Generation and remeshing handle chunks in the camera's view first, then by
+//! distance. With a `RegionStore` edited chunks are saved when they get
+//! unloaded and saved chunks are read back instead of generated, without one
+//! unloaded chunks are regenerated from the seed and lose their edits. Saved
+//! chunks are only written to disk by `save`, call it every now and then.
+//!
+//! With a `LightMap` chunks get lit as they come in and meshed with their light.
+//!
+//! Chunks are meshed at a level of detail picked by their distance and get
+//! remeshed whenever the camera moves far enough for it to change.
+use crate::loader::error::LoadError;
+use crate::tools::camera::Camera;
+use crate::tools::frustum::Frustum;
+use crate::voxel::chunk::CHUNK_SIZE;
+use crate::voxel::light::LightMap;
+use crate::voxel::region::RegionStore;
+use crate::voxel::remesh::Remesher;
+use crate::voxel::terrain::Terrain;
+use crate::voxel::world::{split_pos, world_pos, ChunkPos, World};
+
+/// Picks coarser meshes for chunks further away from the camera
+#[derive(Clone, Copy, Debug)]
+pub struct LodSettings {
+    /// Chunks closer than this many chunks are meshed at full detail
+    pub full_detail: f32,
+    /// Every this many chunks further away the resolution halves again
+    pub step: f32,
+    pub max_level: u32,
+}
+
+impl LodSettings {
+    /// Level of detail for a chunk `distance` chunks away
+    pub fn level(&self, distance: f32) -> u32 {
+        if distance < self.full_detail || self.step <= 0.0 {
+            return 0;
+        }
+        (((distance - self.full_detail) / self.step) as u32 + 1).min(self.max_level)
+    }
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            full_detail: 3.0,
+            step: 3.0,
+            max_level: 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StreamSettings {
+    /// Chunks within this many chunks of the camera get loaded
+    pub load_radius: i32,
+    /// Chunks further away than this get unloaded, should be above `load_radius`
+    pub unload_radius: i32,
+    /// Chunks generated per update at most, generation runs on the main thread
+    pub generate_per_frame: usize,
+    pub lod: LodSettings,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            load_radius: 6,
+            unload_radius: 8,
+            generate_per_frame: 4,
+            lod: LodSettings::default(),
+        }
+    }
+}
+
+/// Numbers of the last update, for tuning the view distance
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StreamStats {
+    /// Chunks in the world
+    pub loaded: usize,
+    /// Chunks waiting to be meshed or uploaded
+    pub pending: usize,
+    /// Chunks with GPU buffers
+    pub meshed: usize,
+    /// Chunks within the load radius that still have to be generated
+    pub missing: usize,
+    pub generated: usize,
+    /// Chunks read from region files
+    pub read: usize,
+    pub unloaded: usize,
+    /// Edited chunks saved while unloading
+    pub saved: usize,
+    pub uploaded: usize,
+    /// Damaged region files and chunks skipped, see `take_warnings`
+    pub warnings: usize,
+}
+
+pub struct ChunkStreamer {
+    settings: StreamSettings,
+    terrain: Terrain,
+    remesher: Remesher,
+    store: Option<RegionStore>,
+    light: Option<LightMap>,
+    stats: StreamStats,
+    warnings: Vec<LoadError>,
+}
+
+impl ChunkStreamer {
+    pub fn new(terrain: Terrain, mut remesher: Remesher, settings: StreamSettings) -> Self {
+        remesher.set_skirts(settings.lod.max_level > 0);
+        Self {
+            settings,
+            terrain,
+            remesher,
+            store: None,
+            light: None,
+            stats: StreamStats::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Where chunks are saved to and loaded from, `None` to not persist them
+    pub fn set_store(&mut self, store: Option<RegionStore>) {
+        self.store = store;
+    }
+
+    /// Lights chunks loaded from now on, `None` to mesh everything fully lit
+    pub fn set_light(&mut self, light: Option<LightMap>) {
+        self.light = light;
+    }
+
+    /// The light map, e.g. to update it after edits
+    pub fn light_mut(&mut self) -> Option<&mut LightMap> {
+        self.light.as_mut()
+    }
+
+    /// Saves every edited chunk and writes the chunks saved while unloading
+    /// to disk, e.g. every few seconds and before quitting
+    pub fn save(&mut self, world: &mut World) -> Result<(), LoadError> {
+        match &mut self.store {
+            Some(store) => store.save_modified(world),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.stats
+    }
+
+    /// Damaged region files and chunks skipped while loading since the last
+    /// call, their chunks got generated instead
+    pub fn take_warnings(&mut self) -> Vec<LoadError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Where missing chunks come from
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    /// The chunk meshes, e.g. to draw them
+    pub fn remesher(&self) -> &Remesher {
+        &self.remesher
+    }
+
+    pub fn update(&mut self, world: &mut World, camera: &Camera, device: &wgpu::Device) {
+        let position = camera.position();
+        let (center, _) = split_pos(
+            position.x.floor() as i32,
+            position.y.floor() as i32,
+            position.z.floor() as i32,
+        );
+        let frustum = camera.frustum();
+        let (unloaded, saved) = self.unload(world, center);
+        let (missing, generated, read) = self.load(world, center, &frustum);
+
+        let lod_settings = self.settings.lod;
+        let lod = |pos: ChunkPos| {
+            let center = world_pos(pos, [CHUNK_SIZE / 2; 3]);
+            let center = uv::Vec3::new(center[0] as f32, center[1] as f32, center[2] as f32);
+            lod_settings.level((center - position).mag() / CHUNK_SIZE as f32)
+        };
+        let mut remesh = world.take_dirty();
+        remesh.extend(
+            world
+                .chunks()
+                .map(|(&pos, _)| pos)
+                .filter(|&pos| self.remesher.lod(pos) != Some(lod(pos))),
+        );
+        remesh.sort();
+        remesh.dedup();
+        remesh.sort_by_key(|&pos| priority(&frustum, center, pos));
+        for pos in remesh {
+            let level = lod(pos);
+            self.remesher
+                .schedule(world, self.light.as_ref(), pos, level);
+        }
+        let uploaded = self.remesher.update(device);
+
+        let warnings = match &mut self.store {
+            Some(store) => store.take_warnings(),
+            None => Vec::new(),
+        };
+
+        self.stats = StreamStats {
+            loaded: world.chunk_count(),
+            pending: self.remesher.pending(),
+            meshed: self.remesher.meshes().count(),
+            missing,
+            generated,
+            read,
+            unloaded,
+            saved,
+            uploaded,
+            warnings: warnings.len(),
+        };
+        self.warnings.extend(warnings);
+    }
+
+    /// Chunks within the load radius that haven't been generated yet, the
+    /// ones to load first at the front
+    fn missing(&self, center: ChunkPos, frustum: &Frustum) -> Vec<ChunkPos> {
+        let radius = self.settings.load_radius;
+        let mut missing = Vec::new();
+        for dz in -radius..=radius {
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let pos = [center[0] + dx, center[1] + dy, center[2] + dz];
+                    if distance_sq(center, pos) <= radius * radius
+                        && !self.terrain.is_generated(pos)
+                    {
+                        missing.push(pos);
+                    }
+                }
+            }
+        }
+        missing.sort_by_key(|&pos| priority(frustum, center, pos));
+        missing
+    }
+
+    /// Reads or generates at most `generate_per_frame` missing chunks and
+    /// lights them. Returns how many chunks are still missing, how many were
+    /// generated and how many were read.
+    fn load(
+        &mut self,
+        world: &mut World,
+        center: ChunkPos,
+        frustum: &Frustum,
+    ) -> (usize, usize, usize) {
+        let missing = self.missing(center, frustum);
+        let attempts = missing.len().min(self.settings.generate_per_frame);
+        let mut generated = 0;
+        let mut read = 0;
+        for &pos in missing[..attempts].iter() {
+            let mut added = false;
+            if let Some(chunk) = self.store.as_mut().and_then(|s| s.load_chunk(pos)) {
+                world.insert_chunk(pos, chunk);
+                read += 1;
+                added = true;
+            }
+            // skips chunks that were just read, but marks them as handled
+            if self.terrain.generate_chunk(world, pos) {
+                generated += 1;
+                added = true;
+            }
+            if let (true, Some(light)) = (added, &mut self.light) {
+                light.add_chunk(world, pos);
+            }
+        }
+        (missing.len() - attempts, generated, read)
+    }
+
+    /// Drops chunks, their meshes and the generated markers beyond the unload
+    /// radius after saving the edited ones to the store, they reach the disk
+    /// with the next `save`. Returns how many chunks left the world and how
+    /// many were saved.
+    fn unload(&mut self, world: &mut World, center: ChunkPos) -> (usize, usize) {
+        let radius_sq = self.settings.unload_radius * self.settings.unload_radius;
+        let far = |pos: ChunkPos| distance_sq(center, pos) > radius_sq;
+
+        let mut unload: Vec<ChunkPos> = world
+            .chunks()
+            .map(|(&pos, _)| pos)
+            .chain(self.remesher.meshes().map(|(&pos, _)| pos))
+            .chain(world.modified())
+            .filter(|&pos| far(pos))
+            .collect();
+        unload.sort();
+        unload.dedup();
+
+        let mut saved = 0;
+        if let Some(store) = &mut self.store {
+            let modified: Vec<ChunkPos> = unload
+                .iter()
+                .copied()
+                .filter(|&pos| world.is_modified(pos))
+                .collect();
+            saved = modified.len();
+            store.save_chunks(world, modified);
+        }
+
+        let mut unloaded = 0;
+        for pos in unload {
+            if world.remove_chunk(pos).is_some() {
+                unloaded += 1;
+            }
+            // dropping the buffers frees their GPU memory
+            self.remesher.remove(pos);
+            if let Some(light) = &mut self.light {
+                light.remove_chunk(pos);
+            }
+        }
+        self.terrain.forget_where(far);
+        (unloaded, saved)
+    }
+}
+
+fn distance_sq(a: ChunkPos, b: ChunkPos) -> i32 {
+    let d = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+}
+
+/// Chunks in view first, then the closest
+fn priority(frustum: &Frustum, center: ChunkPos, pos: ChunkPos) -> (bool, i32) {
+    (!is_visible(frustum, pos), distance_sq(center, pos))
+}
+
+fn is_visible(frustum: &Frustum, pos: ChunkPos) -> bool {
+    let min = world_pos(pos, [0, 0, 0]);
+    let min = uv::Vec3::new(min[0] as f32, min[1] as f32, min[2] as f32);
+    let max = min + uv::Vec3::broadcast(CHUNK_SIZE as f32);
+    frustum.intersects_aabb(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use uv::projection::rh_ydown::perspective_vk;
+
+    use super::*;
+    use crate::voxel::registry::BlockRegistry;
+    use crate::voxel::terrain::TerrainGenerator;
+
+    fn streamer(load_radius: i32, unload_radius: i32, generate_per_frame: usize) -> ChunkStreamer {
+        let remesher = Remesher::new(1, Arc::new(BlockRegistry::builtin()));
+        let settings = StreamSettings {
+            load_radius,
+            unload_radius,
+            generate_per_frame,
+            lod: LodSettings::default(),
+        };
+        ChunkStreamer::new(Terrain::new(TerrainGenerator::new(7)), remesher, settings)
+    }
+
+    /// Looking down -z from the center of the chunk at the origin
+    fn frustum() -> Frustum {
+        let view = uv::Mat4::from_translation(uv::Vec3::broadcast(-(CHUNK_SIZE as f32) / 2.0));
+        let projection = perspective_vk(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 1000.0);
+        Frustum::from_matrix(projection * view)
+    }
+
+    #[test]
+    fn chunks_only_unload_beyond_the_unload_radius() {
+        let mut streamer = streamer(1, 2, 100);
+        let mut world = World::new();
+        assert_eq!(streamer.load(&mut world, [0, 0, 0], &frustum()).0, 0);
+        assert!(streamer.terrain.is_generated([-1, 0, 0]));
+
+        // one chunk over the border and back doesn't unload anything
+        streamer.unload(&mut world, [1, 0, 0]);
+        streamer.load(&mut world, [1, 0, 0], &frustum());
+        streamer.unload(&mut world, [0, 0, 0]);
+        assert!(streamer.terrain.is_generated([-1, 0, 0]));
+        assert!(streamer.terrain.is_generated([2, 0, 0]));
+
+        streamer.unload(&mut world, [2, 0, 0]);
+        assert!(!streamer.terrain.is_generated([-1, 0, 0]));
+        assert!(streamer.terrain.is_generated([0, 0, 0]));
+        assert!(world
+            .chunks()
+            .all(|(&pos, _)| distance_sq([2, 0, 0], pos) <= 2 * 2));
+    }
+
+    #[test]
+    fn chunks_in_view_load_first_then_the_closest() {
+        let streamer = streamer(2, 3, 100);
+        let frustum = frustum();
+        let missing = streamer.missing([0, 0, 0], &frustum);
+        assert_eq!(missing[0], [0, 0, 0]);
+
+        let index = |pos: ChunkPos| missing.iter().position(|&p| p == pos).unwrap();
+        // further away, but in front of the camera
+        assert!(index([0, 0, -2]) < index([0, 0, 1]));
+
+        let visible = missing
+            .iter()
+            .take_while(|&&pos| is_visible(&frustum, pos))
+            .count();
+        assert!(visible > 1 && visible < missing.len());
+        assert!(missing[visible..]
+            .iter()
+            .all(|&pos| !is_visible(&frustum, pos)));
+        for group in [&missing[..visible], &missing[visible..]].iter() {
+            assert!(group
+                .windows(2)
+                .all(|w| distance_sq([0, 0, 0], w[0]) <= distance_sq([0, 0, 0], w[1])));
+        }
+    }
+
+    #[test]
+    fn an_update_generates_at_most_its_budget() {
+        let mut streamer = streamer(2, 3, 3);
+        let mut world = World::new();
+        let before = streamer.missing([0, 0, 0], &frustum());
+
+        let (missing, _, _) = streamer.load(&mut world, [0, 0, 0], &frustum());
+        assert_eq!(missing, before.len() - 3);
+        assert!(before[..3]
+            .iter()
+            .all(|&pos| streamer.terrain.is_generated(pos)));
+        assert_eq!(streamer.missing([0, 0, 0], &frustum()), &before[3..]);
+    }
+
+    #[test]
+    fn lod_halves_every_step_up_to_the_maximum() {
+        let lod = LodSettings::default();
+        assert_eq!(lod.level(0.0), 0);
+        assert_eq!(lod.level(2.9), 0);
+        assert_eq!(lod.level(3.0), 1);
+        assert_eq!(lod.level(5.9), 1);
+        assert_eq!(lod.level(6.0), 2);
+        assert_eq!(lod.level(9.0), 3);
+        assert_eq!(lod.level(100.0), 3);
+
+        let full = LodSettings { step: 0.0, ..lod };
+        assert_eq!(full.level(100.0), 0);
+    }
+}
@@ -0,0 +1,350 @@
+//! Procedural terrain for the voxel world.
+//!
+//! Column heights come from fBm simplex noise with a low frequency mask that
+//! raises mountain ranges, biomes from temperature and moisture noise, and
+//! caves are carved wherever 3D fBm perlin noise passes a threshold. Every
+//! block only depends on the seed and its position, so chunks can be generated
+//! in any order. Heights count up, which is towards -y in the world.
+use std::collections::HashSet;
+
+use crate::voxel::chunk::{BlockId, Chunk, AIR, CHUNK_SIZE};
+use crate::voxel::noise::{splitmix64, Fbm, Noise};
+use crate::voxel::world::{world_pos, ChunkPos, World};
+
+// Ids of the generated block types in `assets/blocks.json`
+pub const STONE: BlockId = 1;
+pub const DIRT: BlockId = 2;
+pub const GRASS: BlockId = 3;
+pub const SAND: BlockId = 4;
+pub const SNOW: BlockId = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+#[derive(Clone, Debug)]
+pub struct TerrainSettings {
+    /// Height of flat ground
+    pub base_height: f64,
+    pub hills: Fbm,
+    pub hill_height: f64,
+    /// Extra height on top of the hills where the mountain mask is at 1
+    pub mountain_height: f64,
+    pub mountain_frequency: f64,
+    pub biome_frequency: f64,
+    /// Mountains are covered in snow above this height
+    pub snow_line: i32,
+    pub caves: Fbm,
+    /// Noise value above which caves are carved, higher means fewer caves
+    pub cave_threshold: f64,
+    /// Blocks below the surface that caves can't reach, keeps the ground closed
+    pub cave_roof: i32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            base_height: 16.0,
+            hills: Fbm::new(5, 1.0 / 128.0),
+            hill_height: 12.0,
+            mountain_height: 72.0,
+            mountain_frequency: 1.0 / 768.0,
+            biome_frequency: 1.0 / 512.0,
+            snow_line: 64,
+            caves: Fbm::new(3, 1.0 / 48.0),
+            cave_threshold: 0.3,
+            cave_roof: 5,
+        }
+    }
+}
+
+/// Column of terrain shared by all blocks with the same x and z
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Column {
+    pub height: i32,
+    pub biome: Biome,
+}
+
+pub struct TerrainGenerator {
+    settings: TerrainSettings,
+    height: Noise,
+    mountains: Noise,
+    temperature: Noise,
+    moisture: Noise,
+    caves: Noise,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self::with_settings(seed, TerrainSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, settings: TerrainSettings) -> Self {
+        // each noise gets its own seed so the layers don't line up
+        let mut state = seed;
+        let mut noise = || Noise::new(splitmix64(&mut state));
+        Self {
+            settings,
+            height: noise(),
+            mountains: noise(),
+            temperature: noise(),
+            moisture: noise(),
+            caves: noise(),
+        }
+    }
+
+    /// How much of the mountain height is added at a column, 0.0 - 1.0
+    fn mountain_mask(&self, x: f64, z: f64) -> f64 {
+        let frequency = self.settings.mountain_frequency;
+        let n = self.mountains.simplex2(x * frequency, z * frequency) * 0.5 + 0.5;
+        smoothstep(0.55, 0.8, n)
+    }
+
+    pub fn column(&self, x: i32, z: i32) -> Column {
+        let settings = &self.settings;
+        let (fx, fz) = (x as f64, z as f64);
+
+        let hills = settings
+            .hills
+            .sample2(|x, y| self.height.simplex2(x, y), fx, fz);
+        let mask = self.mountain_mask(fx, fz);
+        let height = settings.base_height
+            + hills * settings.hill_height
+            + mask * settings.mountain_height * (0.75 + 0.25 * hills);
+        let height = height.floor() as i32;
+
+        let frequency = settings.biome_frequency;
+        let temperature = self.temperature.simplex2(fx * frequency, fz * frequency);
+        let moisture = self.moisture.simplex2(fx * frequency, fz * frequency);
+        let biome = if mask > 0.5 {
+            Biome::Mountains
+        } else if temperature < -0.3 {
+            Biome::Tundra
+        } else if temperature > 0.3 && moisture < 0.0 {
+            Biome::Desert
+        } else {
+            Biome::Plains
+        };
+
+        Column { height, biome }
+    }
+
+    fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        // caves are stretched horizontally
+        let n = self.settings.caves.sample3(
+            |x, y, z| self.caves.perlin3(x, y, z),
+            x as f64,
+            y as f64 * 2.0,
+            z as f64,
+        );
+        n > self.settings.cave_threshold
+    }
+
+    fn column_block(&self, column: Column, x: i32, y: i32, z: i32) -> BlockId {
+        let up = -y;
+        if up > column.height {
+            return AIR;
+        }
+        let depth = column.height - up;
+        if depth >= self.settings.cave_roof && self.is_cave(x, y, z) {
+            return AIR;
+        }
+
+        match (column.biome, depth) {
+            (Biome::Plains, 0) => GRASS,
+            (Biome::Plains, 1..=3) => DIRT,
+            (Biome::Desert, 0..=3) => SAND,
+            (Biome::Tundra, 0) => SNOW,
+            (Biome::Tundra, 1..=2) => DIRT,
+            (Biome::Mountains, 0) if column.height > self.settings.snow_line => SNOW,
+            _ => STONE,
+        }
+    }
+
+    /// Blocks of a chunk, `None` if it would only contain air
+    pub fn generate(&self, pos: ChunkPos) -> Option<Chunk> {
+        let origin = world_pos(pos, [0, 0, 0]);
+        let mut columns = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+        for z in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                columns.push(self.column(origin[0] + x, origin[2] + z));
+            }
+        }
+
+        // the highest block of the chunk is at its lowest y
+        let highest = columns.iter().map(|c| c.height).max().unwrap_or(0);
+        if -origin[1] - (CHUNK_SIZE as i32 - 1) > highest {
+            return None;
+        }
+
+        let mut chunk = Chunk::empty();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let column = columns[z * CHUNK_SIZE + x];
+                for y in 0..CHUNK_SIZE {
+                    let block = self.column_block(
+                        column,
+                        origin[0] + x as i32,
+                        origin[1] + y as i32,
+                        origin[2] + z as i32,
+                    );
+                    if block != AIR {
+                        chunk.set(x, y, z, block);
+                    }
+                }
+            }
+        }
+
+        if chunk.is_empty() {
+            None
+        } else {
+            chunk.compact();
+            Some(chunk)
+        }
+    }
+}
+
+/// Generates the chunks the streamer asks for, remembering which chunks were
+/// already generated so air and edited chunks aren't generated again
+pub struct Terrain {
+    generator: TerrainGenerator,
+    generated: HashSet<ChunkPos>,
+}
+
+impl Terrain {
+    pub fn new(generator: TerrainGenerator) -> Self {
+        Self {
+            generator,
+            generated: HashSet::new(),
+        }
+    }
+
+    pub fn generator(&self) -> &TerrainGenerator {
+        &self.generator
+    }
+
+    /// Generates a single chunk unless it already was, returns whether it was
+    /// added to the world
+    pub fn generate_chunk(&mut self, world: &mut World, pos: ChunkPos) -> bool {
+        if !self.generated.insert(pos) {
+            return false;
+        }
+        // don't overwrite chunks that got there some other way, e.g. loaded
+        if world.contains_chunk(pos) {
+            return false;
+        }
+        match self.generator.generate(pos) {
+            Some(chunk) => {
+                world.insert_chunk(pos, chunk);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_generated(&self, pos: ChunkPos) -> bool {
+        self.generated.contains(&pos)
+    }
+
+    /// Forgets every generated chunk `forget` returns true for
+    pub fn forget_where<F>(&mut self, mut forget: F)
+    where
+        F: FnMut(ChunkPos) -> bool,
+    {
+        self.generated.retain(|&pos| !forget(pos));
+    }
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+
+    fn block_at(generator: &TerrainGenerator, x: i32, y: i32, z: i32) -> BlockId {
+        generator.column_block(generator.column(x, z), x, y, z)
+    }
+
+    #[test]
+    fn a_seed_always_gives_the_same_terrain() {
+        // pinned, a change here changes every world generated so far:
+        // x, z, height, biome and the surface block with the two below it
+        let expected = [
+            (0, 0, 16, Biome::Plains, [GRASS, DIRT, DIRT]),
+            (-12000, -12000, 19, Biome::Plains, [GRASS, DIRT, DIRT]),
+            (-12000, -11700, 47, Biome::Mountains, [STONE, STONE, STONE]),
+            (-12000, -10500, 22, Biome::Tundra, [SNOW, DIRT, DIRT]),
+            (-12000, -8700, 17, Biome::Desert, [SAND, SAND, SAND]),
+        ];
+        let generator = TerrainGenerator::new(SEED);
+        for &(x, z, height, biome, blocks) in expected.iter() {
+            assert_eq!(generator.column(x, z), Column { height, biome });
+            assert_eq!(block_at(&generator, x, -height - 1, z), AIR);
+            for (depth, &block) in blocks.iter().enumerate() {
+                assert_eq!(block_at(&generator, x, -height + depth as i32, z), block);
+            }
+            assert_eq!(block_at(&generator, x, -height + 20, z), STONE);
+        }
+
+        // a cave under the plains next to the origin
+        assert_eq!(generator.column(0, 46).height, 32);
+        assert_eq!(block_at(&generator, 0, -10, 46), AIR);
+        assert_eq!(block_at(&generator, 0, -8, 46), STONE);
+    }
+
+    #[test]
+    fn chunks_match_their_blocks() {
+        let generator = TerrainGenerator::new(SEED);
+        let expected_solid = [([0, 0, 0], 32768), ([0, -1, 0], 19882), ([5, 1, -3], 31885)];
+        for &(pos, solid) in expected_solid.iter() {
+            let chunk = generator.generate(pos).unwrap();
+            assert_eq!(chunk.solid_count(), solid);
+            for ([x, y, z], block) in chunk.iter() {
+                let [x, y, z] = world_pos(pos, [x, y, z]);
+                assert_eq!(block, block_at(&generator, x, y, z));
+            }
+        }
+        assert!(generator.generate([0, -10, 0]).is_none());
+    }
+
+    #[test]
+    fn seeds_give_different_terrain() {
+        let (a, b) = (TerrainGenerator::new(SEED), TerrainGenerator::new(SEED + 1));
+        let differ = (0..100)
+            .filter(|&i| a.column(i * 37, i * -53).height != b.column(i * 37, i * -53).height)
+            .count();
+        assert!(differ > 50);
+    }
+
+    #[test]
+    fn chunks_are_only_generated_once() {
+        let mut world = World::new();
+        let mut terrain = Terrain::new(TerrainGenerator::new(SEED));
+        assert!(terrain.generate_chunk(&mut world, [0, 0, 0]));
+        assert!(terrain.is_generated([0, 0, 0]));
+        assert!(!terrain.generate_chunk(&mut world, [0, 0, 0]));
+
+        // air is remembered too
+        assert!(!terrain.generate_chunk(&mut world, [0, -10, 0]));
+        assert!(terrain.is_generated([0, -10, 0]));
+
+        // chunks already in the world are left alone
+        world.set(CHUNK_SIZE as i32, 0, 0, STONE);
+        assert!(!terrain.generate_chunk(&mut world, [1, 0, 0]));
+
+        terrain.forget_where(|pos| pos == [0, 0, 0]);
+        world.remove_chunk([0, 0, 0]);
+        assert!(!terrain.is_generated([0, 0, 0]));
+        assert!(terrain.generate_chunk(&mut world, [0, 0, 0]));
+        assert_eq!(world.chunk_count(), 2);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::voxel::chunk::{BlockId, Chunk, AIR, CHUNK_SIZE};
+
+/// Chunk coordinate, the chunk at [1, 0, 0] starts at block [CHUNK_SIZE, 0, 0]
+pub type ChunkPos = [i32; 3];
+
+/// Splits a world block position into its chunk and the position inside of it
+pub fn split_pos(x: i32, y: i32, z: i32) -> (ChunkPos, [usize; 3]) {
+    let size = CHUNK_SIZE as i32;
+    (
+        [x.div_euclid(size), y.div_euclid(size), z.div_euclid(size)],
+        [
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        ],
+    )
+}
+
+/// World position of a block inside of a chunk
+pub fn world_pos(chunk: ChunkPos, local: [usize; 3]) -> [i32; 3] {
+    let size = CHUNK_SIZE as i32;
+    [
+        chunk[0] * size + local[0] as i32,
+        chunk[1] * size + local[1] as i32,
+        chunk[2] * size + local[2] as i32,
+    ]
+}
+
+/// Sparse grid of chunks. Chunks that were never written to read as air.
+#[derive(Default)]
+pub struct World {
+    chunks: HashMap<ChunkPos, Chunk>,
+    dirty: HashSet<ChunkPos>,
+    /// Chunks whose blocks changed, unlike `dirty` not set by neighbours
+    modified: HashSet<ChunkPos>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+            modified: HashSet::new(),
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> BlockId {
+        let (chunk, [lx, ly, lz]) = split_pos(x, y, z);
+        match self.chunks.get(&chunk) {
+            Some(chunk) => chunk.get(lx, ly, lz),
+            None => AIR,
+        }
+    }
+
+    /// Sets a block and returns the one it replaced. The chunk and any neighbour
+    /// sharing a face with the block are marked dirty.
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block: BlockId) -> BlockId {
+        let (chunk_pos, local) = split_pos(x, y, z);
+        let old = match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => chunk.set(local[0], local[1], local[2], block),
+            None if block == AIR => AIR,
+            None => {
+                let mut chunk = Chunk::empty();
+                chunk.set(local[0], local[1], local[2], block);
+                self.chunks.insert(chunk_pos, chunk);
+                AIR
+            }
+        };
+
+        if old != block {
+            self.modified.insert(chunk_pos);
+            self.mark_dirty(chunk_pos);
+            for axis in 0..3 {
+                let mut neighbour = chunk_pos;
+                if local[axis] == 0 {
+                    neighbour[axis] -= 1;
+                } else if local[axis] == CHUNK_SIZE - 1 {
+                    neighbour[axis] += 1;
+                } else {
+                    continue;
+                }
+                self.mark_dirty(neighbour);
+            }
+        }
+        old
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    /// Adds or replaces a whole chunk, it and its loaded neighbours become dirty
+    pub fn insert_chunk(&mut self, pos: ChunkPos, chunk: Chunk) -> Option<Chunk> {
+        let old = self.chunks.insert(pos, chunk);
+        self.mark_dirty(pos);
+        self.mark_neighbours_dirty(pos);
+        old
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.dirty.remove(&pos);
+        self.modified.remove(&pos);
+        let removed = self.chunks.remove(&pos);
+        if removed.is_some() {
+            self.mark_neighbours_dirty(pos);
+        }
+        removed
+    }
+
+    pub fn contains_chunk(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn mark_dirty(&mut self, pos: ChunkPos) {
+        if self.chunks.contains_key(&pos) {
+            self.dirty.insert(pos);
+        }
+    }
+
+    fn mark_neighbours_dirty(&mut self, pos: ChunkPos) {
+        for axis in 0..3 {
+            for &offset in [-1, 1].iter() {
+                let mut neighbour = pos;
+                neighbour[axis] += offset;
+                self.mark_dirty(neighbour);
+            }
+        }
+    }
+
+    /// Chunks that changed since the last call, e.g. to remesh them
+    pub fn take_dirty(&mut self) -> Vec<ChunkPos> {
+        self.dirty.drain().collect()
+    }
+
+    /// Whether blocks of the chunk were changed since it was inserted or the
+    /// last `take_modified`
+    pub fn is_modified(&self, pos: ChunkPos) -> bool {
+        self.modified.contains(&pos)
+    }
+
+    /// Chunks with changed blocks, including ones `compact` dropped
+    pub fn modified(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.modified.iter().copied()
+    }
+
+    /// Chunks with changed blocks since the last call, e.g. to save them.
+    /// Inserting a chunk doesn't count as a change.
+    pub fn take_modified(&mut self) -> Vec<ChunkPos> {
+        self.modified.drain().collect()
+    }
+
+    /// Drops unused palette entries of all chunks and unloads chunks of pure air.
+    /// Dirty chunks stay dirty so their old meshes get cleared.
+    pub fn compact(&mut self) {
+        for chunk in self.chunks.values_mut() {
+            chunk.compact();
+        }
+        self.chunks.retain(|_, chunk| !chunk.is_empty());
+    }
+
+    /// Approximate heap usage of all chunks in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.chunks.values().map(Chunk::memory_usage).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = CHUNK_SIZE as i32;
+
+    #[test]
+    fn split_pos_rounds_towards_negative_infinity() {
+        assert_eq!(split_pos(0, 0, 0), ([0, 0, 0], [0, 0, 0]));
+        assert_eq!(split_pos(-1, -1, -1), ([-1, -1, -1], [31, 31, 31]));
+        assert_eq!(split_pos(-SIZE, SIZE, -SIZE - 1), ([-1, 1, -2], [0, 0, 31]));
+        assert_eq!(split_pos(SIZE - 1, -SIZE + 1, 70), ([0, -1, 2], [31, 1, 6]));
+    }
+
+    #[test]
+    fn world_pos_inverts_split_pos() {
+        for &x in [-65, -64, -33, -32, -31, -1, 0, 1, 31, 32, 95].iter() {
+            let position = [x, -x - 7, x * 3];
+            let (chunk, local) = split_pos(position[0], position[1], position[2]);
+            assert!(local.iter().all(|&l| l < CHUNK_SIZE));
+            assert_eq!(world_pos(chunk, local), position);
+        }
+    }
+
+    #[test]
+    fn get_and_set_across_chunks() {
+        let mut world = World::new();
+        assert_eq!(world.get(-1, -1, -1), AIR);
+        assert_eq!(world.set(-1, -1, -1, 4), AIR);
+        assert_eq!(world.set(0, 0, 0, 5), AIR);
+        assert_eq!(world.set(-1, -1, -1, 6), 4);
+
+        assert_eq!(world.get(-1, -1, -1), 6);
+        assert_eq!(world.get(0, 0, 0), 5);
+        assert_eq!(world.get(-1, 0, 0), AIR);
+        assert_eq!(world.chunk_count(), 2);
+        assert!(world.contains_chunk([-1, -1, -1]));
+
+        // air into an unloaded chunk doesn't load it
+        assert_eq!(world.set(100, 100, 100, AIR), AIR);
+        assert_eq!(world.chunk_count(), 2);
+    }
+
+    #[test]
+    fn border_blocks_dirty_loaded_neighbours() {
+        let mut world = World::new();
+        world.insert_chunk([0, 0, 0], Chunk::empty());
+        world.insert_chunk([-1, 0, 0], Chunk::empty());
+        world.insert_chunk([0, 1, 0], Chunk::empty());
+        world.take_dirty();
+
+        // inside the chunk, only it changes
+        world.set(5, 5, 5, 1);
+        assert_eq!(world.take_dirty(), vec![[0, 0, 0]]);
+
+        // on the -x face, the neighbour shows the face now
+        world.set(0, 5, 5, 1);
+        let mut dirty = world.take_dirty();
+        dirty.sort();
+        assert_eq!(dirty, vec![[-1, 0, 0], [0, 0, 0]]);
+
+        // the corner touches -x, -y and -z but only -x is loaded; +y of the
+        // last row touches [0, 1, 0]
+        world.set(0, 0, 0, 1);
+        world.set(3, SIZE - 1, 3, 1);
+        let mut dirty = world.take_dirty();
+        dirty.sort();
+        assert_eq!(dirty, vec![[-1, 0, 0], [0, 0, 0], [0, 1, 0]]);
+
+        // setting the same block again changes nothing
+        world.set(5, 5, 5, 1);
+        assert!(world.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn inserting_and_removing_dirties_neighbours() {
+        let mut world = World::new();
+        world.insert_chunk([0, 0, 0], Chunk::empty());
+        world.insert_chunk([2, 0, 0], Chunk::empty());
+        world.take_dirty();
+
+        world.insert_chunk([1, 0, 0], Chunk::filled(1));
+        let mut dirty = world.take_dirty();
+        dirty.sort();
+        assert_eq!(dirty, vec![[0, 0, 0], [1, 0, 0], [2, 0, 0]]);
+
+        world.remove_chunk([1, 0, 0]);
+        let mut dirty = world.take_dirty();
+        dirty.sort();
+        assert_eq!(dirty, vec![[0, 0, 0], [2, 0, 0]]);
+    }
+
+    #[test]
+    fn modified_only_counts_block_changes() {
+        let mut world = World::new();
+        world.insert_chunk([0, 0, 0], Chunk::empty());
+        world.insert_chunk([1, 0, 0], Chunk::empty());
+        assert!(world.take_modified().is_empty());
+
+        // the neighbour gets dirty but its blocks didn't change
+        world.set(SIZE - 1, 0, 0, 3);
+        assert!(world.take_dirty().contains(&[1, 0, 0]));
+        assert!(!world.is_modified([1, 0, 0]));
+        assert_eq!(world.take_modified(), vec![[0, 0, 0]]);
+        assert!(world.take_modified().is_empty());
+    }
+
+    #[test]
+    fn compact_unloads_air() {
+        let mut world = World::new();
+        world.set(1, 1, 1, 2);
+        world.set(40, 1, 1, 2);
+        world.set(40, 1, 1, AIR);
+        world.compact();
+        assert_eq!(world.chunk_count(), 1);
+        assert_eq!(world.get(1, 1, 1), 2);
+    }
+}